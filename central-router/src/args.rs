@@ -3,7 +3,26 @@ use clap::Clap;
 #[derive(Clap, Clone)]
 #[clap(version = "1.0", author = "Elliot Levin <elliotlevin@hotmail.com>")]
 pub struct Args {
+    /// The interfaces to route between, separated by commas, eg "eth0,eth1" or "eth0.10,eth0.20"
     pub interface: String,
 
-    pub port: u16
-}
\ No newline at end of file
+    pub port: u16,
+
+    /// A scenario file to load on start up
+    #[clap(long)]
+    pub scenario: Option<String>,
+
+    /// A file to append a recording of the session to
+    #[clap(long)]
+    pub record: Option<String>,
+}
+
+impl Args {
+    pub fn interfaces(&self) -> Vec<String> {
+        self.interface
+            .split(',')
+            .map(|i| i.trim().to_string())
+            .filter(|i| !i.is_empty())
+            .collect()
+    }
+}
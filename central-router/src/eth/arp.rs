@@ -1,48 +1,45 @@
-use std::{net::{IpAddr, Ipv4Addr}, sync::mpsc::Sender, thread, time::{Duration, SystemTime}};
+use std::{net::Ipv4Addr, sync::mpsc::Sender, thread, time::{Duration, SystemTime}};
 
 use pnet::packet::arp::{ArpOperations, ArpPacket, MutableArpPacket, ArpHardwareTypes};
 use pnet::{
     packet::Packet,
-    datalink::NetworkInterface,
     packet::ethernet::{EtherTypes, EthernetPacket, MutableEthernetPacket},
     util::MacAddr,
 };
 
-use crate::state::SharedState;
+use crate::state::{Interface, SharedState};
 
 use super::event::Event;
 
-pub fn send_requests(state: SharedState, interface: NetworkInterface, mut tx: Sender<Event>) {
+pub fn send_requests(state: SharedState, mut tx: Sender<Event>) {
     loop {
-        // Get nodes without mac addr
-        let nodes = state
-            .get(|s| s.nodes.clone())
-            .into_iter()
-            .filter(|i| i.mac.is_none())
-            .filter(|i| SystemTime::now().duration_since(i.created).map_or(false, |d| d.as_secs() < 10))
-            .collect::<Vec<_>>();
-
-        for node in nodes {
-            log::info!("sending arp request for ip {}", node.ip.to_string());
-            send_arp_request(&interface, node.ip, &mut tx);
+        // Get nodes without mac addr along with the interface on their subnet
+        let nodes = state.get(|s| {
+            s.nodes
+                .iter()
+                .filter(|i| i.mac.is_none())
+                .filter(|i| SystemTime::now().duration_since(i.created).map_or(false, |d| d.as_secs() < 10))
+                .map(|i| (i.ip, s.interface_for(i.ip).cloned()))
+                .collect::<Vec<_>>()
+        });
+
+        for (ip, interface) in nodes {
+            match interface {
+                Some(interface) => {
+                    log::info!("sending arp request for ip {} on {}", ip, interface.name);
+                    send_arp_request(&interface, ip, &mut tx);
+                }
+                None => log::debug!("ip {} is not on the subnet of any interface", ip),
+            }
         }
 
         thread::sleep(Duration::from_millis(1000));
     }
 }
 
-fn send_arp_request(interface: &NetworkInterface, ip: Ipv4Addr, tx: &mut Sender<Event>) -> () {
+fn send_arp_request(interface: &Interface, ip: Ipv4Addr, tx: &mut Sender<Event>) {
     let source_mac = interface.mac.expect("failed to get mac from interface");
-    let source_ip = interface
-        .ips
-        .iter()
-        .find(|i| i.is_ipv4())
-        .map(|i| i.ip())
-        .map(|i| match i {
-            IpAddr::V4(i) => i,
-            _ => unreachable!(),
-        })
-        .expect("failed to get ipv4 address of interface");
+    let source_ip = interface.ip;
 
     let mut buff = [0u8; 42]; // 14 (eth frame header) + 28 (arp request length)
     let (eth_buff, arp_buff) = buff.split_at_mut(14);
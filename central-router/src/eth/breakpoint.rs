@@ -0,0 +1,63 @@
+use pnet::packet::{ethernet::EthernetPacket, ipv4::Ipv4Packet, Packet};
+
+use crate::state::{Decision, PausedPacket, SharedState};
+
+use super::inspector;
+
+/// Holds the packet if it matches one of the breakpoints, returning true if it was paused
+pub fn pause_if_matches(state: &SharedState, eth: &EthernetPacket, ip: &Ipv4Packet) -> bool {
+    let mut paused = false;
+
+    state.update(|s| {
+        if s.breakpoints.is_empty() {
+            return;
+        }
+
+        let mut record = inspector::decode(s, eth, ip, Decision::Paused { breakpoint: 0 }, &[]);
+
+        let breakpoint = match s.breakpoints.iter().find(|i| i.filter.matches(&record)) {
+            Some(breakpoint) => breakpoint.id,
+            None => return,
+        };
+
+        if !s.can_pause() {
+            log::warn!(
+                "too many packets are paused, ignoring breakpoint #{}",
+                breakpoint
+            );
+            return;
+        }
+
+        log::info!(
+            "pausing packet from {} to {} at breakpoint #{}",
+            ip.get_source(),
+            ip.get_destination(),
+            breakpoint
+        );
+
+        record.decision = Decision::Paused { breakpoint };
+        record.id = s.record_packet(record.clone());
+
+        s.paused.push(PausedPacket {
+            breakpoint,
+            frame: eth.packet().to_vec(),
+            record,
+        });
+        paused = true;
+    });
+
+    paused
+}
+
+/// Removes the paused packet with the id, returning its frame
+pub fn take_paused(state: &SharedState, id: u64) -> Option<Vec<u8>> {
+    let mut frame = None;
+
+    state.update(|s| {
+        if let Some(i) = s.paused.iter().position(|i| i.record.id == id) {
+            frame = Some(s.paused.remove(i).frame);
+        }
+    });
+
+    frame
+}
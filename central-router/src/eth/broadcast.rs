@@ -0,0 +1,349 @@
+use std::{
+    collections::VecDeque,
+    net::Ipv4Addr,
+    sync::mpsc::Sender,
+    time::{Duration, Instant},
+};
+
+use pnet::packet::ethernet::{EthernetPacket, MutableEthernetPacket};
+use pnet::packet::ip::IpNextHeaderProtocols;
+use pnet::packet::ipv4::{Ipv4Packet, MutableIpv4Packet};
+use pnet::packet::{MutablePacket, Packet};
+
+use crate::state::{BroadcastKey, LinkKey, Mode, Node, SharedState, State};
+
+use super::event::Event;
+use super::ip_forwarder::send_on_link;
+use super::switch;
+use super::topology::neighbours;
+
+/// Copies of a propagated packet arriving from another node within this long of it are dropped
+const DUPLICATE_WINDOW: Duration = Duration::from_secs(5);
+
+const IGMP_V1_REPORT: u8 = 0x12;
+const IGMP_V2_REPORT: u8 = 0x16;
+const IGMP_LEAVE: u8 = 0x17;
+const IGMP_V3_REPORT: u8 = 0x22;
+
+/// The kinds of group records in an igmpv3 report
+const MODE_IS_INCLUDE: u8 = 1;
+const MODE_IS_EXCLUDE: u8 = 2;
+const CHANGE_TO_INCLUDE: u8 = 3;
+const CHANGE_TO_EXCLUDE: u8 = 4;
+const ALLOW_NEW_SOURCES: u8 = 5;
+
+/// A node a packet is propagated to and the node it is passed on from
+struct Receiver {
+    node: Node,
+    from: Ipv4Addr,
+    /// The number of links between the source and the node
+    hops: u8,
+}
+
+/// Returns true if the packet is for every node, or every node in one of the router's subnets
+pub fn is_broadcast(state: &State, dest: Ipv4Addr) -> bool {
+    dest.is_broadcast() || state.interfaces.iter().any(|i| i.broadcast() == dest)
+}
+
+/// Returns true if the packet is propagated to many nodes rather than forwarded to one
+pub fn is_propagated(state: &State, dest: Ipv4Addr) -> bool {
+    dest.is_multicast() || is_broadcast(state, dest)
+}
+
+/// Groups in 224.0.0.0/24 are only used on the local network and every node listens to them
+fn is_link_local(group: Ipv4Addr) -> bool {
+    group.octets()[..3] == [224, 0, 0]
+}
+
+/// Propagates a broadcast or multicast packet from its source through the source's chain so
+/// each node receives it in turn, returning the names of the nodes it was sent to along with
+/// the labels for the packet inspector. Copies of the packet which other nodes return to the
+/// router are dropped, while packets repeated by their sender are propagated again.
+pub fn propagate(
+    tx: &mut Sender<Event>,
+    state: &SharedState,
+    eth: &EthernetPacket,
+    ip: &Ipv4Packet,
+) -> Result<(Vec<String>, Vec<&'static str>), &'static str> {
+    let now = Instant::now();
+    let dest = ip.get_destination();
+
+    let (receivers, mut tags) = state.update(|s| {
+        let source = s
+            .nodes
+            .iter()
+            .find(|i| i.mac == Some(eth.get_source()))
+            .cloned()
+            .ok_or("source is not a registered node")?;
+
+        if !s.group(&source.group).on {
+            return Err("the group is off");
+        }
+
+        // The node the packet came from, which differs from the node it arrived from
+        // when another node has returned a copy of it
+        let origin = s
+            .nodes
+            .iter()
+            .find(|i| i.ip == ip.get_source())
+            .cloned()
+            .unwrap_or_else(|| source.clone());
+
+        if ip.get_next_level_protocol() == IpNextHeaderProtocols::Igmp {
+            snoop(s, &origin, ip.payload(), now);
+        }
+
+        let key = BroadcastKey {
+            src: ip.get_source(),
+            dst: dest,
+            protocol: ip.get_next_level_protocol().0,
+            id: ip.get_identification(),
+        };
+
+        s.broadcasts_seen
+            .retain(|_, (i, _)| now.duration_since(*i) < DUPLICATE_WINDOW);
+
+        // Many packets, such as igmp reports, are sent with an id of 0 so a packet is only a
+        // copy if it arrives from a node other than the one it was first seen from
+        let ingress = s.broadcasts_seen.get(&key).map_or(origin.ip, |(_, i)| *i);
+
+        if source.ip != origin.ip || source.ip != ingress {
+            log::debug!("dropping copy of packet {:?} from {}", key, source.name);
+            return Err("copy of a packet which was already propagated");
+        }
+
+        s.broadcasts_seen.insert(key, (now, source.ip));
+
+        let (mut receivers, mut tags) = match s.mode {
+            Mode::Routing => (hop_by_hop(s, &source, ip.get_ttl()), vec![]),
+            Mode::Switching => {
+                let (nodes, tags) = switch::flood_frame(s, &source, eth.get_destination())?;
+                let receivers = nodes
+                    .into_iter()
+                    .map(|node| Receiver {
+                        node,
+                        from: source.ip,
+                        hops: 1,
+                    })
+                    .collect::<Vec<_>>();
+                (receivers, tags)
+            }
+        };
+
+        if dest.is_multicast() {
+            tags.insert(0, "multicast");
+
+            if s.igmp_snooping && !is_link_local(dest) {
+                let members = s.multicast_members(dest);
+                receivers.retain(|i| members.contains(&i.node.ip));
+                tags.push("snooped");
+            }
+        } else {
+            tags.insert(0, "broadcast");
+        }
+
+        Ok((receivers, tags))
+    })?;
+
+    if receivers.is_empty() {
+        return Err(if dest.is_multicast() {
+            "no members of the multicast group were reached"
+        } else {
+            "no other nodes were reached"
+        });
+    }
+
+    let mut sent = vec![];
+
+    for receiver in receivers {
+        match send_copy(tx, state, eth, &receiver) {
+            Ok(copy_tags) => {
+                sent.push(receiver.node.name);
+                for tag in copy_tags {
+                    if !tags.contains(&tag) {
+                        tags.push(tag);
+                    }
+                }
+            }
+            Err(reason) => {
+                log::debug!(
+                    "could not propagate packet to {}: {}",
+                    receiver.node.name,
+                    reason
+                )
+            }
+        }
+    }
+
+    if sent.is_empty() {
+        return Err("failed to send the packet to any node");
+    }
+
+    log::debug!("propagated packet to {} to {}", dest, sent.join(", "));
+    Ok((sent, tags))
+}
+
+/// Walks outwards from the source along the chain and its extra links, returning each other node
+/// once, nearest first, along with the node before it. Each node passing the packet on counts as
+/// a hop so the packet goes no further than its ttl allows.
+fn hop_by_hop(state: &State, source: &Node, ttl: u8) -> Vec<Receiver> {
+    let chain = state.chain(source);
+    let start = chain.iter().position(|i| i == source).unwrap();
+
+    let mut visited = vec![false; chain.len()];
+    let mut pending = VecDeque::from(vec![(start, 0u8)]);
+    let mut receivers = vec![];
+
+    visited[start] = true;
+
+    while let Some((index, hops)) = pending.pop_front() {
+        if hops >= ttl {
+            continue;
+        }
+
+        for neighbour in neighbours(&chain, &state.links, index) {
+            if visited[neighbour] {
+                continue;
+            }
+
+            visited[neighbour] = true;
+            receivers.push(Receiver {
+                node: chain[neighbour].clone(),
+                from: chain[index].ip,
+                hops: hops + 1,
+            });
+            pending.push_back((neighbour, hops + 1));
+        }
+    }
+
+    receivers
+}
+
+/// Sends a copy of the packet along the link to the receiver, with the ttl it would have
+/// after being passed on by the nodes in between
+fn send_copy(
+    tx: &mut Sender<Event>,
+    state: &SharedState,
+    eth: &EthernetPacket,
+    receiver: &Receiver,
+) -> Result<Vec<&'static str>, &'static str> {
+    let node = &receiver.node;
+    let dest_mac = node.mac.ok_or("next hop mac is not known")?;
+    let interface_mac = state
+        .get(|s| s.interface_for(node.ip).and_then(|i| i.mac))
+        .ok_or("interface mac is not known")?;
+
+    let mut copy = MutableEthernetPacket::owned(eth.packet().to_vec()).unwrap();
+    copy.set_source(interface_mac);
+    copy.set_destination(dest_mac);
+
+    let mut ip = MutableIpv4Packet::new(copy.payload_mut()).unwrap();
+    let ttl = ip.get_ttl() - (receiver.hops - 1);
+    set_ttl(&mut ip, ttl);
+
+    let link = LinkKey {
+        from: receiver.from,
+        to: node.ip,
+    };
+
+    send_on_link(state, link, copy.consume_to_immutable(), |eth| {
+        match tx.send(Event::SendPacket(eth)) {
+            Ok(()) => true,
+            Err(err) => {
+                log::warn!("error while propagating packet: {}", err);
+                false
+            }
+        }
+    })
+}
+
+/// Sets the ttl, adjusting the header checksum for the change (RFC 1624) rather than
+/// recalculating it so a header corrupted on an earlier link stays corrupt
+fn set_ttl(ip: &mut MutableIpv4Packet, ttl: u8) {
+    let protocol = ip.get_next_level_protocol().0;
+    let old = u16::from_be_bytes([ip.get_ttl(), protocol]);
+    let new = u16::from_be_bytes([ttl, protocol]);
+
+    let mut sum = (!ip.get_checksum()) as u32 + (!old) as u32 + new as u32;
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+
+    ip.set_ttl(ttl);
+    ip.set_checksum(!(sum as u16));
+}
+
+/// Learns which multicast groups the node has joined or left from its igmp membership reports
+fn snoop(state: &mut State, node: &Node, igmp: &[u8], now: Instant) {
+    let group = |at: usize| {
+        igmp.get(at..at + 4)
+            .map(|i| Ipv4Addr::new(i[0], i[1], i[2], i[3]))
+            .filter(|i| i.is_multicast())
+    };
+
+    match igmp.first().copied() {
+        Some(IGMP_V1_REPORT) | Some(IGMP_V2_REPORT) => {
+            if let Some(group) = group(4) {
+                join(state, node, group, now);
+            }
+        }
+        Some(IGMP_LEAVE) => {
+            if let Some(group) = group(4) {
+                leave(state, node, group);
+            }
+        }
+        Some(IGMP_V3_REPORT) => {
+            let records = igmp
+                .get(6..8)
+                .map_or(0, |i| u16::from_be_bytes([i[0], i[1]]));
+            let mut at = 8;
+
+            for _ in 0..records {
+                let header = match igmp.get(at..at + 4) {
+                    Some(header) => header,
+                    None => break,
+                };
+                let (kind, aux_words) = (header[0], header[1] as usize);
+                let sources = u16::from_be_bytes([header[2], header[3]]) as usize;
+
+                // Excluding no sources asks for every source, while including none leaves the group
+                match (kind, group(at + 4)) {
+                    (MODE_IS_EXCLUDE, Some(group)) | (CHANGE_TO_EXCLUDE, Some(group)) => {
+                        join(state, node, group, now)
+                    }
+                    (MODE_IS_INCLUDE, Some(group)) | (ALLOW_NEW_SOURCES, Some(group))
+                        if sources > 0 =>
+                    {
+                        join(state, node, group, now)
+                    }
+                    (CHANGE_TO_INCLUDE, Some(group)) if sources == 0 => leave(state, node, group),
+                    _ => {}
+                }
+
+                at += 8 + sources * 4 + aux_words * 4;
+            }
+        }
+        _ => {}
+    }
+}
+
+fn join(state: &mut State, node: &Node, group: Ipv4Addr, now: Instant) {
+    log::debug!("{} reported membership of {}", node.name, group);
+    state
+        .multicast_groups
+        .entry(group)
+        .or_default()
+        .insert(node.ip, now);
+}
+
+fn leave(state: &mut State, node: &Node, group: Ipv4Addr) {
+    log::debug!("{} left {}", node.name, group);
+
+    if let Some(members) = state.multicast_groups.get_mut(&group) {
+        members.remove(&node.ip);
+
+        if members.is_empty() {
+            state.multicast_groups.remove(&group);
+        }
+    }
+}
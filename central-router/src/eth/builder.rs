@@ -0,0 +1,111 @@
+use std::net::Ipv4Addr;
+
+use pnet::packet::ethernet::{EtherTypes, EthernetPacket, MutableEthernetPacket};
+use pnet::packet::icmp::{self, IcmpCode, IcmpPacket, IcmpType, MutableIcmpPacket};
+use pnet::packet::ip::IpNextHeaderProtocol;
+use pnet::packet::ipv4::{self, Ipv4Flags, MutableIpv4Packet};
+use pnet::packet::tcp::{self, MutableTcpPacket};
+use pnet::packet::udp::{self, MutableUdpPacket};
+use pnet::packet::{MutablePacket, Packet};
+use pnet::util::MacAddr;
+
+const ETHERNET_HEADER_LEN: usize = 14;
+const IPV4_HEADER_LEN: usize = 20;
+const ICMP_HEADER_LEN: usize = 8;
+const UDP_HEADER_LEN: usize = 8;
+const TCP_HEADER_LEN: usize = 20;
+
+/// The fields of an ipv4 header which can be chosen when crafting a packet
+pub struct Ipv4Header {
+    pub src: Ipv4Addr,
+    pub dst: Ipv4Addr,
+    pub ttl: u8,
+    pub tos: u8,
+    pub identification: u16,
+    pub dont_fragment: bool,
+    pub protocol: IpNextHeaderProtocol,
+}
+
+/// The fields of a tcp header which can be chosen when crafting a packet
+pub struct TcpHeader {
+    pub src_port: u16,
+    pub dst_port: u16,
+    pub sequence: u32,
+    pub acknowledgement: u32,
+    pub flags: u16,
+    pub window: u16,
+}
+
+/// Wraps the ipv4 packet in an ethernet frame
+pub fn build_ethernet(src: MacAddr, dst: MacAddr, ip: &[u8]) -> EthernetPacket<'static> {
+    let mut eth = MutableEthernetPacket::owned(vec![0u8; ETHERNET_HEADER_LEN + ip.len()]).unwrap();
+    eth.set_source(src);
+    eth.set_destination(dst);
+    eth.set_ethertype(EtherTypes::Ipv4);
+    eth.set_payload(ip);
+    eth.consume_to_immutable()
+}
+
+/// Builds an ipv4 packet without options around the supplied payload
+pub fn build_ipv4(header: &Ipv4Header, payload: &[u8]) -> Vec<u8> {
+    let mut ip = MutableIpv4Packet::owned(vec![0u8; IPV4_HEADER_LEN + payload.len()]).unwrap();
+    ip.set_version(4);
+    ip.set_header_length((IPV4_HEADER_LEN / 4) as u8);
+    ip.set_dscp(header.tos >> 2);
+    ip.set_ecn(header.tos & 0b11);
+    ip.set_total_length((IPV4_HEADER_LEN + payload.len()) as u16);
+    ip.set_identification(header.identification);
+    if header.dont_fragment {
+        ip.set_flags(Ipv4Flags::DontFragment);
+    }
+    ip.set_ttl(header.ttl);
+    ip.set_next_level_protocol(header.protocol);
+    ip.set_source(header.src);
+    ip.set_destination(header.dst);
+    ip.set_payload(payload);
+    ip.set_checksum(ipv4::checksum(&ip.to_immutable()));
+    ip.packet().to_vec()
+}
+
+/// Builds an icmp message, `rest` is the second word of the header such as the echo id and sequence
+pub fn build_icmp(icmp_type: u8, code: u8, rest: [u8; 4], payload: &[u8]) -> Vec<u8> {
+    let mut icmp = MutableIcmpPacket::owned(vec![0u8; ICMP_HEADER_LEN + payload.len()]).unwrap();
+    icmp.set_icmp_type(IcmpType(icmp_type));
+    icmp.set_icmp_code(IcmpCode(code));
+    icmp.packet_mut()[4..ICMP_HEADER_LEN].copy_from_slice(&rest);
+    icmp.packet_mut()[ICMP_HEADER_LEN..].copy_from_slice(payload);
+    icmp.set_checksum(icmp::checksum(&IcmpPacket::new(icmp.packet()).unwrap()));
+    icmp.packet().to_vec()
+}
+
+/// Builds a udp datagram, the addresses are needed for the checksum
+pub fn build_udp(
+    src: Ipv4Addr,
+    dst: Ipv4Addr,
+    src_port: u16,
+    dst_port: u16,
+    payload: &[u8],
+) -> Vec<u8> {
+    let mut udp = MutableUdpPacket::owned(vec![0u8; UDP_HEADER_LEN + payload.len()]).unwrap();
+    udp.set_source(src_port);
+    udp.set_destination(dst_port);
+    udp.set_length((UDP_HEADER_LEN + payload.len()) as u16);
+    udp.set_payload(payload);
+    udp.set_checksum(udp::ipv4_checksum(&udp.to_immutable(), &src, &dst));
+    udp.packet().to_vec()
+}
+
+/// Builds a tcp segment without options, the addresses are needed for the checksum
+pub fn build_tcp(src: Ipv4Addr, dst: Ipv4Addr, header: &TcpHeader, payload: &[u8]) -> Vec<u8> {
+    let mut tcp = MutableTcpPacket::owned(vec![0u8; TCP_HEADER_LEN + payload.len()]).unwrap();
+    tcp.set_source(header.src_port);
+    tcp.set_destination(header.dst_port);
+    tcp.set_sequence(header.sequence);
+    tcp.set_acknowledgement(header.acknowledgement);
+    tcp.set_data_offset((TCP_HEADER_LEN / 4) as u8);
+    tcp.set_flags(header.flags);
+    tcp.set_window(header.window);
+    tcp.set_payload(payload);
+    tcp.set_checksum(tcp::ipv4_checksum(&tcp.to_immutable(), &src, &dst));
+    tcp.packet().to_vec()
+}
@@ -0,0 +1,124 @@
+use std::ops::Range;
+
+use pnet::packet::{
+    ethernet::{EthernetPacket, MutableEthernetPacket},
+    icmp::{self, MutableIcmpPacket},
+    ip::IpNextHeaderProtocols,
+    ipv4::{self, Ipv4Flags, Ipv4Packet, MutableIpv4Packet},
+    tcp::{self, MutableTcpPacket},
+    udp::{self, MutableUdpPacket},
+    MutablePacket, Packet,
+};
+use rand::Rng;
+
+use crate::state::{CorruptionTarget, LinkKey, SharedState};
+
+/// Flips random bits of the packet according to the link's corruption settings,
+/// returning the packet to send and whether it was corrupted
+pub fn corrupt(
+    state: &SharedState,
+    link: LinkKey,
+    eth: EthernetPacket<'static>,
+) -> (EthernetPacket<'static>, bool) {
+    let settings = match state.get(|s| s.corruption.get(&link).map(|i| i.settings)) {
+        Some(settings) => settings,
+        None => return (eth, false),
+    };
+
+    let mut rng = rand::thread_rng();
+
+    if !rng.gen_bool(settings.rate) {
+        return (eth, false);
+    }
+
+    let mut new_eth = MutableEthernetPacket::owned(eth.packet().to_vec()).unwrap();
+    let packet = new_eth.payload_mut();
+
+    let range = match target_range(packet, settings.target) {
+        Some(range) => range,
+        None => return (eth, false),
+    };
+
+    for _ in 0..settings.bits {
+        let byte = rng.gen_range(range.clone());
+        packet[byte] ^= 1 << rng.gen_range(0..8);
+    }
+
+    if settings.fix_checksums {
+        fix_checksums(packet);
+    }
+
+    log::debug!(
+        "flipped {} bits of packet from {} to {}",
+        settings.bits,
+        link.from,
+        link.to
+    );
+
+    state.update(|s| {
+        if let Some(corruption) = s.corruption.get_mut(&link) {
+            corruption.corrupted += 1;
+        }
+    });
+
+    (new_eth.consume_to_immutable(), true)
+}
+
+/// Returns the bytes of the ipv4 packet which may be corrupted, none if there are none
+fn target_range(packet: &[u8], target: CorruptionTarget) -> Option<Range<usize>> {
+    let ip = Ipv4Packet::new(packet)?;
+    let end = (ip.get_total_length() as usize).min(packet.len());
+    let header_end = (ip.get_header_length() as usize * 4).min(end);
+
+    let range = match target {
+        CorruptionTarget::Header => 0..header_end,
+        CorruptionTarget::Payload => header_end..end,
+        CorruptionTarget::Any => 0..end,
+    };
+
+    if range.is_empty() {
+        return None;
+    }
+
+    Some(range)
+}
+
+/// Recalculates the ipv4 and transport checksums so the corruption is not detected
+fn fix_checksums(packet: &mut [u8]) {
+    let ip = match Ipv4Packet::new(packet) {
+        Some(ip) => ip,
+        None => return,
+    };
+    let offset = ip.get_header_length() as usize * 4;
+    let end = (ip.get_total_length() as usize).min(packet.len());
+    let protocol = ip.get_next_level_protocol();
+    let (src, dst) = (ip.get_source(), ip.get_destination());
+    // The transport checksum covers the whole packet so cannot be recalculated from a fragment
+    let fragment = ip.get_fragment_offset() > 0 || ip.get_flags() & Ipv4Flags::MoreFragments != 0;
+
+    if offset < end && !fragment {
+        let transport = &mut packet[offset..end];
+
+        match protocol {
+            IpNextHeaderProtocols::Tcp => {
+                if let Some(mut tcp) = MutableTcpPacket::new(transport) {
+                    tcp.set_checksum(tcp::ipv4_checksum(&tcp.to_immutable(), &src, &dst));
+                }
+            }
+            IpNextHeaderProtocols::Udp => {
+                if let Some(mut udp) = MutableUdpPacket::new(transport) {
+                    udp.set_checksum(udp::ipv4_checksum(&udp.to_immutable(), &src, &dst));
+                }
+            }
+            IpNextHeaderProtocols::Icmp => {
+                if let Some(mut icmp) = MutableIcmpPacket::new(transport) {
+                    icmp.set_checksum(icmp::checksum(&icmp.to_immutable()));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut ip = MutableIpv4Packet::new(packet).unwrap();
+    ip.set_checksum(ipv4::checksum(&ip.to_immutable()));
+}
@@ -4,5 +4,7 @@ use pnet::packet::ethernet::EthernetPacket;
 pub enum Event {
     PacketReceived(EthernetPacket<'static>),
     SendPacket(EthernetPacket<'static>),
+    /// Forwards or drops the packet with the id which is held at a breakpoint
+    ReleasePacket { id: u64, forward: bool },
     Terminate(Result<()>)
 }
\ No newline at end of file
@@ -0,0 +1,153 @@
+use pnet::packet::{
+    ethernet::EthernetPacket,
+    icmp::IcmpTypes,
+    ip::IpNextHeaderProtocols,
+    ipv4::{self, Ipv4Flags, Ipv4Packet, MutableIpv4Packet},
+    Packet,
+};
+
+use crate::state::{LinkKey, SharedState};
+
+use super::builder::{build_ethernet, build_icmp, build_ipv4, Ipv4Header};
+
+/// The number of bytes of the original packet's payload quoted in icmp errors
+const ICMP_QUOTE_LEN: usize = 8;
+
+/// Splits the frame into fragments which fit within the link's mtu.
+/// Packets which may not be fragmented are dropped and the sender is told
+/// the link's mtu with an icmp fragmentation needed message.
+pub fn apply_mtu<F>(
+    state: &SharedState,
+    link: LinkKey,
+    eth: EthernetPacket<'static>,
+    send: F,
+) -> Result<Vec<EthernetPacket<'static>>, &'static str>
+where
+    F: FnOnce(EthernetPacket<'static>) -> bool,
+{
+    let mtu = match state.get(|s| s.mtus.get(&link).copied()) {
+        Some(mtu) => mtu,
+        None => return Ok(vec![eth]),
+    };
+
+    let ip = match Ipv4Packet::new(eth.payload()) {
+        Some(ip) => ip,
+        None => return Ok(vec![eth]),
+    };
+
+    if ip.get_total_length() <= mtu {
+        return Ok(vec![eth]);
+    }
+
+    if ip.get_flags() & Ipv4Flags::DontFragment != 0 {
+        log::debug!(
+            "packet from {} to {} is larger than the mtu {} but may not be fragmented",
+            ip.get_source(),
+            ip.get_destination(),
+            mtu
+        );
+        send_fragmentation_needed(state, &ip, mtu, send);
+        return Err("packet is larger than the link mtu");
+    }
+
+    let fragments = fragment(&ip, mtu as usize)
+        .into_iter()
+        .map(|i| build_ethernet(eth.get_source(), eth.get_destination(), &i))
+        .collect::<Vec<_>>();
+
+    log::debug!(
+        "split packet from {} to {} into {} fragments",
+        ip.get_source(),
+        ip.get_destination(),
+        fragments.len()
+    );
+
+    Ok(fragments)
+}
+
+/// Splits the packet into fragments of at most `mtu` bytes, the packet may itself be a fragment
+fn fragment(ip: &Ipv4Packet, mtu: usize) -> Vec<Vec<u8>> {
+    let header_len = ip.get_header_length() as usize * 4;
+    let payload = ip.payload();
+    // The offsets are in units of 8 bytes so all but the last fragment must be a multiple of 8
+    let chunk_len = (mtu - header_len) / 8 * 8;
+    let offset = ip.get_fragment_offset() as usize * 8;
+    let more_fragments = ip.get_flags() & Ipv4Flags::MoreFragments != 0;
+
+    payload
+        .chunks(chunk_len)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let mut buff = vec![0u8; header_len + chunk.len()];
+            buff[..header_len].copy_from_slice(&ip.packet()[..header_len]);
+            buff[header_len..].copy_from_slice(chunk);
+
+            let last = (i + 1) * chunk_len >= payload.len();
+            let mut fragment = MutableIpv4Packet::new(&mut buff).unwrap();
+            fragment.set_total_length((header_len + chunk.len()) as u16);
+            fragment.set_fragment_offset(((offset + i * chunk_len) / 8) as u16);
+            fragment.set_flags(if last && !more_fragments {
+                0
+            } else {
+                Ipv4Flags::MoreFragments
+            });
+            fragment.set_checksum(ipv4::checksum(&fragment.to_immutable()));
+
+            buff
+        })
+        .collect()
+}
+
+/// Replies to the node which sent the packet with the mtu of the link, as in path mtu discovery
+fn send_fragmentation_needed<F>(state: &SharedState, ip: &Ipv4Packet, mtu: u16, send: F)
+where
+    F: FnOnce(EthernetPacket<'static>) -> bool,
+{
+    let (router_ip, router_mac, sender_mac) = state.get(|s| {
+        let sender = s.nodes.iter().find(|i| i.ip == ip.get_source());
+        let interface = s.interface_for(ip.get_source());
+        (
+            interface.map(|i| i.ip),
+            interface.and_then(|i| i.mac),
+            sender.and_then(|i| i.mac),
+        )
+    });
+
+    let (router_ip, router_mac, sender_mac) = match (router_ip, router_mac, sender_mac) {
+        (Some(ip), Some(mac), Some(sender_mac)) => (ip, mac, sender_mac),
+        _ => {
+            log::debug!("cannot send fragmentation needed to {}", ip.get_source());
+            return;
+        }
+    };
+
+    // The original header and the start of its payload are quoted after the next hop mtu
+    let header_len = ip.get_header_length() as usize * 4;
+    let quote_len = (header_len + ICMP_QUOTE_LEN).min(ip.packet().len());
+    let mut rest = [0u8; 4];
+    rest[2..].copy_from_slice(&mtu.to_be_bytes());
+
+    let icmp = build_icmp(
+        IcmpTypes::DestinationUnreachable.0,
+        4,
+        rest,
+        &ip.packet()[..quote_len],
+    );
+
+    let reply = build_ipv4(
+        &Ipv4Header {
+            src: router_ip,
+            dst: ip.get_source(),
+            ttl: 64,
+            tos: 0,
+            identification: 0,
+            dont_fragment: false,
+            protocol: IpNextHeaderProtocols::Icmp,
+        },
+        &icmp,
+    );
+
+    if !send(build_ethernet(router_mac, sender_mac, &reply)) {
+        log::warn!("failed to send fragmentation needed to {}", ip.get_source());
+    }
+}
@@ -0,0 +1,183 @@
+use std::time::SystemTime;
+
+use pnet::packet::{
+    ethernet::EthernetPacket,
+    icmp::{IcmpPacket, IcmpTypes},
+    ip::IpNextHeaderProtocols,
+    ipv4::{Ipv4Flags, Ipv4Packet},
+    tcp::{TcpFlags, TcpPacket},
+    udp::UdpPacket,
+    Packet,
+};
+
+use crate::state::{Decision, Flow, PacketRecord, SharedState, State};
+
+/// Decodes the frame and adds it to the packet inspector along with what was done with it
+pub fn record(
+    state: &SharedState,
+    eth: &EthernetPacket,
+    ip: &Ipv4Packet,
+    decision: Decision,
+    tags: &[&str],
+) {
+    state.update(|s| {
+        let packet = decode(s, eth, ip, decision, tags);
+        s.record_packet(packet);
+    });
+}
+
+/// Decodes the frame's headers for display in the packet inspector
+pub fn decode(
+    state: &State,
+    eth: &EthernetPacket,
+    ip: &Ipv4Packet,
+    decision: Decision,
+    tags: &[&str],
+) -> PacketRecord {
+    let (protocol, src_port, dst_port, info) = decode_transport(ip);
+    let info = match fragment_info(ip) {
+        Some(fragment) if info.is_empty() => fragment,
+        Some(fragment) => format!("{} {}", info, fragment),
+        None => info,
+    };
+
+    PacketRecord {
+        id: 0,
+        time: SystemTime::now(),
+        src_mac: eth.get_source(),
+        dst_mac: eth.get_destination(),
+        src_ip: ip.get_source(),
+        dst_ip: ip.get_destination(),
+        src_node: state.node_name(ip.get_source()),
+        dst_node: state.node_name(ip.get_destination()),
+        protocol,
+        src_port,
+        dst_port,
+        ttl: ip.get_ttl(),
+        length: eth.packet().len(),
+        info,
+        decision,
+        tags: tags.iter().map(|i| i.to_string()).collect(),
+    }
+}
+
+/// Decodes the addresses, protocol and ports of the packet
+pub fn decode_flow(ip: &Ipv4Packet) -> Flow {
+    let (protocol, src_port, dst_port, _) = decode_transport(ip);
+
+    Flow {
+        src_ip: ip.get_source(),
+        dst_ip: ip.get_destination(),
+        protocol,
+        src_port,
+        dst_port,
+    }
+}
+
+/// Returns the protocol name, ports and a short description of the transport header
+fn decode_transport(ip: &Ipv4Packet) -> (String, Option<u16>, Option<u16>, String) {
+    let protocol = ip.get_next_level_protocol();
+
+    // Only the first fragment carries the transport header
+    if ip.get_fragment_offset() > 0 {
+        return (
+            protocol.to_string().to_lowercase(),
+            None,
+            None,
+            String::new(),
+        );
+    }
+
+    match protocol {
+        IpNextHeaderProtocols::Tcp => match TcpPacket::new(ip.payload()) {
+            Some(tcp) => (
+                "tcp".to_string(),
+                Some(tcp.get_source()),
+                Some(tcp.get_destination()),
+                format!(
+                    "[{}] seq={} ack={} len={}",
+                    tcp_flags(tcp.get_flags()),
+                    tcp.get_sequence(),
+                    tcp.get_acknowledgement(),
+                    tcp.payload().len()
+                ),
+            ),
+            None => ("tcp".to_string(), None, None, "truncated".to_string()),
+        },
+        IpNextHeaderProtocols::Udp => match UdpPacket::new(ip.payload()) {
+            Some(udp) => (
+                "udp".to_string(),
+                Some(udp.get_source()),
+                Some(udp.get_destination()),
+                format!("len={}", udp.payload().len()),
+            ),
+            None => ("udp".to_string(), None, None, "truncated".to_string()),
+        },
+        IpNextHeaderProtocols::Icmp => match IcmpPacket::new(ip.payload()) {
+            Some(icmp) => ("icmp".to_string(), None, None, icmp_type(&icmp)),
+            None => ("icmp".to_string(), None, None, "truncated".to_string()),
+        },
+        _ => (
+            protocol.to_string().to_lowercase(),
+            None,
+            None,
+            format!("len={}", ip.payload().len()),
+        ),
+    }
+}
+
+/// Describes where the fragment belongs in the original packet, none if it is not a fragment
+fn fragment_info(ip: &Ipv4Packet) -> Option<String> {
+    let more_fragments = ip.get_flags() & Ipv4Flags::MoreFragments != 0;
+
+    if !more_fragments && ip.get_fragment_offset() == 0 {
+        return None;
+    }
+
+    Some(format!(
+        "fragment id={} offset={}{}",
+        ip.get_identification(),
+        ip.get_fragment_offset() as usize * 8,
+        if more_fragments { " MF" } else { "" }
+    ))
+}
+
+fn tcp_flags(flags: u16) -> String {
+    let names = [
+        (TcpFlags::SYN, "SYN"),
+        (TcpFlags::ACK, "ACK"),
+        (TcpFlags::FIN, "FIN"),
+        (TcpFlags::RST, "RST"),
+        (TcpFlags::PSH, "PSH"),
+        (TcpFlags::URG, "URG"),
+    ];
+
+    names
+        .iter()
+        .filter(|(flag, _)| flags & flag != 0)
+        .map(|(_, name)| *name)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn icmp_type(icmp: &IcmpPacket) -> String {
+    let name = match icmp.get_icmp_type() {
+        IcmpTypes::EchoRequest => "echo request",
+        IcmpTypes::EchoReply => "echo reply",
+        IcmpTypes::DestinationUnreachable => "destination unreachable",
+        IcmpTypes::TimeExceeded => "time exceeded",
+        IcmpTypes::RedirectMessage => "redirect",
+        IcmpTypes::ParameterProblem => "parameter problem",
+        _ => "",
+    };
+
+    if name.is_empty() {
+        format!(
+            "type={} code={}",
+            icmp.get_icmp_type().0,
+            icmp.get_icmp_code().0
+        )
+    } else {
+        format!("{} code={}", name, icmp.get_icmp_code().0)
+    }
+}
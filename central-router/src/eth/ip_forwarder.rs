@@ -1,32 +1,229 @@
-use std::{
-    net::{IpAddr, Ipv4Addr},
-    sync::mpsc::Sender,
-};
+use std::{net::Ipv4Addr, sync::mpsc::Sender};
 
 use pnet::packet::ipv4::Ipv4Packet;
 use pnet::{
-    datalink::NetworkInterface,
     packet::ethernet::{EthernetPacket, MutableEthernetPacket},
     packet::Packet,
+    util::MacAddr,
 };
 use state::{Node, State};
 
-use crate::state::{self, SharedState};
+use crate::state::{self, Decision, Flow, LinkKey, Mode, Path, SharedState};
 
+use super::breakpoint;
+use super::broadcast;
+use super::builder::build_ethernet;
+use super::corruption;
 use super::event::Event;
+use super::fragment;
+use super::inspector;
+use super::queue;
+use super::switch;
+use super::topology;
+
+/// The next hop a packet was sent to, along with labels for anything done to it on the way
+struct Forwarded {
+    next_hop: String,
+    tags: Vec<&'static str>,
+}
 
 pub fn process_packet(
     tx: &mut Sender<Event>,
     state: &mut SharedState,
     eth: EthernetPacket,
-    interface: &NetworkInterface,
+    vlan: u16,
 ) {
     let ip = Ipv4Packet::new(eth.payload()).unwrap();
     log::trace!("packet is ipv4");
 
+    // Frames sent by or to the router itself are not candidates for forwarding
+    // so are left out of the packet inspector
+    let (from_router, to_router) = state.get(|s| {
+        (
+            s.interfaces.iter().any(|i| i.mac == Some(eth.get_source())),
+            s.interfaces.iter().any(|i| i.ip == ip.get_destination()),
+        )
+    });
+
+    if from_router {
+        log::trace!("packet is sent from interface, ignoring");
+        return;
+    }
+
+    if to_router {
+        log::trace!("packet dest is local interface, ignoring");
+        return;
+    }
+
+    // The frame must be tagged with the vlan of the node which sent it
+    let sender_vlan = state.get(|s| {
+        s.nodes
+            .iter()
+            .find(|i| i.mac == Some(eth.get_source()))
+            .map(|i| i.vlan)
+    });
+
+    if sender_vlan.map_or(false, |i| i != vlan) {
+        log::debug!("packet from {} is tagged with vlan {}", eth.get_source(), vlan);
+        let decision = Decision::Dropped {
+            reason: "frame is tagged with another vlan".to_string(),
+        };
+        inspector::record(state, &eth, &ip, decision, &[]);
+        return;
+    }
+
+    if breakpoint::pause_if_matches(state, &eth, &ip) {
+        return;
+    }
+
+    let (decision, tags) = decide(forward_packet(tx, state, &eth, &ip));
+    inspector::record(state, &eth, &ip, decision, &tags);
+}
+
+/// Forwards or drops a packet which was held at a breakpoint
+pub fn release_packet(
+    tx: &mut Sender<Event>,
+    state: &SharedState,
+    id: u64,
+    forward: bool,
+) {
+    let frame = match breakpoint::take_paused(state, id) {
+        Some(frame) => frame,
+        None => {
+            log::debug!("could not find paused packet {}", id);
+            return;
+        }
+    };
+
+    let eth = EthernetPacket::new(&frame).unwrap();
+    let ip = Ipv4Packet::new(eth.payload()).unwrap();
+
+    let (decision, tags) = if forward {
+        log::info!("releasing paused packet {}", id);
+        let (decision, mut tags) = decide(forward_packet(tx, state, &eth, &ip));
+        tags.insert(0, "released");
+        (decision, tags)
+    } else {
+        log::info!("dropping paused packet {}", id);
+        let decision = Decision::Dropped {
+            reason: "dropped at breakpoint".to_string(),
+        };
+        (decision, vec![])
+    };
+
+    inspector::record(state, &eth, &ip, decision, &tags);
+}
+
+/// Returns what was done with the packet along with its labels for the packet inspector
+fn decide(result: Result<Forwarded, &'static str>) -> (Decision, Vec<&'static str>) {
+    match result {
+        Ok(forwarded) => (
+            Decision::Forwarded {
+                next_hop: forwarded.next_hop,
+            },
+            forwarded.tags,
+        ),
+        Err(reason) => (
+            Decision::Dropped {
+                reason: reason.to_string(),
+            },
+            vec![],
+        ),
+    }
+}
+
+/// Sends a crafted ipv4 packet to the next hop along the chain as if it had been
+/// received from the node `from`, returning the name of the next hop.
+/// The ethernet addresses default to the router's and the next hop's.
+pub fn inject_packet(
+    state: &SharedState,
+    from: Ipv4Addr,
+    ip: &[u8],
+    src_mac: Option<MacAddr>,
+    dst_mac: Option<MacAddr>,
+) -> Result<String, &'static str> {
+    let packet = Ipv4Packet::new(ip).ok_or("invalid ipv4 packet")?;
+
+    let (router_mac, next_hop) = state.update(|s| -> Result<_, &'static str> {
+        let source_node = s
+            .nodes
+            .iter()
+            .find(|i| i.ip == from)
+            .cloned()
+            .ok_or("source is not a registered node")?;
+
+        // Frames sent to an explicit mac address skip the routing
+        if let Some(mac) = dst_mac {
+            let node = s.nodes.iter().find(|i| i.mac == Some(mac));
+            let name = node.map_or(mac.to_string(), |i| i.name.clone());
+            let ip = node.map_or(packet.get_destination(), |i| i.ip);
+            return Ok((router_mac(s, ip), (name, Some(mac), node.map(|i| i.ip), vec![])));
+        }
+
+        let dest_node = s
+            .nodes
+            .iter()
+            .find(|i| i.ip == packet.get_destination())
+            .cloned()
+            .ok_or("destination is not a registered node")?;
+        let flow = inspector::decode_flow(&packet);
+        let (next_hop_node, tags) = find_next_hop(s, &source_node, &dest_node, &flow)?;
+
+        Ok((
+            router_mac(s, next_hop_node.ip),
+            (next_hop_node.name, next_hop_node.mac, Some(next_hop_node.ip), tags),
+        ))
+    })?;
+
+    let (next_hop, next_hop_mac, next_hop_ip, switch_tags) = next_hop;
+    let src_mac = src_mac.or(router_mac).ok_or("interface mac is not known")?;
+    let dst_mac = next_hop_mac.ok_or("next hop mac is not known")?;
+
+    let eth = build_ethernet(src_mac, dst_mac, ip);
+    let frame = eth.packet().to_vec();
+
+    log::info!("injecting packet as if from {} via next hop {}", from, next_hop);
+    let send = |eth| state.send_event(Event::SendPacket(eth));
+    let mut tags = match next_hop_ip {
+        Some(to) => send_on_link(state, LinkKey { from, to }, eth, send)?,
+        None if !send(eth) => return Err("the ethernet forwarder is not running"),
+        None => vec![],
+    };
+    tags.splice(0..0, switch_tags);
+    tags.insert(0, "injected");
+
+    inspector::record(
+        state,
+        &EthernetPacket::new(&frame).unwrap(),
+        &packet,
+        Decision::Forwarded {
+            next_hop: next_hop.clone(),
+        },
+        &tags,
+    );
+
+    Ok(next_hop)
+}
+
+/// Returns the mac of the interface on the subnet the frame is headed to
+fn router_mac(state: &State, ip: Ipv4Addr) -> Option<MacAddr> {
+    state
+        .interface_for(ip)
+        .or_else(|| state.interfaces.first())
+        .and_then(|i| i.mac)
+}
+
+/// Sends the packet to the next hop towards its destination, returning the name
+/// of the next hop or the reason the packet was dropped
+fn forward_packet(
+    tx: &mut Sender<Event>,
+    state: &SharedState,
+    eth: &EthernetPacket,
+    ip: &Ipv4Packet,
+) -> Result<Forwarded, &'static str> {
     if !state.get(|s| s.on) {
         log::trace!("ignoring, state is off");
-        return;
+        return Err("forwarding is off");
     }
 
     let source_mac = eth.get_source();
@@ -34,19 +231,32 @@ pub fn process_packet(
 
     log::trace!("packet from {} to dest {}", source_mac, dest_ip);
 
-    if !is_in_local_net(dest_ip, interface) {
-        log::trace!("packet dest is not local network, ignoring");
-        return;
+    // Broadcasts and multicasts are sent on to many nodes rather than along one path
+    if state.get(|s| broadcast::is_propagated(s, dest_ip)) {
+        let (nodes, tags) = broadcast::propagate(tx, state, eth, ip)?;
+        return Ok(Forwarded {
+            next_hop: nodes.join(", "),
+            tags,
+        });
     }
 
-    if interface.ips.iter().any(|i| i.ip() == IpAddr::V4(dest_ip)) {
-        log::trace!("packet dest is local interface, ignoring");
-        return;
+    let (is_local, to_router) = state.get(|s| {
+        (
+            s.interface_for(dest_ip).is_some(),
+            s.interfaces.iter().any(|i| i.mac == Some(eth.get_destination())),
+        )
+    });
+
+    if !is_local {
+        log::trace!("packet dest is not local network, ignoring");
+        return Err("destination is not in the local network");
     }
 
-    if source_mac == interface.mac.unwrap() {
-        log::trace!("packet is sent from interface, ignoring");
-        return;
+    // Nodes which forward packets themselves send them directly to their
+    // next hop, these must not be routed a second time
+    if !to_router {
+        log::trace!("packet is not addressed to interface, ignoring");
+        return Err("frame is not addressed to the router");
     }
 
     log::trace!(
@@ -55,37 +265,30 @@ pub fn process_packet(
         dest_ip
     );
 
-    let nodes = state.get(|state| {
-        let source_node = state.nodes.iter().find(|i| i.mac == Some(source_mac));
+    let nodes = state.update(|state| {
+        let source_node = match state.nodes.iter().find(|i| i.mac == Some(source_mac)) {
+            Some(node) => node.clone(),
+            None => {
+                log::trace!("could not find node with mac {}", source_mac);
+                return Err("source is not a registered node");
+            }
+        };
 
-        if source_node.is_none() {
-            log::trace!("could not find node with mac {}", source_mac);
-            return None;
-        }
-
-        let dest_node = state.nodes.iter().find(|i| i.ip == dest_ip);
-
-        if dest_node.is_none() {
-            log::debug!("could not find dest node with ip {}", dest_ip);
-            return None;
-        }
+        let dest_node = match state.nodes.iter().find(|i| i.ip == dest_ip) {
+            Some(node) => node.clone(),
+            None => {
+                log::debug!("could not find dest node with ip {}", dest_ip);
+                return Err("destination is not a registered node");
+            }
+        };
 
-        let source_node = source_node.unwrap();
-        let dest_node = dest_node.unwrap();
-        let next_hop_node = find_next_hop_node(state, source_node, dest_node);
+        let flow = inspector::decode_flow(ip);
+        let (next_hop_node, tags) = find_next_hop(state, &source_node, &dest_node, &flow)?;
 
-        Some((
-            source_node.clone(),
-            dest_node.clone(),
-            next_hop_node.clone(),
-        ))
+        Ok((source_node, dest_node, next_hop_node, tags))
     });
 
-    if nodes.is_none() {
-        return;
-    }
-
-    let (source_node, dest_node, next_hop_node) = nodes.unwrap();
+    let (source_node, dest_node, next_hop_node, mut tags) = nodes?;
 
     log::debug!(
         "forwarding packet from {} to {} via next hop {}",
@@ -93,57 +296,197 @@ pub fn process_packet(
         dest_node.name,
         next_hop_node.name
     );
-    send_packet_to_next_hop(tx, next_hop_node, interface, eth);
+    let next_hop = next_hop_node.name.clone();
+    tags.extend(send_packet_to_next_hop(tx, state, &source_node, next_hop_node, eth)?);
+
+    Ok(Forwarded { next_hop, tags })
 }
 
-fn is_in_local_net(dest_ip: Ipv4Addr, interface: &NetworkInterface) -> bool {
-    interface
-        .ips
-        .iter()
-        .any(|i| i.contains(IpAddr::V4(dest_ip)))
+/// Returns the node the packet is sent to next along with labels for the packet inspector.
+/// In switching mode the frame is switched straight to the destination.
+fn find_next_hop(
+    state: &mut State,
+    source_node: &Node,
+    dest_node: &Node,
+    flow: &Flow,
+) -> Result<(Node, Vec<&'static str>), &'static str> {
+    match state.mode {
+        Mode::Switching if source_node != dest_node => {
+            if !state.group(&source_node.group).on {
+                return Err("the group is off");
+            }
+
+            let tags = switch::switch_frame(state, source_node, dest_node)?;
+            Ok((dest_node.clone(), tags))
+        }
+        _ => Ok((find_next_hop_node(state, source_node, dest_node, flow)?, vec![])),
+    }
+}
+
+/// Where a packet heads when it crosses between parts of the network such as groups or subnets
+enum Hop {
+    /// Along the source's chain towards the node
+    Towards(Node),
+    /// Straight to the node as the source is the gateway out of its part of the network
+    Direct(Node),
 }
 
-/// Returns the next node along the chain.
-/// This will return a node which is one stop closer to the destination along the chain.
-/// The chain is defined by the order at which the appear in the Vec<Node>
-fn find_next_hop_node<'a>(
-    state: &'a State,
-    source_node: &'a state::Node,
-    dest_node: &'a state::Node,
-) -> &'a Node {
+/// Returns the next node along the path picked by the routes for the flow.
+/// By default this will return a node which is one stop closer to the destination along the chain.
+/// The chain is defined by the order at which the nodes of the source's group, vlan and subnet appear in the Vec<Node>,
+/// packets for other groups, vlans or subnets head to the gateway which sends them on to the other gateway
+fn find_next_hop_node(
+    state: &mut State,
+    source_node: &state::Node,
+    dest_node: &state::Node,
+    flow: &Flow,
+) -> Result<Node, &'static str> {
     if source_node == dest_node {
         log::debug!("source node is equal to dest node, looping back");
-        return dest_node;
+        return Ok(dest_node.clone());
+    }
+
+    let source_group = state.group(&source_node.group);
+    let dest_group = state.group(&dest_node.group);
+
+    if !source_group.on || !dest_group.on {
+        return Err("the group is off");
     }
 
-    let source_index = state
-        .nodes
+    let target = match cross(
+        state,
+        source_node,
+        dest_node,
+        |s, n| s.group(&n.group).gateway,
+        |_, a, b| a.group == b.group,
+    ) {
+        Some(Hop::Towards(target)) => target,
+        Some(Hop::Direct(gateway)) => {
+            log::trace!("routing from group {} to {}", source_node.group, dest_node.group);
+            return Ok(gateway);
+        }
+        None => return Err("destination is in another group without a gateway"),
+    };
+
+    let target = match cross(
+        state,
+        source_node,
+        &target,
+        |s, n| s.vlan(n.vlan).gateway,
+        |_, a, b| a.vlan == b.vlan,
+    ) {
+        Some(Hop::Towards(target)) => target,
+        Some(Hop::Direct(gateway)) => {
+            log::trace!("routing from vlan {} to {}", source_node.vlan, gateway.vlan);
+            return Ok(gateway);
+        }
+        None => return Err("destination is in another vlan without a gateway"),
+    };
+
+    let target = match cross(
+        state,
+        source_node,
+        &target,
+        |s, n| s.interface_for(n.ip).and_then(|i| i.gateway),
+        |s, a, b| s.interface_index(a.ip) == s.interface_index(b.ip),
+    ) {
+        Some(Hop::Towards(target)) => target,
+        Some(Hop::Direct(gateway)) => {
+            log::trace!("routing from subnet of {} to {}", source_node.ip, gateway.ip);
+            return Ok(gateway);
+        }
+        None => return Err("destination is in another subnet without a gateway"),
+    };
+
+    let chain = state.chain(source_node);
+    let source_index = chain.iter().position(|c| c == source_node).unwrap();
+    // A gateway for one part of the network can sit in another, eg a subnet's gateway in another vlan
+    let dest_index = chain
         .iter()
-        .position(|c| c == source_node)
-        .unwrap();
-    let dest_index = state.nodes.iter().position(|c| c == dest_node).unwrap();
+        .position(|c| c == &target)
+        .ok_or("the gateway is not in the source's chain")?;
 
-    let next_hop_index = if source_index < dest_index {
-        source_index + 1
-    } else {
-        source_index - 1
+    let path = state.find_path(flow);
+    log::trace!("routing flow {:?} along {} path", flow, path.name());
+
+    let next_hop_index = match path {
+        Path::Chain => {
+            if source_index < dest_index {
+                source_index + 1
+            } else {
+                source_index - 1
+            }
+        }
+        Path::Direct => dest_index,
+        Path::Shortest => {
+            topology::find_shortest_next_hop(state, &chain, source_index, dest_index, flow)
+        }
+        Path::Reverse => {
+            // The direction is taken from the node which sent the packet so every
+            // hop keeps going the same way around the ends of the chain
+            let origin_index = chain
+                .iter()
+                .position(|c| c.ip == flow.src_ip)
+                .unwrap_or(source_index);
+            let len = chain.len();
+
+            if origin_index < dest_index {
+                (source_index + len - 1) % len
+            } else {
+                (source_index + 1) % len
+            }
+        }
+    };
+
+    Ok(chain[next_hop_index].clone())
+}
+
+/// Packets for another part of the network are routed along the source's chain to the gateway
+/// of its part, which sends them straight on to the gateway of the destination's part
+fn cross<G, S>(state: &State, source: &Node, dest: &Node, gateway: G, same: S) -> Option<Hop>
+where
+    G: Fn(&State, &Node) -> Option<Ipv4Addr>,
+    S: Fn(&State, &Node, &Node) -> bool,
+{
+    if same(state, source, dest) {
+        return Some(Hop::Towards(dest.clone()));
+    }
+
+    // Gateways must be in the same part of the network and group as the node they serve
+    let find = |node: &Node| {
+        let ip = gateway(state, node)?;
+        state
+            .nodes
+            .iter()
+            .find(|i| i.ip == ip && i.group == node.group && same(state, i, node))
+            .cloned()
     };
 
-    return &state.nodes[next_hop_index];
+    let (source_gateway, dest_gateway) = (find(source)?, find(dest)?);
+
+    if *source == source_gateway {
+        Some(Hop::Direct(dest_gateway))
+    } else {
+        Some(Hop::Towards(source_gateway))
+    }
 }
 
 fn send_packet_to_next_hop(
     tx: &mut Sender<Event>,
+    state: &SharedState,
+    source: &Node,
     next_hop: Node,
-    interface: &NetworkInterface,
-    eth: EthernetPacket,
-) {
-    if interface.mac.is_none() {
+    eth: &EthernetPacket,
+) -> Result<Vec<&'static str>, &'static str> {
+    // The frame leaves through the interface on the next hop's subnet
+    let interface_mac = state.get(|s| s.interface_for(next_hop.ip).and_then(|i| i.mac));
+
+    if interface_mac.is_none() {
         log::warn!(
-            "could not forward packet on interface {} as mac is not known",
-            interface.name
+            "could not forward packet to next hop {} as the mac of its interface is not known",
+            next_hop.name
         );
-        return;
+        return Err("interface mac is not known");
     }
 
     if next_hop.mac.is_none() {
@@ -151,15 +494,53 @@ fn send_packet_to_next_hop(
             "could not forward packet to next hop {} as mac is not known",
             next_hop.name
         );
-        return;
+        return Err("next hop mac is not known");
     }
 
     let mut new_eth = MutableEthernetPacket::owned(eth.packet().to_vec()).unwrap();
 
-    new_eth.set_source(interface.mac.unwrap());
+    new_eth.set_source(interface_mac.unwrap());
     new_eth.set_destination(next_hop.mac.unwrap());
 
-    if let Err(err) = tx.send(Event::SendPacket(new_eth.consume_to_immutable())) {
-        log::warn!("error while forwarding packet: {}", err);
+    let link = LinkKey {
+        from: source.ip,
+        to: next_hop.ip,
+    };
+
+    send_on_link(state, link, new_eth.consume_to_immutable(), |eth| {
+        match tx.send(Event::SendPacket(eth)) {
+            Ok(()) => true,
+            Err(err) => {
+                log::warn!("error while forwarding packet: {}", err);
+                false
+            }
+        }
+    })
+}
+
+/// Sends the frame along the link, fragmenting it to fit the link's mtu
+/// and waiting in the link's queue when it has one. Returns the labels for
+/// anything done to the frame on the way.
+pub fn send_on_link<F>(
+    state: &SharedState,
+    link: LinkKey,
+    eth: EthernetPacket<'static>,
+    mut send: F,
+) -> Result<Vec<&'static str>, &'static str>
+where
+    F: FnMut(EthernetPacket<'static>) -> bool,
+{
+    let (eth, corrupted) = corruption::corrupt(state, link, eth);
+
+    for eth in fragment::apply_mtu(state, link, eth, &mut send)? {
+        if queue::enqueue(state, link, &eth)? {
+            continue;
+        }
+
+        if !send(eth) {
+            return Err("failed to send packet");
+        }
     }
+
+    Ok(if corrupted { vec!["corrupted"] } else { vec![] })
 }
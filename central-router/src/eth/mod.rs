@@ -1,49 +1,88 @@
 mod arp;
-mod event;
+mod breakpoint;
+mod broadcast;
+pub mod builder;
+mod corruption;
+pub mod event;
+mod fragment;
+mod inspector;
 mod ip_forwarder;
+mod queue;
+mod stp;
+mod switch;
+mod topology;
+mod vlan;
 
-use std::{sync::mpsc::{self, Sender}, thread, time::Duration};
+pub use ip_forwarder::inject_packet;
+
+use std::{net::IpAddr, sync::mpsc::{self, Sender}, thread, time::Duration};
 
 use anyhow::anyhow;
 use anyhow::{bail, Result};
 use datalink::NetworkInterface;
 use event::Event;
 use pnet::datalink::{self, Channel, DataLinkReceiver};
+use pnet::packet::arp::ArpPacket;
 use pnet::packet::ethernet::{EtherTypes, EthernetPacket};
+use pnet::packet::ipv4::Ipv4Packet;
 use pnet::{datalink::DataLinkSender, packet::Packet};
 
-use crate::{args::Args, state::SharedState};
+use crate::{
+    args::Args,
+    state::{Interface, SharedState, State, NATIVE_VLAN},
+};
 
 pub fn start(args: Args, mut state: SharedState) -> Result<()> {
+    let names = args.interfaces();
     log::info!(
-        "starting ethernet forwarder on interface {}",
-        args.interface
+        "starting ethernet forwarder on interfaces {}",
+        names.join(", ")
     );
 
-    let interface = datalink::interfaces()
-        .into_iter()
-        .filter(|i| i.name == args.interface)
-        .next()
-        .ok_or(anyhow!("could not find interface named {}", args.interface))?;
-
-    let (mut dtx, drx) = match datalink::channel(&interface, Default::default()) {
-        Ok(Channel::Ethernet(tx, rx)) => (tx, rx),
-        Ok(_) => bail!("Unknown channel type"),
-        Err(err) => bail!("Error while creating packet socket: {}", err),
-    };
+    let interfaces = names
+        .iter()
+        .map(|name| {
+            datalink::interfaces()
+                .into_iter()
+                .find(|i| &i.name == name)
+                .ok_or(anyhow!("could not find interface named {}", name))
+        })
+        .collect::<Result<Vec<_>>>()?;
 
     let (mut tx, rx) = mpsc::channel::<Event>();
+    let mut dtxs = vec![];
+
+    for interface in interfaces.iter() {
+        let (dtx, drx) = match datalink::channel(interface, Default::default()) {
+            Ok(Channel::Ethernet(tx, rx)) => (tx, rx),
+            Ok(_) => bail!("Unknown channel type"),
+            Err(err) => bail!("Error while creating packet socket: {}", err),
+        };
+
+        dtxs.push(dtx);
+        spawn(&tx, &state, &interfaces, move |tx, _, _| receive_packets(drx, tx));
+    }
+
+    let subnets = interfaces
+        .iter()
+        .map(to_subnet)
+        .collect::<Result<Vec<_>>>()?;
+
+    state.set_events(tx.clone());
+    state.update(|s| s.interfaces = subnets);
 
-    spawn(&tx, &state, &interface, move |tx, _, _| receive_packets(drx, tx));
-    spawn(&tx, &state, &interface, |tx, state, _| terminate_if_stopped(state, tx));
-    spawn(&tx, &state, &interface, |tx, state, interface| {
-        arp::send_requests(state, interface, tx)
-    });
+    spawn(&tx, &state, &interfaces, |tx, state, _| terminate_if_stopped(state, tx));
+    spawn(&tx, &state, &interfaces, |tx, state, _| arp::send_requests(state, tx));
+    spawn(&tx, &state, &interfaces, |tx, state, _| queue::service(state, tx));
+    spawn(&tx, &state, &interfaces, |_, state, _| stp::run(state));
 
     loop {
         match rx.recv()? {
-            Event::PacketReceived(packet) => process_packet(&mut tx, &mut state, packet, &interface),
-            Event::SendPacket(packet) => send_packet(&mut dtx, packet),
+            Event::PacketReceived(packet) => process_packet(&mut tx, &mut state, packet),
+            Event::SendPacket(packet) => send_packet(&mut dtxs, &state, packet),
+            Event::ReleasePacket { id, forward } => {
+                ip_forwarder::release_packet(&mut tx, &state, id, forward)
+            }
             Event::Terminate(res) => break res?,
         }
     }
@@ -53,19 +92,72 @@ pub fn start(args: Args, mut state: SharedState) -> Result<()> {
     Ok(())
 }
 
-fn spawn<F>(tx: &mpsc::Sender<Event>, state: &SharedState, interface: &NetworkInterface, f: F)
+fn spawn<F>(tx: &mpsc::Sender<Event>, state: &SharedState, interfaces: &[NetworkInterface], f: F)
 where
-    F: FnOnce(mpsc::Sender<Event>, SharedState, NetworkInterface) -> () + Send + 'static,
+    F: FnOnce(mpsc::Sender<Event>, SharedState, Vec<NetworkInterface>) + Send + 'static,
 {
     let tx = tx.clone();
     let state = state.clone();
-    let interface = interface.clone();
+    let interfaces = interfaces.to_vec();
 
-    thread::spawn(move || f(tx, state, interface));
+    thread::spawn(move || f(tx, state, interfaces));
 }
 
-fn send_packet(dtx: &mut Box<dyn DataLinkSender>, packet: EthernetPacket) {
-    dtx.send_to(packet.packet(), None);
+/// Describes the subnet of nodes reached through the interface
+fn to_subnet(interface: &NetworkInterface) -> Result<Interface> {
+    let network = interface
+        .ips
+        .iter()
+        .find(|i| i.is_ipv4())
+        .ok_or(anyhow!("interface {} has no ipv4 address", interface.name))?;
+
+    let ip = match network.ip() {
+        IpAddr::V4(ip) => ip,
+        _ => unreachable!(),
+    };
+
+    Ok(Interface {
+        name: interface.name.clone(),
+        mac: interface.mac,
+        ip,
+        prefix: network.prefix(),
+        gateway: None,
+    })
+}
+
+fn send_packet(
+    dtxs: &mut [Box<dyn DataLinkSender>],
+    state: &SharedState,
+    packet: EthernetPacket<'static>,
+) {
+    let (index, vlan) = state.get(|s| choose_port(s, &packet));
+    let packet = vlan::tag(packet, vlan);
+    dtxs[index].send_to(packet.packet(), None);
+}
+
+/// Picks the interface on the subnet of the node the frame is addressed to, or of
+/// the ip it is for when it is broadcast, falling back to the first interface.
+/// The frame is tagged with the vlan of that node.
+fn choose_port(state: &State, eth: &EthernetPacket) -> (usize, u16) {
+    let dest_ip = state
+        .nodes
+        .iter()
+        .find(|i| i.mac == Some(eth.get_destination()))
+        .map(|i| i.ip)
+        .or_else(|| match eth.get_ethertype() {
+            EtherTypes::Arp => ArpPacket::new(eth.payload()).map(|i| i.get_target_proto_addr()),
+            EtherTypes::Ipv4 => Ipv4Packet::new(eth.payload()).map(|i| i.get_destination()),
+            _ => None,
+        });
+
+    let index = dest_ip
+        .and_then(|ip| state.interface_index(ip))
+        .unwrap_or(0);
+    let vlan = dest_ip
+        .and_then(|ip| state.nodes.iter().find(|i| i.ip == ip))
+        .map_or(NATIVE_VLAN, |i| i.vlan);
+
+    (index, vlan)
 }
 
 fn receive_packets(mut drx: Box<dyn DataLinkReceiver>, tx: mpsc::Sender<Event>) {
@@ -95,10 +187,21 @@ fn receive_packets(mut drx: Box<dyn DataLinkReceiver>, tx: mpsc::Sender<Event>)
     }
 }
 
-fn process_packet(tx: &mut Sender<Event>, state: &mut SharedState, packet: EthernetPacket, interface: &NetworkInterface) {
+fn process_packet(tx: &mut Sender<Event>, state: &mut SharedState, packet: EthernetPacket<'static>) {
+    let (packet, vlan) = match vlan::untag(packet) {
+        Some(untagged) => untagged,
+        None => {
+            log::warn!("failed to parse vlan tag");
+            return;
+        }
+    };
+
     match packet.get_ethertype() {
-        EtherTypes::Arp => arp::process_packet(state, packet),
-        EtherTypes::Ipv4 => ip_forwarder::process_packet(tx, state, packet, interface),
+        EtherTypes::Arp => {
+            switch::observe(state, &packet);
+            arp::process_packet(state, packet)
+        }
+        EtherTypes::Ipv4 => ip_forwarder::process_packet(tx, state, packet, vlan),
         _ => {}
     }
 }
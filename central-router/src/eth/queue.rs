@@ -0,0 +1,140 @@
+use std::{
+    sync::mpsc::Sender,
+    thread,
+    time::{Duration, Instant},
+};
+
+use pnet::packet::{ethernet::EthernetPacket, ipv4::Ipv4Packet, Packet};
+use rand::Rng;
+
+use crate::state::{LinkKey, LinkQueue, QueueDiscipline, QueuedPacket, SharedState};
+
+use super::event::Event;
+
+const SERVICE_INTERVAL: Duration = Duration::from_millis(5);
+
+/// The weight of the current depth in red's moving average, this is much higher
+/// than usual as the queues here are small and need to react within a demo
+const RED_WEIGHT: f64 = 0.2;
+
+/// Adds the frame to the link's queue, returning false if the link has no queue
+/// and the frame should be sent straight away
+pub fn enqueue(
+    state: &SharedState,
+    link: LinkKey,
+    eth: &EthernetPacket,
+) -> Result<bool, &'static str> {
+    // Packets are prioritised by the class selector, the top 3 bits of the dscp
+    let priority = Ipv4Packet::new(eth.payload()).map_or(0, |ip| ip.get_dscp() >> 3);
+
+    state.update(|s| {
+        let queue = match s.queues.get_mut(&link) {
+            Some(queue) => queue,
+            None => return Ok(false),
+        };
+
+        let depth = queue.packets.len() as f64;
+        queue.average = (1.0 - RED_WEIGHT) * queue.average + RED_WEIGHT * depth;
+
+        if queue.packets.len() >= queue.settings.capacity {
+            log::debug!("queue from {} to {} is full", link.from, link.to);
+            queue.stats.tail_drops += 1;
+            return Err("queue is full");
+        }
+
+        if drop_early(queue) {
+            log::debug!("red dropped packet from {} to {}", link.from, link.to);
+            queue.stats.early_drops += 1;
+            return Err("dropped early by red");
+        }
+
+        queue.packets.push_back(QueuedPacket {
+            frame: eth.packet().to_vec(),
+            priority,
+        });
+        queue.stats.enqueued += 1;
+
+        Ok(true)
+    })
+}
+
+/// Sends the packets waiting in each queue at the queue's rate until the router shuts down
+pub fn service(state: SharedState, tx: Sender<Event>) {
+    while state.running() {
+        let frames = state.update(|s| {
+            let now = Instant::now();
+            s.queues
+                .values_mut()
+                .flat_map(|i| dequeue(i, now))
+                .collect::<Vec<_>>()
+        });
+
+        for frame in frames {
+            let eth = EthernetPacket::owned(frame).unwrap();
+            if tx.send(Event::SendPacket(eth)).is_err() {
+                return;
+            }
+        }
+
+        thread::sleep(SERVICE_INTERVAL);
+    }
+}
+
+fn drop_early(queue: &LinkQueue) -> bool {
+    let (min_threshold, max_threshold, max_probability) = match queue.settings.discipline {
+        QueueDiscipline::Red {
+            min_threshold,
+            max_threshold,
+            max_probability,
+        } => (min_threshold, max_threshold, max_probability),
+        _ => return false,
+    };
+
+    if queue.average < min_threshold {
+        return false;
+    }
+
+    if queue.average >= max_threshold {
+        return true;
+    }
+
+    let probability =
+        max_probability * (queue.average - min_threshold) / (max_threshold - min_threshold);
+
+    rand::thread_rng().gen::<f64>() < probability
+}
+
+/// Removes the packets which can be sent since the queue was last serviced
+fn dequeue(queue: &mut LinkQueue, now: Instant) -> Vec<Vec<u8>> {
+    let elapsed = now.duration_since(queue.last_service).as_secs_f64();
+    queue.last_service = now;
+    queue.credit += elapsed * queue.settings.rate;
+
+    // An idle link cannot save up credit to send a burst later
+    if queue.packets.is_empty() {
+        queue.credit = queue.credit.min(1.0);
+    }
+
+    let mut frames = vec![];
+
+    while queue.credit >= 1.0 {
+        let index = match queue.settings.discipline {
+            QueueDiscipline::Priority => {
+                let max = queue.packets.iter().map(|i| i.priority).max();
+                queue.packets.iter().position(|i| Some(i.priority) == max)
+            }
+            _ => Some(0),
+        };
+
+        let packet = match index.and_then(|i| queue.packets.remove(i)) {
+            Some(packet) => packet,
+            None => break,
+        };
+
+        queue.credit -= 1.0;
+        queue.stats.sent += 1;
+        frames.push(packet.frame);
+    }
+
+    frames
+}
@@ -0,0 +1,157 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    net::Ipv4Addr,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::state::{Bridge, PortRole, PortState, SharedState, State, StpMode, StpPort};
+
+use super::topology::{distances_to, neighbours};
+
+/// How often the spanning tree is recalculated and the ports move through their states
+const STP_INTERVAL: Duration = Duration::from_millis(100);
+
+/// The path cost of each link between switches, as for a 100 Mb/s link
+const PORT_COST: u32 = 19;
+
+/// How long stp ports spend listening and then learning before they forward frames
+const FORWARD_DELAY: Duration = Duration::from_secs(15);
+
+/// Keeps the spanning tree up to date as nodes and links change until the router shuts down
+pub fn run(state: SharedState) {
+    while state.running() {
+        state.update(update);
+        thread::sleep(STP_INTERVAL);
+    }
+}
+
+/// Elects the root bridges and assigns the port roles, moving each port towards the state of its role
+fn update(state: &mut State) {
+    if state.stp == StpMode::Off {
+        state.bridges.clear();
+        state.stp_ports.clear();
+        return;
+    }
+
+    let (bridges, roles) = elect(state);
+    let now = Instant::now();
+    let mode = state.stp;
+
+    state.stp_ports.retain(|key, _| roles.contains_key(key));
+
+    for (key, role) in roles {
+        let port = state.stp_ports.entry(key).or_insert(StpPort {
+            role,
+            state: PortState::Blocking,
+            since: now,
+        });
+        port.role = role;
+
+        let next = match (role, port.state) {
+            (PortRole::Alternate, _) => PortState::Blocking,
+            (_, PortState::Forwarding) => PortState::Forwarding,
+            (_, _) if mode == StpMode::Rstp => PortState::Forwarding,
+            (_, PortState::Blocking) => PortState::Listening,
+            (_, PortState::Listening) if now - port.since >= FORWARD_DELAY => PortState::Learning,
+            (_, PortState::Learning) if now - port.since >= FORWARD_DELAY => PortState::Forwarding,
+            (_, current) => current,
+        };
+
+        if next != port.state {
+            log::debug!("port {} -> {} is now {}", key.0, key.1, next.name());
+            port.state = next;
+            port.since = now;
+        }
+    }
+
+    state.bridges = bridges;
+}
+
+/// Bridges are ordered by their priority, then by their node's ip which stands in for the mac address
+fn bridge_id(state: &State, ip: Ipv4Addr) -> (u16, Ipv4Addr) {
+    (state.bridge_priority(ip), ip)
+}
+
+/// Runs the election in each chain of switches. The bridge with the lowest id becomes the root,
+/// every other bridge picks the port along its cheapest path to the root as its root port and
+/// the cheaper end of each link is its designated port. Ports which are neither are blocked.
+#[allow(clippy::type_complexity)]
+fn elect(
+    state: &State,
+) -> (
+    BTreeMap<Ipv4Addr, Bridge>,
+    BTreeMap<(Ipv4Addr, Ipv4Addr), PortRole>,
+) {
+    let mut bridges = BTreeMap::new();
+    let mut roles = BTreeMap::new();
+    let mut elected = BTreeSet::new();
+
+    for node in state.nodes.iter() {
+        if elected.contains(&node.ip) {
+            continue;
+        }
+
+        let chain = state.chain(node);
+        elected.extend(chain.iter().map(|i| i.ip));
+
+        let root = (0..chain.len())
+            .min_by_key(|i| bridge_id(state, chain[*i].ip))
+            .unwrap();
+        let costs = distances_to(&chain, &state.links, root)
+            .into_iter()
+            .map(|i| i.unwrap_or(0) as u32 * PORT_COST)
+            .collect::<Vec<_>>();
+
+        // The end of a link which is closer to the root, or has the lower id, is designated
+        let priority = |i: usize| (costs[i], bridge_id(state, chain[i].ip));
+
+        for (index, bridge) in chain.iter().enumerate() {
+            bridges.insert(
+                bridge.ip,
+                Bridge {
+                    root: chain[root].ip,
+                    cost: costs[index],
+                },
+            );
+
+            let neighbours = neighbours(&chain, &state.links, index);
+            let root_port = neighbours
+                .iter()
+                .copied()
+                .filter(|_| index != root)
+                .min_by_key(|i| priority(*i));
+
+            for neighbour in neighbours {
+                let role = if Some(neighbour) == root_port {
+                    PortRole::Root
+                } else if priority(index) < priority(neighbour) {
+                    PortRole::Designated
+                } else {
+                    PortRole::Alternate
+                };
+
+                roles.insert((bridge.ip, chain[neighbour].ip), role);
+            }
+        }
+    }
+
+    (bridges, roles)
+}
+
+/// Returns true if the switch's port to the other switch forwards frames
+pub fn forwards(state: &State, switch: Ipv4Addr, other: Ipv4Addr) -> bool {
+    state.stp == StpMode::Off
+        || state
+            .stp_ports
+            .get(&(switch, other))
+            .map_or(false, |i| i.state == PortState::Forwarding)
+}
+
+/// Returns true if the switch learns the sources of frames arriving on its port from the other switch
+pub fn learns(state: &State, switch: Ipv4Addr, other: Ipv4Addr) -> bool {
+    state.stp == StpMode::Off
+        || state.stp_ports.get(&(switch, other)).map_or(false, |i| {
+            i.state == PortState::Learning || i.state == PortState::Forwarding
+        })
+}
@@ -0,0 +1,196 @@
+use std::{collections::VecDeque, time::Instant};
+
+use pnet::packet::ethernet::EthernetPacket;
+use pnet::util::MacAddr;
+
+use crate::state::{MacEntry, Mode, Node, SharedState, State, SwitchPort};
+
+use super::stp;
+use super::topology::neighbours;
+
+/// Copies of a frame going around a loop are dropped after passing through this many switches
+const MAX_SWITCH_HOPS: usize = 16;
+
+/// A storm is cut short once this many copies of a frame have reached switches
+const MAX_FLOOD_COPIES: usize = 1000;
+
+/// The nodes a frame was delivered to by their switches
+struct Delivery {
+    /// Nodes are listed once for each copy of the frame they receive
+    nodes: Vec<Node>,
+    /// True if a switch did not know where the destination was and flooded the frame
+    flooded: bool,
+    /// True if copies of the frame were still going around a loop when they were dropped
+    storm: bool,
+}
+
+/// Switches the frame from the source along its chain of switches, returning the labels
+/// for the packet inspector or the reason the destination was not reached
+pub fn switch_frame(
+    state: &mut State,
+    source: &Node,
+    dest: &Node,
+) -> Result<Vec<&'static str>, &'static str> {
+    let src_mac = source.mac.ok_or("source mac is not known")?;
+    let dst_mac = dest.mac.ok_or("next hop mac is not known")?;
+
+    let delivery = flood(state, source, src_mac, dst_mac);
+
+    let copies = delivery.nodes.iter().filter(|i| *i == dest).count();
+
+    if copies == 0 {
+        log::debug!(
+            "frame from {} was not switched to {}",
+            source.name,
+            dest.name
+        );
+        return Err("destination was not reached through the switches");
+    }
+
+    let mut tags = vec!["switched"];
+
+    if delivery.flooded {
+        tags.push("flooded");
+    }
+
+    if delivery.storm {
+        log::warn!(
+            "broadcast storm from {}, {} copies reached {}",
+            source.name,
+            copies,
+            dest.name
+        );
+        tags.push("storm");
+    }
+
+    Ok(tags)
+}
+
+/// Floods a broadcast or multicast frame from the source through its switches, returning the
+/// other nodes it reached once each along with the labels for the packet inspector
+pub fn flood_frame(
+    state: &mut State,
+    source: &Node,
+    dst_mac: MacAddr,
+) -> Result<(Vec<Node>, Vec<&'static str>), &'static str> {
+    let src_mac = source.mac.ok_or("source mac is not known")?;
+
+    let delivery = flood(state, source, src_mac, dst_mac);
+
+    let mut nodes: Vec<Node> = vec![];
+    for node in delivery.nodes {
+        if node != *source && !nodes.contains(&node) {
+            nodes.push(node);
+        }
+    }
+
+    let mut tags = vec!["switched"];
+
+    if delivery.storm {
+        log::warn!("broadcast storm from {}", source.name);
+        tags.push("storm");
+    }
+
+    Ok((nodes, tags))
+}
+
+/// Lets the switches learn the source of a frame the router does not forward itself, such as an arp request
+pub fn observe(state: &SharedState, eth: &EthernetPacket) {
+    state.update(|s| {
+        if s.mode != Mode::Switching {
+            return;
+        }
+
+        let source = match s.nodes.iter().find(|i| i.mac == Some(eth.get_source())) {
+            Some(node) => node.clone(),
+            None => return,
+        };
+
+        flood(s, &source, eth.get_source(), eth.get_destination());
+    });
+}
+
+/// Sends the frame out of the source node's switch. Each switch learns the port the source
+/// was seen on, then sends the frame out of the port the destination was learnt on, or
+/// floods it out of every other port when the destination is unknown or the broadcast address.
+/// Ports blocked by the spanning tree neither learn nor forward frames.
+fn flood(state: &mut State, source: &Node, src_mac: MacAddr, dst_mac: MacAddr) -> Delivery {
+    let chain = state.chain(source);
+    let start = chain.iter().position(|i| i == source).unwrap();
+    let now = Instant::now();
+    let aging = state.mac_aging;
+
+    let mut delivery = Delivery {
+        nodes: vec![],
+        flooded: false,
+        storm: false,
+    };
+    let mut pending = VecDeque::from(vec![(start, SwitchPort::Node, 0)]);
+    let mut copies = 0;
+
+    while let Some((index, ingress, hops)) = pending.pop_front() {
+        let switch = chain[index].ip;
+
+        if hops > MAX_SWITCH_HOPS || copies >= MAX_FLOOD_COPIES {
+            delivery.storm = true;
+            continue;
+        }
+
+        copies += 1;
+
+        if let SwitchPort::Switch(other) = ingress {
+            if !stp::learns(state, switch, other) {
+                continue;
+            }
+        }
+
+        let table = state.mac_tables.entry(switch).or_default();
+        table.retain(|_, i| now.duration_since(i.learned) < aging);
+        table.insert(
+            src_mac,
+            MacEntry {
+                port: ingress,
+                learned: now,
+            },
+        );
+
+        let links = neighbours(&chain, &state.links, index)
+            .into_iter()
+            .map(|i| chain[i].ip)
+            .collect::<Vec<_>>();
+
+        let ports = match table.get(&dst_mac) {
+            Some(entry) => vec![entry.port],
+            None => {
+                delivery.flooded = true;
+                let mut ports = vec![SwitchPort::Node];
+                ports.extend(links.iter().map(|i| SwitchPort::Switch(*i)));
+                ports
+            }
+        };
+
+        if let SwitchPort::Switch(other) = ingress {
+            if !stp::forwards(state, switch, other) {
+                continue;
+            }
+        }
+
+        // Frames are never sent back out of the port they arrived on
+        for port in ports.into_iter().filter(|i| *i != ingress) {
+            match port {
+                SwitchPort::Node => delivery.nodes.push(chain[index].clone()),
+                // Entries for links which have since been removed lead nowhere
+                SwitchPort::Switch(other)
+                    if links.contains(&other) && stp::forwards(state, switch, other) =>
+                {
+                    if let Some(next) = chain.iter().position(|i| i.ip == other) {
+                        pending.push_back((next, SwitchPort::Switch(switch), hops + 1));
+                    }
+                }
+                SwitchPort::Switch(_) => {}
+            }
+        }
+    }
+
+    delivery
+}
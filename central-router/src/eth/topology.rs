@@ -0,0 +1,130 @@
+use std::{
+    collections::{hash_map::DefaultHasher, VecDeque},
+    hash::{Hash, Hasher},
+};
+
+use crate::state::{Ecmp, Flow, Link, Node, PathKey, State};
+
+/// Returns the next hop along one of the shortest paths from the source towards the destination
+/// within the chain, counting the packet against the chosen path when there is more than one
+pub fn find_shortest_next_hop(
+    state: &mut State,
+    chain: &[Node],
+    source_index: usize,
+    dest_index: usize,
+    flow: &Flow,
+) -> usize {
+    let candidates = equal_cost_next_hops(chain, &state.links, source_index, dest_index);
+
+    if candidates.len() <= 1 {
+        return candidates.first().copied().unwrap_or(dest_index);
+    }
+
+    let key = |via: usize| PathKey {
+        from: chain[source_index].ip,
+        to: chain[dest_index].ip,
+        via: chain[via].ip,
+    };
+
+    let choice = match state.ecmp {
+        Ecmp::Hash => {
+            let mut hasher = DefaultHasher::new();
+            flow.hash(&mut hasher);
+            hasher.finish() as usize % candidates.len()
+        }
+        Ecmp::RoundRobin => {
+            // The packets already sent along these paths tell us whose turn it is
+            let sent: u64 = candidates
+                .iter()
+                .map(|i| state.path_counters.get(&key(*i)).copied().unwrap_or(0))
+                .sum();
+            sent as usize % candidates.len()
+        }
+    };
+
+    let next_hop = candidates[choice];
+    *state.path_counters.entry(key(next_hop)).or_insert(0) += 1;
+
+    log::trace!(
+        "chose next hop {} of {} equal cost paths using {}",
+        choice + 1,
+        candidates.len(),
+        state.ecmp.name()
+    );
+
+    next_hop
+}
+
+/// Returns the neighbours of the source which are one link closer to the destination
+fn equal_cost_next_hops(
+    chain: &[Node],
+    links: &[Link],
+    source_index: usize,
+    dest_index: usize,
+) -> Vec<usize> {
+    let distances = distances_to(chain, links, dest_index);
+
+    let distance = match distances[source_index] {
+        Some(distance) if distance > 0 => distance,
+        _ => return vec![],
+    };
+
+    neighbours(chain, links, source_index)
+        .into_iter()
+        .filter(|i| distances[*i] == Some(distance - 1))
+        .collect()
+}
+
+/// Breadth first search from the destination, returning the number of links from each node
+pub fn distances_to(chain: &[Node], links: &[Link], dest_index: usize) -> Vec<Option<usize>> {
+    let mut distances = vec![None; chain.len()];
+    let mut queue = VecDeque::new();
+
+    distances[dest_index] = Some(0);
+    queue.push_back(dest_index);
+
+    while let Some(index) = queue.pop_front() {
+        let distance = distances[index].unwrap();
+
+        for neighbour in neighbours(chain, links, index) {
+            if distances[neighbour].is_none() {
+                distances[neighbour] = Some(distance + 1);
+                queue.push_back(neighbour);
+            }
+        }
+    }
+
+    distances
+}
+
+/// Returns the adjacent nodes in the chain along with the nodes in it joined by extra links
+pub fn neighbours(chain: &[Node], links: &[Link], index: usize) -> Vec<usize> {
+    let mut neighbours = vec![];
+
+    if index > 0 {
+        neighbours.push(index - 1);
+    }
+
+    if index + 1 < chain.len() {
+        neighbours.push(index + 1);
+    }
+
+    let ip = chain[index].ip;
+    for link in links.iter() {
+        let other = if link.a == ip {
+            link.b
+        } else if link.b == ip {
+            link.a
+        } else {
+            continue;
+        };
+
+        if let Some(other) = chain.iter().position(|i| i.ip == other) {
+            if !neighbours.contains(&other) {
+                neighbours.push(other);
+            }
+        }
+    }
+
+    neighbours
+}
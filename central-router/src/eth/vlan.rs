@@ -0,0 +1,52 @@
+use pnet::packet::ethernet::{EtherTypes, EthernetPacket, MutableEthernetPacket};
+use pnet::packet::vlan::{ClassesOfService, MutableVlanPacket, VlanPacket};
+use pnet::packet::{MutablePacket, Packet};
+
+use crate::state::NATIVE_VLAN;
+
+/// The length of the source and destination addresses at the start of the frame
+const ADDRESSES_LEN: usize = 12;
+const VLAN_TAG_LEN: usize = 4;
+
+/// Removes the 802.1Q tag from the frame, returning the untagged frame along with
+/// the vlan it was tagged with. Untagged and priority tagged frames are in the native vlan.
+pub fn untag(eth: EthernetPacket<'static>) -> Option<(EthernetPacket<'static>, u16)> {
+    if eth.get_ethertype() != EtherTypes::Vlan {
+        return Some((eth, NATIVE_VLAN));
+    }
+
+    let tag = VlanPacket::new(eth.payload())?;
+    let vlan = match tag.get_vlan_identifier() {
+        0 => NATIVE_VLAN,
+        vlan => vlan,
+    };
+
+    let mut buff = eth.packet()[..ADDRESSES_LEN].to_vec();
+    buff.extend_from_slice(&tag.get_ethertype().0.to_be_bytes());
+    buff.extend_from_slice(tag.payload());
+
+    Some((EthernetPacket::owned(buff)?, vlan))
+}
+
+/// Tags the frame with the vlan, frames in the native vlan are sent untagged
+pub fn tag(eth: EthernetPacket<'static>, vlan: u16) -> EthernetPacket<'static> {
+    if vlan == NATIVE_VLAN {
+        return eth;
+    }
+
+    let frame = eth.packet();
+    let mut buff = vec![0u8; frame.len() + VLAN_TAG_LEN];
+    buff[..ADDRESSES_LEN].copy_from_slice(&frame[..ADDRESSES_LEN]);
+
+    let mut tagged = MutableEthernetPacket::owned(buff).unwrap();
+    tagged.set_ethertype(EtherTypes::Vlan);
+
+    let mut tag = MutableVlanPacket::new(tagged.payload_mut()).unwrap();
+    tag.set_priority_code_point(ClassesOfService::BE);
+    tag.set_drop_eligible_indicator(0);
+    tag.set_vlan_identifier(vlan);
+    tag.set_ethertype(eth.get_ethertype());
+    tag.set_payload(eth.payload());
+
+    tagged.consume_to_immutable()
+}
@@ -1,5 +1,7 @@
 mod args;
 mod eth;
+mod scenario;
+mod session;
 pub mod state;
 mod web;
 
@@ -27,6 +29,8 @@ fn main() {
     let threads = vec![
         spawn(&args, &state, eth::start),
         spawn(&args, &state, web::start),
+        spawn(&args, &state, scenario::start),
+        spawn(&args, &state, session::start),
     ];
 
     let error = threads
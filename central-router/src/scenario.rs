@@ -0,0 +1,623 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    net::Ipv4Addr,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use pnet::packet::ethernet::EthernetPacket;
+use serde::Deserialize;
+
+use crate::{
+    args::Args,
+    eth::event::Event,
+    state::{
+        CorruptionSettings, CorruptionTarget, Link, LinkCorruption, LinkKey, LinkQueue,
+        QueueDiscipline, QueueSettings, SharedState, State, DEFAULT_QUEUE_CAPACITY,
+        DEFAULT_QUEUE_RATE, DEFAULT_RED_MAX_PROBABILITY, DEFAULT_RED_MAX_THRESHOLD,
+        DEFAULT_RED_MIN_THRESHOLD, MAX_CORRUPTED_BITS, MIN_MTU,
+    },
+};
+
+/// How often the scenario's timeline is checked for steps which are due
+const TICK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A timeline of changes to the router, loaded from a toml file such as
+///
+/// ```toml
+/// name = "Broken link"
+///
+/// [[steps]]
+/// at = 0
+/// action = "power"
+/// on = true
+///
+/// [[steps]]
+/// at = 30
+/// action = "set-corruption"
+/// from = "alice"
+/// to = "bob"
+/// rate = 0.5
+/// ```
+#[derive(Clone, Debug, Deserialize)]
+pub struct Scenario {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub steps: Vec<Step>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Step {
+    /// Seconds since the scenario started playing
+    pub at: f64,
+    #[serde(flatten)]
+    pub action: Action,
+}
+
+/// Nodes are referred to by their names or ips and are looked up when the step runs
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "action", rename_all = "kebab-case")]
+pub enum Action {
+    /// Turns forwarding on or off
+    Power {
+        on: bool,
+    },
+    /// Turns forwarding off for a number of seconds
+    Pause {
+        seconds: f64,
+    },
+    AddLink {
+        a: String,
+        b: String,
+    },
+    RemoveLink {
+        a: String,
+        b: String,
+    },
+    /// Moves the node to the index in the chain, starting from 0
+    MoveNode {
+        node: String,
+        index: usize,
+    },
+    SetMtu {
+        from: String,
+        to: String,
+        mtu: u16,
+    },
+    RemoveMtu {
+        from: String,
+        to: String,
+    },
+    SetCorruption {
+        from: String,
+        to: String,
+        rate: f64,
+        /// One of "header", "payload" or "any"
+        #[serde(default = "default_target")]
+        target: String,
+        #[serde(default = "default_bits")]
+        bits: u32,
+        #[serde(default)]
+        fix_checksums: bool,
+    },
+    RemoveCorruption {
+        from: String,
+        to: String,
+    },
+    /// Adds or replaces the queue on the link, packets already waiting in it are kept
+    SetQueue {
+        from: String,
+        to: String,
+        /// One of "tail-drop", "red" or "priority"
+        discipline: String,
+        #[serde(default = "default_capacity")]
+        capacity: usize,
+        #[serde(default = "default_rate")]
+        rate: f64,
+        #[serde(default = "default_min_threshold")]
+        min_threshold: f64,
+        #[serde(default = "default_max_threshold")]
+        max_threshold: f64,
+        #[serde(default = "default_max_probability")]
+        max_probability: f64,
+    },
+    /// Removes the queue on the link, sending the packets still waiting in it
+    RemoveQueue {
+        from: String,
+        to: String,
+    },
+}
+
+fn default_target() -> String {
+    "any".to_string()
+}
+
+fn default_bits() -> u32 {
+    1
+}
+
+fn default_capacity() -> usize {
+    DEFAULT_QUEUE_CAPACITY
+}
+
+fn default_rate() -> f64 {
+    DEFAULT_QUEUE_RATE
+}
+
+fn default_min_threshold() -> f64 {
+    DEFAULT_RED_MIN_THRESHOLD
+}
+
+fn default_max_threshold() -> f64 {
+    DEFAULT_RED_MAX_THRESHOLD
+}
+
+fn default_max_probability() -> f64 {
+    DEFAULT_RED_MAX_PROBABILITY
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScenarioStatus {
+    Playing,
+    Paused,
+    Finished,
+}
+
+/// The outcome of each step once it has run
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StepStatus {
+    Pending,
+    Done,
+    Failed(String),
+}
+
+/// The parts of the router a scenario can change, kept so it can be reset
+#[derive(Clone, Debug)]
+struct Baseline {
+    on: bool,
+    order: Vec<Ipv4Addr>,
+    links: Vec<Link>,
+    mtus: BTreeMap<LinkKey, u16>,
+    corruption: BTreeMap<LinkKey, LinkCorruption>,
+    queues: BTreeMap<LinkKey, QueueSettings>,
+}
+
+/// A loaded scenario and how far through it has played
+#[derive(Clone, Debug)]
+pub struct ScenarioRun {
+    pub scenario: Scenario,
+    pub status: ScenarioStatus,
+    pub steps: Vec<StepStatus>,
+    /// Time played before it was last paused
+    played: Duration,
+    playing_since: Option<Instant>,
+    /// When forwarding is turned back on after a pause step, in seconds
+    resume_at: Option<f64>,
+    baseline: Baseline,
+}
+
+impl Scenario {
+    /// Parses and validates a scenario, ordering its steps by time
+    pub fn parse(toml: &str) -> Result<Self> {
+        let mut scenario: Scenario = toml::from_str(toml)?;
+
+        for step in scenario.steps.iter() {
+            if !step.at.is_finite() || step.at < 0.0 {
+                return Err(anyhow!("step times must be 0 or more seconds"));
+            }
+
+            step.action.validate()?;
+        }
+
+        scenario
+            .steps
+            .sort_by(|a, b| a.at.partial_cmp(&b.at).unwrap());
+
+        Ok(scenario)
+    }
+
+    /// Seconds until the last step runs
+    pub fn duration(&self) -> f64 {
+        self.steps.last().map_or(0.0, |i| i.at)
+    }
+}
+
+impl Action {
+    fn validate(&self) -> Result<()> {
+        match self {
+            Action::Pause { seconds } if !seconds.is_finite() || *seconds <= 0.0 => {
+                Err(anyhow!("pauses must be longer than 0 seconds"))
+            }
+            Action::SetMtu { mtu, .. } if *mtu < MIN_MTU => {
+                Err(anyhow!("mtus must be at least {}", MIN_MTU))
+            }
+            Action::SetCorruption {
+                rate, target, bits, ..
+            } => {
+                if !(0.0..=1.0).contains(rate) {
+                    return Err(anyhow!("corruption rates must be between 0 and 1"));
+                }
+
+                if *bits == 0 || *bits > MAX_CORRUPTED_BITS {
+                    return Err(anyhow!(
+                        "between 1 and {} bits can be corrupted",
+                        MAX_CORRUPTED_BITS
+                    ));
+                }
+
+                CorruptionTarget::from_name(target)
+                    .map(|_| ())
+                    .ok_or_else(|| anyhow!("unknown corruption target {}", target))
+            }
+            Action::SetQueue { .. } => self.queue_settings().map(|_| ()).map_err(|i| anyhow!(i)),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the settings of the queue a set-queue step adds
+    fn queue_settings(&self) -> Result<QueueSettings, String> {
+        match self {
+            Action::SetQueue {
+                discipline,
+                capacity,
+                rate,
+                min_threshold,
+                max_threshold,
+                max_probability,
+                ..
+            } => {
+                let discipline = QueueDiscipline::from_name(
+                    discipline,
+                    *min_threshold,
+                    *max_threshold,
+                    *max_probability,
+                )?;
+                QueueSettings::new(discipline, *capacity, *rate)
+            }
+            _ => Err("not a set-queue step".to_string()),
+        }
+    }
+
+    /// A short summary of the action shown in the timeline
+    pub fn describe(&self) -> String {
+        match self {
+            Action::Power { on: true } => "turn forwarding on".to_string(),
+            Action::Power { on: false } => "turn forwarding off".to_string(),
+            Action::Pause { seconds } => format!("pause forwarding for {}s", seconds),
+            Action::AddLink { a, b } => format!("link {} and {}", a, b),
+            Action::RemoveLink { a, b } => format!("unlink {} and {}", a, b),
+            Action::MoveNode { node, index } => format!("move {} to index {}", node, index),
+            Action::SetMtu { from, to, mtu } => format!("set mtu {} from {} to {}", mtu, from, to),
+            Action::RemoveMtu { from, to } => format!("remove mtu from {} to {}", from, to),
+            Action::SetCorruption { from, to, rate, .. } => {
+                format!("corrupt {}% from {} to {}", rate * 100.0, from, to)
+            }
+            Action::RemoveCorruption { from, to } => {
+                format!("stop corrupting from {} to {}", from, to)
+            }
+            Action::SetQueue {
+                from,
+                to,
+                discipline,
+                ..
+            } => format!("set {} queue from {} to {}", discipline, from, to),
+            Action::RemoveQueue { from, to } => format!("remove queue from {} to {}", from, to),
+        }
+    }
+
+    /// Changes the router, adding the frames which were waiting in any removed queue to `released`
+    fn apply(&self, state: &mut State, released: &mut Vec<Vec<u8>>) -> Result<(), String> {
+        match self {
+            Action::Power { on } => state.on = *on,
+            Action::Pause { .. } => state.on = false,
+            Action::AddLink { a, b } => {
+                let (a, b) = find_link(state, a, b)?;
+                let exists = state
+                    .links
+                    .iter()
+                    .any(|i| (i.a == a && i.b == b) || (i.a == b && i.b == a));
+
+                if !exists {
+                    state.add_link(a, b);
+                }
+            }
+            Action::RemoveLink { a, b } => {
+                let (a, b) = find_link(state, a, b)?;
+                state
+                    .links
+                    .retain(|i| !((i.a == a && i.b == b) || (i.a == b && i.b == a)));
+            }
+            Action::MoveNode { node, index } => {
+                let cur = state
+                    .nodes
+                    .iter()
+                    .position(|i| &i.name == node || &i.ip.to_string() == node)
+                    .ok_or_else(|| format!("could not find node {}", node))?;
+
+                if *index >= state.nodes.len() {
+                    return Err(format!("there is no index {} in the chain", index));
+                }
+
+                let node = state.nodes.remove(cur);
+                state.nodes.insert(*index, node);
+            }
+            Action::SetMtu { from, to, mtu } => {
+                let link = find_link_key(state, from, to)?;
+                state.mtus.insert(link, *mtu);
+            }
+            Action::RemoveMtu { from, to } => {
+                let link = find_link_key(state, from, to)?;
+                state.mtus.remove(&link);
+            }
+            Action::SetCorruption {
+                from,
+                to,
+                rate,
+                target,
+                bits,
+                fix_checksums,
+            } => {
+                let link = find_link_key(state, from, to)?;
+                let settings = CorruptionSettings {
+                    rate: *rate,
+                    target: CorruptionTarget::from_name(target).unwrap(),
+                    bits: *bits,
+                    fix_checksums: *fix_checksums,
+                };
+
+                state
+                    .corruption
+                    .entry(link)
+                    .and_modify(|i| i.settings = settings)
+                    .or_insert(LinkCorruption {
+                        settings,
+                        corrupted: 0,
+                    });
+            }
+            Action::RemoveCorruption { from, to } => {
+                let link = find_link_key(state, from, to)?;
+                state.corruption.remove(&link);
+            }
+            Action::SetQueue { from, to, .. } => {
+                let link = find_link_key(state, from, to)?;
+                let settings = self.queue_settings()?;
+
+                state
+                    .queues
+                    .entry(link)
+                    .and_modify(|i| i.settings = settings)
+                    .or_insert_with(|| LinkQueue::new(settings));
+            }
+            Action::RemoveQueue { from, to } => {
+                let link = find_link_key(state, from, to)?;
+                if let Some(queue) = state.queues.remove(&link) {
+                    released.extend(queue.packets.into_iter().map(|i| i.frame));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn find_link(state: &State, a: &str, b: &str) -> Result<(Ipv4Addr, Ipv4Addr), String> {
+    match (state.find_node(a), state.find_node(b)) {
+        (Some(a), Some(b)) if a == b => Err("a link needs two different nodes".to_string()),
+        (Some(a), Some(b)) if a.group != b.group => {
+            Err("the nodes are in different groups".to_string())
+        }
+        (Some(a), Some(b)) => Ok((a.ip, b.ip)),
+        (None, _) => Err(format!("could not find node {}", a)),
+        (_, None) => Err(format!("could not find node {}", b)),
+    }
+}
+
+fn find_link_key(state: &State, from: &str, to: &str) -> Result<LinkKey, String> {
+    find_link(state, from, to).map(|(from, to)| LinkKey { from, to })
+}
+
+impl ScenarioStatus {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ScenarioStatus::Playing => "playing",
+            ScenarioStatus::Paused => "paused",
+            ScenarioStatus::Finished => "finished",
+        }
+    }
+}
+
+impl Baseline {
+    fn new(state: &State) -> Self {
+        Self {
+            on: state.on,
+            order: state.nodes.iter().map(|i| i.ip).collect(),
+            links: state.links.clone(),
+            mtus: state.mtus.clone(),
+            corruption: state.corruption.clone(),
+            queues: state
+                .queues
+                .iter()
+                .map(|(link, queue)| (*link, queue.settings))
+                .collect(),
+        }
+    }
+
+    /// Puts the router back how it was, returning the frames waiting in queues which were removed
+    fn restore(&self, state: &mut State) -> Vec<Vec<u8>> {
+        state.on = self.on;
+        state.links = self.links.clone();
+        state.mtus = self.mtus.clone();
+        state.corruption = self.corruption.clone();
+
+        // Queues which are kept or restored keep the packets waiting in them
+        let mut released = vec![];
+        let queues = &self.queues;
+        let removed = state
+            .queues
+            .keys()
+            .filter(|i| !queues.contains_key(i))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for link in removed {
+            let queue = state.queues.remove(&link).unwrap();
+            released.extend(queue.packets.into_iter().map(|i| i.frame));
+        }
+
+        for (link, settings) in queues.iter() {
+            state
+                .queues
+                .entry(*link)
+                .and_modify(|i| i.settings = *settings)
+                .or_insert_with(|| LinkQueue::new(*settings));
+        }
+
+        // Nodes which joined since the scenario was loaded stay at the end of the chain
+        let order = &self.order;
+        state
+            .nodes
+            .sort_by_key(|n| order.iter().position(|i| *i == n.ip).unwrap_or(order.len()));
+
+        released
+    }
+}
+
+impl ScenarioRun {
+    /// Loads the scenario, remembering the router's current state for resets
+    pub fn new(state: &State, scenario: Scenario) -> Self {
+        Self {
+            steps: vec![StepStatus::Pending; scenario.steps.len()],
+            scenario,
+            status: ScenarioStatus::Paused,
+            played: Duration::from_secs(0),
+            playing_since: None,
+            resume_at: None,
+            baseline: Baseline::new(state),
+        }
+    }
+
+    /// Seconds played so far
+    pub fn elapsed(&self) -> f64 {
+        let playing = self
+            .playing_since
+            .map_or(Duration::from_secs(0), |i| i.elapsed());
+        (self.played + playing).as_secs_f64()
+    }
+
+    pub fn play(&mut self) {
+        if self.status == ScenarioStatus::Paused {
+            self.status = ScenarioStatus::Playing;
+            self.playing_since = Some(Instant::now());
+        }
+    }
+
+    pub fn pause(&mut self) {
+        if self.status == ScenarioStatus::Playing {
+            self.played += self.playing_since.take().unwrap().elapsed();
+            self.status = ScenarioStatus::Paused;
+        }
+    }
+}
+
+/// Restores the router to how it was when the scenario was loaded and rewinds it, returning
+/// the frames waiting in queues which were removed so they can be sent
+pub fn reset(state: &mut State) -> Vec<Vec<u8>> {
+    let run = match state.scenario.take() {
+        Some(run) => run,
+        None => return vec![],
+    };
+
+    let released = run.baseline.restore(state);
+    state.scenario = Some(ScenarioRun::new(state, run.scenario));
+    released
+}
+
+/// Sends frames which were waiting in removed queues rather than silently losing them
+pub fn send_released(state: &SharedState, frames: Vec<Vec<u8>>) {
+    for frame in frames {
+        let eth = EthernetPacket::owned(frame).unwrap();
+        state.send_event(Event::SendPacket(eth));
+    }
+}
+
+/// Reads the scenario file and loads it paused
+pub fn load_file(state: &SharedState, path: &str) -> Result<()> {
+    let scenario = Scenario::parse(&fs::read_to_string(path)?)?;
+
+    log::info!("loaded scenario {} from {}", scenario.name, path);
+    state.update(|s| s.scenario = Some(ScenarioRun::new(s, scenario)));
+    Ok(())
+}
+
+/// Runs the steps of the playing scenario as they become due until the router shuts down
+pub fn start(args: Args, state: SharedState) -> Result<()> {
+    if let Some(path) = args.scenario.as_ref() {
+        load_file(&state, path)?;
+    }
+
+    while state.running() {
+        let released = state.update(run_due_steps);
+        send_released(&state, released);
+        thread::sleep(TICK_INTERVAL);
+    }
+
+    Ok(())
+}
+
+/// Runs the steps which are due, returning the frames waiting in any queues they removed
+fn run_due_steps(state: &mut State) -> Vec<Vec<u8>> {
+    let mut run = match state.scenario.take() {
+        Some(run) => run,
+        None => return vec![],
+    };
+    let mut released = vec![];
+
+    if run.status == ScenarioStatus::Playing {
+        let elapsed = run.elapsed();
+
+        if run.resume_at.map_or(false, |i| i <= elapsed) {
+            log::info!("scenario resuming forwarding");
+            run.resume_at = None;
+            state.on = true;
+        }
+
+        for (step, status) in run.scenario.steps.iter().zip(run.steps.iter_mut()) {
+            if step.at > elapsed {
+                break;
+            }
+
+            if *status != StepStatus::Pending {
+                continue;
+            }
+
+            *status = match step.action.apply(state, &mut released) {
+                Ok(()) => {
+                    log::info!("scenario step at {}s: {}", step.at, step.action.describe());
+                    StepStatus::Done
+                }
+                Err(err) => {
+                    log::warn!("scenario step at {}s failed: {}", step.at, err);
+                    StepStatus::Failed(err)
+                }
+            };
+
+            if let Action::Pause { seconds } = step.action {
+                run.resume_at = Some(step.at + seconds);
+            }
+        }
+
+        let pending = run.steps.iter().any(|i| *i == StepStatus::Pending);
+        if !pending && run.resume_at.is_none() {
+            log::info!("scenario {} finished", run.scenario.name);
+            run.pause();
+            run.status = ScenarioStatus::Finished;
+        }
+    }
+
+    state.scenario = Some(run);
+    released
+}
@@ -0,0 +1,232 @@
+use std::{
+    fs::OpenOptions,
+    io::{BufWriter, Write},
+    net::Ipv4Addr,
+    sync::mpsc,
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::{
+    args::Args,
+    state::{Decision, PacketRecord, SharedState, State},
+};
+
+/// How often the router's state is compared against the last recorded state and the
+/// packets seen since are written
+const RECORD_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A change during the session, written as a line of json to the recording
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+enum SessionEvent {
+    /// The router started, anything recorded before no longer applies
+    Started,
+    Status {
+        on: bool,
+    },
+    /// A node registered or changed its name or group
+    NodeJoined {
+        name: String,
+        ip: String,
+        group: String,
+    },
+    NodeLeft {
+        ip: String,
+    },
+    /// The ips of every node in the new order of the chain
+    NodesReordered {
+        order: Vec<String>,
+    },
+    LinkAdded {
+        id: u64,
+        a: String,
+        b: String,
+    },
+    LinkRemoved {
+        id: u64,
+    },
+    Packet(PacketSummary),
+}
+
+#[derive(Serialize)]
+struct PacketSummary {
+    id: u64,
+    src_ip: String,
+    dst_ip: String,
+    src_node: Option<String>,
+    dst_node: Option<String>,
+    protocol: String,
+    decision: &'static str,
+    /// The next hop of forwarded packets or why others were dropped
+    detail: String,
+    info: String,
+    tags: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct Entry {
+    /// Milliseconds since the unix epoch
+    time: u64,
+    #[serde(flatten)]
+    event: SessionEvent,
+}
+
+/// The last recorded state of the router
+#[derive(Default)]
+struct Snapshot {
+    on: Option<bool>,
+    nodes: Vec<(Ipv4Addr, String, String)>,
+    links: Vec<u64>,
+}
+
+impl From<&PacketRecord> for PacketSummary {
+    fn from(p: &PacketRecord) -> Self {
+        Self {
+            id: p.id,
+            src_ip: p.src_ip.to_string(),
+            dst_ip: p.dst_ip.to_string(),
+            src_node: p.src_node.clone(),
+            dst_node: p.dst_node.clone(),
+            protocol: p.protocol.clone(),
+            decision: p.decision.name(),
+            detail: match &p.decision {
+                Decision::Forwarded { next_hop } => next_hop.clone(),
+                Decision::Dropped { reason } => reason.clone(),
+                Decision::Paused { breakpoint } => format!("breakpoint #{}", breakpoint),
+            },
+            info: p.info.clone(),
+            tags: p.tags.clone(),
+        }
+    }
+}
+
+impl Snapshot {
+    /// Returns the events which changed the state since the last snapshot, updating it
+    fn record(&mut self, state: &State) -> Vec<(SystemTime, SessionEvent)> {
+        let now = SystemTime::now();
+        let mut events = vec![];
+
+        if self.on != Some(state.on) {
+            self.on = Some(state.on);
+            events.push((now, SessionEvent::Status { on: state.on }));
+        }
+
+        let nodes = state
+            .nodes
+            .iter()
+            .map(|i| (i.ip, i.name.clone(), i.group.clone()))
+            .collect::<Vec<_>>();
+
+        // Nodes which join are added to the end of the chain, any other change is a reorder
+        let mut expected = vec![];
+        for (ip, _, _) in self.nodes.iter() {
+            if nodes.iter().any(|i| i.0 == *ip) {
+                expected.push(*ip);
+            } else {
+                events.push((now, SessionEvent::NodeLeft { ip: ip.to_string() }));
+            }
+        }
+
+        for (ip, name, group) in nodes.iter() {
+            match self.nodes.iter().find(|i| i.0 == *ip) {
+                Some(old) if old.1 == *name && old.2 == *group => continue,
+                Some(_) => {}
+                None => expected.push(*ip),
+            }
+
+            events.push((
+                now,
+                SessionEvent::NodeJoined {
+                    name: name.clone(),
+                    ip: ip.to_string(),
+                    group: group.clone(),
+                },
+            ));
+        }
+
+        if nodes.iter().map(|i| i.0).ne(expected.into_iter()) {
+            events.push((
+                now,
+                SessionEvent::NodesReordered {
+                    order: nodes.iter().map(|i| i.0.to_string()).collect(),
+                },
+            ));
+        }
+
+        self.nodes = nodes;
+
+        for id in self.links.iter() {
+            if !state.links.iter().any(|i| i.id == *id) {
+                events.push((now, SessionEvent::LinkRemoved { id: *id }));
+            }
+        }
+
+        for link in state.links.iter() {
+            if !self.links.contains(&link.id) {
+                events.push((
+                    now,
+                    SessionEvent::LinkAdded {
+                        id: link.id,
+                        a: link.a.to_string(),
+                        b: link.b.to_string(),
+                    },
+                ));
+            }
+        }
+
+        self.links = state.links.iter().map(|i| i.id).collect();
+
+        events
+    }
+}
+
+/// Appends every change to the router's state and each packet it sees to the
+/// recording file until the router shuts down
+pub fn start(args: Args, state: SharedState) -> Result<()> {
+    let path = match args.record {
+        Some(path) => path,
+        None => return Ok(()),
+    };
+
+    let file = OpenOptions::new().create(true).append(true).open(&path)?;
+    let mut file = BufWriter::new(file);
+
+    log::info!("recording session to {}", path);
+
+    let (tx, rx) = mpsc::channel();
+    state.update(|s| {
+        s.recording = Some(path);
+        s.session_packets = Some(tx);
+    });
+
+    let mut snapshot = Snapshot::default();
+    write(&mut file, SystemTime::now(), SessionEvent::Started)?;
+
+    while state.running() {
+        // Packets come first as they were seen before the state was last changed
+        for packet in rx.try_iter() {
+            let event = SessionEvent::Packet((&packet).into());
+            write(&mut file, packet.time, event)?;
+        }
+
+        for (time, event) in state.get(|s| snapshot.record(s)) {
+            write(&mut file, time, event)?;
+        }
+
+        file.flush()?;
+        thread::sleep(RECORD_INTERVAL);
+    }
+
+    Ok(())
+}
+
+fn write(file: &mut impl Write, time: SystemTime, event: SessionEvent) -> Result<()> {
+    let time = time.duration_since(UNIX_EPOCH)?.as_millis() as u64;
+    serde_json::to_writer(&mut *file, &Entry { time, event })?;
+    writeln!(file)?;
+    Ok(())
+}
@@ -1,19 +1,25 @@
 use std::{
+    collections::{BTreeMap, VecDeque},
     env,
     net::Ipv4Addr,
     sync::{
         atomic::{AtomicBool, Ordering},
+        mpsc::Sender,
         Arc, Mutex,
     },
-    time::SystemTime,
+    time::{Duration, Instant, SystemTime},
 };
 
 use pnet::util::MacAddr;
 
+use crate::{eth::event::Event, scenario::ScenarioRun};
+
 #[derive(Clone)]
 pub struct SharedState {
     term: Arc<AtomicBool>,
     state: Arc<Mutex<State>>,
+    /// Sends events to the ethernet forwarder's loop once it has started
+    events: Arc<Mutex<Option<Sender<Event>>>>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -22,12 +28,406 @@ pub struct Node {
     pub ip: Ipv4Addr,
     pub mac: Option<MacAddr>,
     pub created: SystemTime,
+    /// The group whose chain the node is part of
+    pub group: String,
+    /// Nodes only reach others in their vlan directly
+    pub vlan: u16,
+}
+
+/// One of the router's network interfaces, each with its own subnet of nodes
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Interface {
+    pub name: String,
+    pub mac: Option<MacAddr>,
+    pub ip: Ipv4Addr,
+    pub prefix: u8,
+    /// The node which packets to and from other subnets are routed through
+    pub gateway: Option<Ipv4Addr>,
+}
+
+/// The group nodes join when they do not choose one
+pub const DEFAULT_GROUP: &str = "default";
+
+/// An isolated chain of nodes, each group's chain follows the order of its nodes in `State::nodes`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Group {
+    pub on: bool,
+    /// The node which packets to and from other groups are routed through
+    pub gateway: Option<Ipv4Addr>,
+}
+
+/// Frames of nodes in the native vlan are sent and received without an 802.1Q tag
+pub const NATIVE_VLAN: u16 = 1;
+
+/// The largest vlan id, 4095 is reserved
+pub const MAX_VLAN: u16 = 4094;
+
+/// The settings of a vlan, vlans exist while nodes are assigned to them
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Vlan {
+    /// The node which packets to and from other vlans are routed through
+    pub gateway: Option<Ipv4Addr>,
+}
+
+/// How the central router moves packets between nodes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Packets are routed hop by hop along the chain of nodes
+    Routing,
+    /// A switch is simulated at each node and frames are switched along the chain of switches
+    Switching,
+}
+
+/// The ports of the switch simulated at each node
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SwitchPort {
+    /// The port the switch's own node is plugged into
+    Node,
+    /// The port linked to the switch of the node with the ip, which is
+    /// next to it in the chain or joined to it by an extra link
+    Switch(Ipv4Addr),
+}
+
+/// The port a mac address was last seen on by a switch
+#[derive(Clone, Copy, Debug)]
+pub struct MacEntry {
+    pub port: SwitchPort,
+    pub learned: Instant,
+}
+
+/// How long switches remember mac addresses they have not seen again
+pub const DEFAULT_MAC_AGING: Duration = Duration::from_secs(60);
+
+/// Whether the switches run the spanning tree protocol to block the loops made by extra links
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StpMode {
+    /// Frames are flooded around loops until they reach the hop limit
+    Off,
+    /// Ports listen and learn for the forward delay before forwarding frames
+    Stp,
+    /// Ports forward frames as soon as their role is agreed
+    Rstp,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PortRole {
+    /// The switch's port towards the root bridge
+    Root,
+    /// The port forwarding frames onto its link away from the root bridge
+    Designated,
+    /// A port blocked to break a loop
+    Alternate,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PortState {
+    Blocking,
+    Listening,
+    Learning,
+    Forwarding,
+}
+
+/// The spanning tree's view of a port between two switches
+#[derive(Clone, Copy, Debug)]
+pub struct StpPort {
+    pub role: PortRole,
+    pub state: PortState,
+    /// When the port entered its state
+    pub since: Instant,
+}
+
+/// A switch's place in the spanning tree
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Bridge {
+    pub root: Ipv4Addr,
+    /// The cost of the path to the root bridge
+    pub cost: u32,
+}
+
+/// The bridge priority of switches the instructor has not changed, lower priorities win the root election
+pub const DEFAULT_BRIDGE_PRIORITY: u16 = 32768;
+
+/// How long a node stays a member of a multicast group without reporting it again,
+/// the igmp group membership interval
+pub const MULTICAST_MEMBERSHIP: Duration = Duration::from_secs(260);
+
+/// A broadcast or multicast packet which has been propagated, identified by the same fields
+/// which tell the fragments of different packets apart
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct BroadcastKey {
+    pub src: Ipv4Addr,
+    pub dst: Ipv4Addr,
+    pub protocol: u8,
+    pub id: u16,
+}
+
+/// The maximum number of frames kept for the packet inspector
+const MAX_PACKET_RECORDS: usize = 500;
+
+/// The maximum number of packets which can be held at breakpoints at once
+const MAX_PAUSED_PACKETS: usize = 100;
+
+/// The smallest mtu every ipv4 host must support
+pub const MIN_MTU: u16 = 68;
+
+/// Bit errors can be introduced into at most this many bits of each packet
+pub const MAX_CORRUPTED_BITS: u32 = 64;
+
+/// The settings of a queue which are not given when it is added
+pub const DEFAULT_QUEUE_CAPACITY: usize = 20;
+pub const DEFAULT_QUEUE_RATE: f64 = 10.0;
+pub const DEFAULT_RED_MIN_THRESHOLD: f64 = 5.0;
+pub const DEFAULT_RED_MAX_THRESHOLD: f64 = 15.0;
+pub const DEFAULT_RED_MAX_PROBABILITY: f64 = 0.1;
+
+/// What the router did with a frame it received
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Decision {
+    Forwarded { next_hop: String },
+    Dropped { reason: String },
+    Paused { breakpoint: u64 },
+}
+
+/// A frame seen by the router along with its decoded headers, kept for the packet inspector
+#[derive(Clone, Debug)]
+pub struct PacketRecord {
+    pub id: u64,
+    pub time: SystemTime,
+    pub src_mac: MacAddr,
+    pub dst_mac: MacAddr,
+    pub src_ip: Ipv4Addr,
+    pub dst_ip: Ipv4Addr,
+    pub src_node: Option<String>,
+    pub dst_node: Option<String>,
+    pub protocol: String,
+    pub src_port: Option<u16>,
+    pub dst_port: Option<u16>,
+    pub ttl: u8,
+    pub length: usize,
+    /// Protocol specific details such as tcp flags or the icmp type
+    pub info: String,
+    pub decision: Decision,
+    /// Labels for frames which were not simply forwarded, eg "injected"
+    pub tags: Vec<String>,
+}
+
+/// Criteria which packets are matched against, empty criteria match every packet
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PacketFilter {
+    /// The name or ip of the node sending or receiving the packet
+    pub node: Option<String>,
+    pub protocol: Option<String>,
+    /// The source or destination port
+    pub port: Option<u16>,
+}
+
+/// The addresses, protocol and ports of a packet, used to match it against
+/// filters before it is known which frame it will be sent in
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Flow {
+    pub src_ip: Ipv4Addr,
+    pub dst_ip: Ipv4Addr,
+    pub protocol: String,
+    pub src_port: Option<u16>,
+    pub dst_port: Option<u16>,
+}
+
+/// How packets are routed from their source node to their destination node
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Path {
+    /// Hop along each adjacent node towards the destination
+    Chain,
+    /// Send straight to the destination
+    Direct,
+    /// Hop along each node in the opposite direction, wrapping around the ends of the chain
+    Reverse,
+    /// Hop along the fewest links, including the extra links, spreading flows across equal cost paths
+    Shortest,
+}
+
+/// How flows are spread across equal cost paths
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ecmp {
+    /// Packets of the same flow always take the same path
+    Hash,
+    /// Each packet takes the next path in turn
+    RoundRobin,
+}
+
+/// An extra link between two nodes alongside the links between adjacent nodes in the chain
+#[derive(Clone, Debug)]
+pub struct Link {
+    pub id: u64,
+    pub a: Ipv4Addr,
+    pub b: Ipv4Addr,
+}
+
+/// One of several equal cost next hops from a node towards a destination
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PathKey {
+    pub from: Ipv4Addr,
+    pub to: Ipv4Addr,
+    pub via: Ipv4Addr,
+}
+
+/// The direction of a link from a node to the next hop its packets are forwarded to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LinkKey {
+    pub from: Ipv4Addr,
+    pub to: Ipv4Addr,
+}
+
+/// How a link's queue decides which packets to drop and which to send next
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum QueueDiscipline {
+    /// Packets are sent in the order they arrive and dropped when the queue is full
+    TailDrop,
+    /// Random early detection, packets are dropped with a probability rising from zero
+    /// to `max_probability` as the average depth grows between the thresholds
+    Red {
+        min_threshold: f64,
+        max_threshold: f64,
+        max_probability: f64,
+    },
+    /// Packets with a higher dscp class selector are sent first
+    Priority,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QueueSettings {
+    pub discipline: QueueDiscipline,
+    /// The maximum number of packets waiting to be sent
+    pub capacity: usize,
+    /// Packets sent per second
+    pub rate: f64,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct QueueStats {
+    pub enqueued: u64,
+    pub sent: u64,
+    pub tail_drops: u64,
+    pub early_drops: u64,
+}
+
+#[derive(Clone, Debug)]
+pub struct QueuedPacket {
+    pub frame: Vec<u8>,
+    pub priority: u8,
+}
+
+/// A bounded output queue for the packets forwarded along a link
+#[derive(Clone, Debug)]
+pub struct LinkQueue {
+    pub settings: QueueSettings,
+    pub packets: VecDeque<QueuedPacket>,
+    /// The moving average of the depth used by red
+    pub average: f64,
+    /// How many packets can be sent when the queue is next serviced
+    pub credit: f64,
+    pub last_service: Instant,
+    pub stats: QueueStats,
+}
+
+/// The part of a packet whose bits may be flipped
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CorruptionTarget {
+    Header,
+    Payload,
+    /// Either the header or the payload
+    Any,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CorruptionSettings {
+    /// The probability of each packet being corrupted
+    pub rate: f64,
+    pub target: CorruptionTarget,
+    /// The number of bits flipped in each corrupted packet
+    pub bits: u32,
+    /// Recalculates the checksums after flipping bits so the corruption goes undetected
+    pub fix_checksums: bool,
+}
+
+/// Bit errors introduced into the packets sent along a link
+#[derive(Clone, Debug)]
+pub struct LinkCorruption {
+    pub settings: CorruptionSettings,
+    pub corrupted: u64,
+}
+
+/// Packets matching the filter take the route's path rather than the chain
+#[derive(Clone, Debug)]
+pub struct Route {
+    pub id: u64,
+    pub filter: PacketFilter,
+    pub path: Path,
+}
+
+/// Packets matching the filter are held until they are released by the instructor
+#[derive(Clone, Debug)]
+pub struct Breakpoint {
+    pub id: u64,
+    pub filter: PacketFilter,
+}
+
+/// A packet held at a breakpoint
+#[derive(Clone, Debug)]
+pub struct PausedPacket {
+    pub breakpoint: u64,
+    pub frame: Vec<u8>,
+    /// The packet as shown in the packet inspector, the ids are shared
+    pub record: PacketRecord,
 }
 
 #[derive(Clone, Debug)]
 pub struct State {
     pub on: bool,
+    pub mode: Mode,
+    /// The router's interfaces, once the ethernet forwarder has started
+    pub interfaces: Vec<Interface>,
     pub nodes: Vec<Node>,
+    pub groups: BTreeMap<String, Group>,
+    pub vlans: BTreeMap<u16, Vlan>,
+    pub packets: VecDeque<PacketRecord>,
+    next_packet_id: u64,
+    pub breakpoints: Vec<Breakpoint>,
+    next_breakpoint_id: u64,
+    pub paused: Vec<PausedPacket>,
+    /// Checked in order, the first route matching a packet picks its path
+    pub routes: Vec<Route>,
+    next_route_id: u64,
+    pub links: Vec<Link>,
+    next_link_id: u64,
+    pub ecmp: Ecmp,
+    /// The number of packets sent along each of the equal cost paths
+    pub path_counters: BTreeMap<PathKey, u64>,
+    pub queues: BTreeMap<LinkKey, LinkQueue>,
+    /// The largest packets which can be sent along each link without being fragmented
+    pub mtus: BTreeMap<LinkKey, u16>,
+    pub corruption: BTreeMap<LinkKey, LinkCorruption>,
+    /// The mac table of the switch simulated at each node
+    pub mac_tables: BTreeMap<Ipv4Addr, BTreeMap<MacAddr, MacEntry>>,
+    pub mac_aging: Duration,
+    pub stp: StpMode,
+    pub bridge_priorities: BTreeMap<Ipv4Addr, u16>,
+    /// The switch each node's switch sees as the root bridge, kept up to date by the spanning tree
+    pub bridges: BTreeMap<Ipv4Addr, Bridge>,
+    /// The ports between switches keyed by the switch and the switch at the other end
+    pub stp_ports: BTreeMap<(Ipv4Addr, Ipv4Addr), StpPort>,
+    /// The members of each multicast group by when they last reported it, learnt by igmp snooping
+    pub multicast_groups: BTreeMap<Ipv4Addr, BTreeMap<Ipv4Addr, Instant>>,
+    /// When off multicast packets are propagated to every node like broadcasts
+    pub igmp_snooping: bool,
+    /// When each recently propagated packet was last seen and the node it arrived from,
+    /// copies of them arriving from other nodes are dropped
+    pub broadcasts_seen: BTreeMap<BroadcastKey, (Instant, Ipv4Addr)>,
+    pub scenario: Option<ScenarioRun>,
+    /// The file the session is being recorded to
+    pub recording: Option<String>,
+    /// Receives every packet recorded for the packet inspector while the session is being
+    /// recorded, so none are missed once they fall out of the inspector
+    pub session_packets: Option<Sender<PacketRecord>>,
 }
 
 impl SharedState {
@@ -35,16 +435,17 @@ impl SharedState {
         Self {
             term: Arc::new(AtomicBool::new(false)),
             state: Arc::new(Mutex::new(State::new())),
+            events: Arc::new(Mutex::new(None)),
         }
     }
 
-    pub fn update<F>(&self, f: F)
+    pub fn update<F, R>(&self, f: F) -> R
     where
-        F: FnOnce(&mut State) -> (),
+        F: FnOnce(&mut State) -> R,
     {
         let mut state = self.state.lock().unwrap();
 
-        f(&mut *state);
+        f(&mut state)
     }
 
     pub fn get<F, R>(&self, f: F) -> R
@@ -53,7 +454,19 @@ impl SharedState {
     {
         let state = self.state.lock().unwrap();
 
-        f(&*state)
+        f(&state)
+    }
+
+    pub fn set_events(&self, tx: Sender<Event>) {
+        *self.events.lock().unwrap() = Some(tx);
+    }
+
+    /// Sends the event to the ethernet forwarder, returning false if it is not running
+    pub fn send_event(&self, event: Event) -> bool {
+        match self.events.lock().unwrap().as_ref() {
+            Some(tx) => tx.send(event).is_ok(),
+            None => false,
+        }
     }
 
     pub fn term_arc(&self) -> Arc<AtomicBool> {
@@ -65,11 +478,468 @@ impl SharedState {
     }
 }
 
+impl Default for SharedState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl State {
     fn new() -> Self {
         Self {
             on: env::var("FORWARDER_ON").map(|_| true).unwrap_or(false),
+            mode: Mode::Routing,
+            interfaces: Vec::new(),
             nodes: Vec::new(),
+            groups: BTreeMap::new(),
+            vlans: BTreeMap::new(),
+            packets: VecDeque::new(),
+            next_packet_id: 0,
+            breakpoints: Vec::new(),
+            next_breakpoint_id: 0,
+            paused: Vec::new(),
+            routes: Vec::new(),
+            next_route_id: 0,
+            links: Vec::new(),
+            next_link_id: 0,
+            ecmp: Ecmp::Hash,
+            path_counters: BTreeMap::new(),
+            queues: BTreeMap::new(),
+            mtus: BTreeMap::new(),
+            corruption: BTreeMap::new(),
+            mac_tables: BTreeMap::new(),
+            mac_aging: DEFAULT_MAC_AGING,
+            stp: StpMode::Rstp,
+            bridge_priorities: BTreeMap::new(),
+            bridges: BTreeMap::new(),
+            stp_ports: BTreeMap::new(),
+            multicast_groups: BTreeMap::new(),
+            igmp_snooping: true,
+            broadcasts_seen: BTreeMap::new(),
+            scenario: None,
+            recording: None,
+            session_packets: None,
+        }
+    }
+
+    /// Adds the packet to the packet inspector, returning its id
+    pub fn record_packet(&mut self, mut packet: PacketRecord) -> u64 {
+        let id = self.next_packet_id;
+        packet.id = id;
+        self.next_packet_id += 1;
+
+        if let Some(tx) = &self.session_packets {
+            if tx.send(packet.clone()).is_err() {
+                self.session_packets = None;
+            }
+        }
+
+        if self.packets.len() >= MAX_PACKET_RECORDS {
+            self.packets.pop_front();
         }
+
+        self.packets.push_back(packet);
+        id
+    }
+
+    pub fn add_breakpoint(&mut self, filter: PacketFilter) -> u64 {
+        let id = self.next_breakpoint_id;
+        self.next_breakpoint_id += 1;
+        self.breakpoints.push(Breakpoint { id, filter });
+        id
+    }
+
+    pub fn add_route(&mut self, filter: PacketFilter, path: Path) -> u64 {
+        let id = self.next_route_id;
+        self.next_route_id += 1;
+        self.routes.push(Route { id, filter, path });
+        id
+    }
+
+    /// Finds a registered node by its name or ip
+    pub fn find_node(&self, node: &str) -> Option<&Node> {
+        self.nodes
+            .iter()
+            .find(|i| i.name == node || i.ip.to_string() == node)
+    }
+
+    /// Returns the index of the interface whose subnet contains the ip
+    pub fn interface_index(&self, ip: Ipv4Addr) -> Option<usize> {
+        self.interfaces.iter().position(|i| i.contains(ip))
+    }
+
+    pub fn interface_for(&self, ip: Ipv4Addr) -> Option<&Interface> {
+        self.interface_index(ip).map(|i| &self.interfaces[i])
+    }
+
+    /// Returns the nodes in the group in the order of its chain
+    pub fn group_nodes(&self, group: &str) -> Vec<Node> {
+        self.nodes
+            .iter()
+            .filter(|i| i.group == group)
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the nodes in the same group, vlan and subnet as the node, in the order of their chain
+    pub fn chain(&self, node: &Node) -> Vec<Node> {
+        let subnet = self.interface_index(node.ip);
+        self.nodes
+            .iter()
+            .filter(|i| i.group == node.group && i.vlan == node.vlan)
+            .filter(|i| self.interface_index(i.ip) == subnet)
+            .cloned()
+            .collect()
+    }
+
+    pub fn bridge_priority(&self, ip: Ipv4Addr) -> u16 {
+        self.bridge_priorities
+            .get(&ip)
+            .copied()
+            .unwrap_or(DEFAULT_BRIDGE_PRIORITY)
+    }
+
+    /// Returns the group's settings, groups are created as nodes join them
+    pub fn group(&self, group: &str) -> Group {
+        self.groups.get(group).cloned().unwrap_or_default()
+    }
+
+    /// Returns the vlan's settings
+    pub fn vlan(&self, vlan: u16) -> Vlan {
+        self.vlans.get(&vlan).cloned().unwrap_or_default()
+    }
+
+    /// Returns the ids of the vlans with settings or nodes, always including the native vlan
+    pub fn vlan_ids(&self) -> Vec<u16> {
+        let mut ids = self
+            .vlans
+            .keys()
+            .copied()
+            .chain(self.nodes.iter().map(|i| i.vlan))
+            .chain(Some(NATIVE_VLAN))
+            .collect::<Vec<_>>();
+        ids.sort_unstable();
+        ids.dedup();
+        ids
+    }
+
+    /// Returns the nodes which have reported membership of the multicast group recently
+    pub fn multicast_members(&self, group: Ipv4Addr) -> Vec<Ipv4Addr> {
+        self.multicast_groups
+            .get(&group)
+            .into_iter()
+            .flatten()
+            .filter(|(_, reported)| reported.elapsed() < MULTICAST_MEMBERSHIP)
+            .map(|(ip, _)| *ip)
+            .collect()
+    }
+
+    /// Returns the name of the registered node with the ip
+    pub fn node_name(&self, ip: Ipv4Addr) -> Option<String> {
+        self.nodes.iter().find(|i| i.ip == ip).map(|i| i.name.clone())
+    }
+
+    pub fn add_link(&mut self, a: Ipv4Addr, b: Ipv4Addr) -> u64 {
+        let id = self.next_link_id;
+        self.next_link_id += 1;
+        self.links.push(Link { id, a, b });
+        id
+    }
+
+    /// Returns the path of the first route matching the flow. Other flows hop along the chain
+    /// until extra links are added, then take the shortest path spread across equal cost paths.
+    pub fn find_path(&self, flow: &Flow) -> Path {
+        let default = if self.links.is_empty() {
+            Path::Chain
+        } else {
+            Path::Shortest
+        };
+
+        self.routes
+            .iter()
+            .find(|i| i.filter.matches_flow(flow, &self.nodes))
+            .map_or(default, |i| i.path)
+    }
+
+    /// Returns false if too many packets are already paused
+    pub fn can_pause(&self) -> bool {
+        self.paused.len() < MAX_PAUSED_PACKETS
+    }
+}
+
+impl Interface {
+    /// Returns true if the ip is in the interface's subnet
+    pub fn contains(&self, ip: Ipv4Addr) -> bool {
+        let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+        u32::from(ip) & mask == u32::from(self.ip) & mask
+    }
+
+    /// Returns the directed broadcast address of the interface's subnet
+    pub fn broadcast(&self) -> Ipv4Addr {
+        let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+        Ipv4Addr::from(u32::from(self.ip) | !mask)
+    }
+}
+
+impl Group {
+    /// Group names are limited to letters, digits, dashes and underscores so they can be used in urls
+    pub fn is_valid_name(name: &str) -> bool {
+        !name.is_empty()
+            && name
+                .chars()
+                .all(|i| i.is_ascii_alphanumeric() || i == '-' || i == '_')
+    }
+}
+
+impl Default for Group {
+    fn default() -> Self {
+        Self {
+            on: true,
+            gateway: None,
+        }
+    }
+}
+
+impl Decision {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Decision::Forwarded { .. } => "forwarded",
+            Decision::Dropped { .. } => "dropped",
+            Decision::Paused { .. } => "paused",
+        }
+    }
+}
+
+impl LinkQueue {
+    pub fn new(settings: QueueSettings) -> Self {
+        Self {
+            settings,
+            packets: VecDeque::new(),
+            average: 0.0,
+            credit: 0.0,
+            last_service: Instant::now(),
+            stats: QueueStats::default(),
+        }
+    }
+}
+
+impl QueueDiscipline {
+    pub fn name(&self) -> &'static str {
+        match self {
+            QueueDiscipline::TailDrop => "tail-drop",
+            QueueDiscipline::Red { .. } => "red",
+            QueueDiscipline::Priority => "priority",
+        }
+    }
+
+    /// Parses the discipline from its name, the thresholds and probability are only used by red
+    pub fn from_name(
+        name: &str,
+        min_threshold: f64,
+        max_threshold: f64,
+        max_probability: f64,
+    ) -> Result<Self, String> {
+        match name.to_lowercase().as_str() {
+            "tail-drop" => Ok(QueueDiscipline::TailDrop),
+            "priority" => Ok(QueueDiscipline::Priority),
+            "red" => {
+                if min_threshold < 0.0 || min_threshold >= max_threshold {
+                    return Err("the min threshold must be below the max threshold".to_string());
+                }
+
+                if !(0.0..=1.0).contains(&max_probability) {
+                    return Err("the max probability must be between 0 and 1".to_string());
+                }
+
+                Ok(QueueDiscipline::Red {
+                    min_threshold,
+                    max_threshold,
+                    max_probability,
+                })
+            }
+            other => Err(format!("unknown queue discipline {}", other)),
+        }
+    }
+}
+
+impl QueueSettings {
+    pub fn new(discipline: QueueDiscipline, capacity: usize, rate: f64) -> Result<Self, String> {
+        if capacity == 0 || rate.is_nan() || rate <= 0.0 {
+            return Err("the capacity and rate must be above 0".to_string());
+        }
+
+        Ok(Self {
+            discipline,
+            capacity,
+            rate,
+        })
+    }
+}
+
+impl CorruptionTarget {
+    pub fn name(&self) -> &'static str {
+        match self {
+            CorruptionTarget::Header => "header",
+            CorruptionTarget::Payload => "payload",
+            CorruptionTarget::Any => "any",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "header" => Some(CorruptionTarget::Header),
+            "payload" => Some(CorruptionTarget::Payload),
+            "any" => Some(CorruptionTarget::Any),
+            _ => None,
+        }
+    }
+}
+
+impl Mode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mode::Routing => "routing",
+            Mode::Switching => "switching",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "routing" => Some(Mode::Routing),
+            "switching" => Some(Mode::Switching),
+            _ => None,
+        }
+    }
+}
+
+impl SwitchPort {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SwitchPort::Node => "node",
+            SwitchPort::Switch(_) => "switch",
+        }
+    }
+}
+
+impl StpMode {
+    pub fn name(&self) -> &'static str {
+        match self {
+            StpMode::Off => "off",
+            StpMode::Stp => "stp",
+            StpMode::Rstp => "rstp",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "off" => Some(StpMode::Off),
+            "stp" => Some(StpMode::Stp),
+            "rstp" => Some(StpMode::Rstp),
+            _ => None,
+        }
+    }
+}
+
+impl PortRole {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PortRole::Root => "root",
+            PortRole::Designated => "designated",
+            PortRole::Alternate => "alternate",
+        }
+    }
+}
+
+impl PortState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PortState::Blocking => "blocking",
+            PortState::Listening => "listening",
+            PortState::Learning => "learning",
+            PortState::Forwarding => "forwarding",
+        }
+    }
+}
+
+impl Ecmp {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Ecmp::Hash => "hash",
+            Ecmp::RoundRobin => "round-robin",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "hash" => Some(Ecmp::Hash),
+            "round-robin" => Some(Ecmp::RoundRobin),
+            _ => None,
+        }
+    }
+}
+
+impl Path {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Path::Chain => "chain",
+            Path::Direct => "direct",
+            Path::Reverse => "reverse",
+            Path::Shortest => "shortest",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "chain" => Some(Path::Chain),
+            "direct" => Some(Path::Direct),
+            "reverse" => Some(Path::Reverse),
+            "shortest" => Some(Path::Shortest),
+            _ => None,
+        }
+    }
+}
+
+impl PacketFilter {
+    pub fn matches(&self, p: &PacketRecord) -> bool {
+        let matches_node =
+            self.matches_node(&p.src_node, p.src_ip) || self.matches_node(&p.dst_node, p.dst_ip);
+
+        matches_node && self.matches_transport(&p.protocol, p.src_port, p.dst_port)
+    }
+
+    /// Matches the flow, naming its addresses from the nodes
+    pub fn matches_flow(&self, flow: &Flow, nodes: &[Node]) -> bool {
+        let name = |ip: Ipv4Addr| nodes.iter().find(|i| i.ip == ip).map(|i| i.name.clone());
+        let matches_node = self.matches_node(&name(flow.src_ip), flow.src_ip)
+            || self.matches_node(&name(flow.dst_ip), flow.dst_ip);
+
+        matches_node && self.matches_transport(&flow.protocol, flow.src_port, flow.dst_port)
+    }
+
+    fn matches_node(&self, name: &Option<String>, ip: Ipv4Addr) -> bool {
+        let node = match &self.node {
+            Some(node) => node,
+            None => return true,
+        };
+
+        name.as_deref()
+            .map_or(false, |i| i.eq_ignore_ascii_case(node))
+            || ip.to_string() == *node
+    }
+
+    fn matches_transport(&self, protocol: &str, src_port: Option<u16>, dst_port: Option<u16>) -> bool {
+        if let Some(filter) = &self.protocol {
+            if !protocol.eq_ignore_ascii_case(filter) {
+                return false;
+            }
+        }
+
+        if let Some(port) = self.port {
+            if src_port != Some(port) && dst_port != Some(port) {
+                return false;
+            }
+        }
+
+        true
     }
 }
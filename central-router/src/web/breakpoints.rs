@@ -0,0 +1,157 @@
+use serde::{Deserialize, Serialize};
+use warp::{filters::BoxedFilter, hyper::StatusCode, Filter, Reply};
+
+use crate::{
+    eth::event::Event,
+    state::{Breakpoint, PacketFilter, PausedPacket, SharedState},
+};
+
+use super::packets::PacketResponse;
+
+#[derive(Serialize)]
+struct BreakpointResponse {
+    id: u64,
+    node: Option<String>,
+    protocol: Option<String>,
+    port: Option<u16>,
+}
+
+/// Packets matching all of the given criteria are paused, at least one is required
+#[derive(Deserialize)]
+struct NewBreakpoint {
+    node: Option<String>,
+    protocol: Option<String>,
+    port: Option<u16>,
+}
+
+#[derive(Serialize)]
+struct PausedResponse {
+    breakpoint: u64,
+    packet: PacketResponse,
+    /// The whole ethernet frame as hex
+    frame: String,
+}
+
+#[derive(Deserialize)]
+struct ReleaseRequest {
+    /// Forwards the packet if true, otherwise it is dropped
+    forward: bool,
+}
+
+impl From<&Breakpoint> for BreakpointResponse {
+    fn from(b: &Breakpoint) -> Self {
+        Self {
+            id: b.id,
+            node: b.filter.node.clone(),
+            protocol: b.filter.protocol.clone(),
+            port: b.filter.port,
+        }
+    }
+}
+
+impl From<&PausedPacket> for PausedResponse {
+    fn from(p: &PausedPacket) -> Self {
+        Self {
+            breakpoint: p.breakpoint,
+            packet: PacketResponse::from(&p.record),
+            frame: p.frame.iter().map(|i| format!("{:02x}", i)).collect(),
+        }
+    }
+}
+
+pub fn get(state: SharedState) -> BoxedFilter<(impl Reply,)> {
+    warp::get()
+        .map(move || {
+            let breakpoints = state.get(|s| {
+                s.breakpoints
+                    .iter()
+                    .map(BreakpointResponse::from)
+                    .collect::<Vec<_>>()
+            });
+
+            warp::reply::json(&breakpoints)
+        })
+        .boxed()
+}
+
+pub fn post(state: SharedState) -> BoxedFilter<(impl Reply,)> {
+    warp::post()
+        .and(warp::body::json())
+        .map(move |b: NewBreakpoint| {
+            let filter = PacketFilter {
+                node: b.node.filter(|i| !i.is_empty()),
+                protocol: b.protocol.filter(|i| !i.is_empty()),
+                port: b.port,
+            };
+
+            // A breakpoint without criteria would pause every packet
+            if filter == PacketFilter::default() {
+                return StatusCode::BAD_REQUEST;
+            }
+
+            state.update(|s| {
+                if s.breakpoints.iter().all(|i| i.filter != filter) {
+                    let id = s.add_breakpoint(filter);
+                    log::info!("added breakpoint #{}", id);
+                }
+            });
+
+            StatusCode::OK
+        })
+        .boxed()
+}
+
+pub fn delete(state: SharedState) -> BoxedFilter<(impl Reply,)> {
+    warp::delete()
+        .and(warp::path::param())
+        .and(warp::path::end())
+        .map(move |id: u64| {
+            state.update(|s| {
+                s.breakpoints.retain(|i| i.id != id);
+            });
+
+            log::info!("removed breakpoint #{}", id);
+            StatusCode::OK
+        })
+        .boxed()
+}
+
+pub fn get_paused(state: SharedState) -> BoxedFilter<(impl Reply,)> {
+    warp::get()
+        .map(move || {
+            let paused = state.get(|s| {
+                s.paused
+                    .iter()
+                    .map(PausedResponse::from)
+                    .collect::<Vec<_>>()
+            });
+
+            warp::reply::json(&paused)
+        })
+        .boxed()
+}
+
+pub fn release(state: SharedState) -> BoxedFilter<(impl Reply,)> {
+    warp::post()
+        .and(warp::path::param())
+        .and(warp::path::end())
+        .and(warp::body::json())
+        .map(move |id: u64, req: ReleaseRequest| {
+            let paused = state.get(|s| s.paused.iter().any(|i| i.record.id == id));
+
+            if !paused {
+                return StatusCode::NOT_FOUND;
+            }
+
+            // The packet is sent from the ethernet thread which owns the interface
+            if !state.send_event(Event::ReleasePacket {
+                id,
+                forward: req.forward,
+            }) {
+                return StatusCode::SERVICE_UNAVAILABLE;
+            }
+
+            StatusCode::OK
+        })
+        .boxed()
+}
@@ -0,0 +1,137 @@
+use std::net::Ipv4Addr;
+
+use serde::{Deserialize, Serialize};
+use warp::{filters::BoxedFilter, hyper::StatusCode, Filter, Reply};
+
+use crate::state::{
+    CorruptionSettings, CorruptionTarget, LinkCorruption, LinkKey, SharedState, MAX_CORRUPTED_BITS,
+};
+
+use super::topology::node_name;
+
+#[derive(Serialize)]
+struct CorruptionResponse {
+    from: String,
+    to: String,
+    from_name: String,
+    to_name: String,
+    rate: f64,
+    target: &'static str,
+    bits: u32,
+    fix_checksums: bool,
+    corrupted: u64,
+}
+
+/// Introduces bit errors on the link from one node to another by their names or ips
+#[derive(Deserialize)]
+struct NewCorruption {
+    from: String,
+    to: String,
+    /// The probability of each packet being corrupted, from 0 to 1
+    rate: f64,
+    /// One of "header", "payload" or "any"
+    #[serde(default = "default_target")]
+    target: String,
+    #[serde(default = "default_bits")]
+    bits: u32,
+    #[serde(default)]
+    fix_checksums: bool,
+}
+
+fn default_target() -> String {
+    "any".to_string()
+}
+
+fn default_bits() -> u32 {
+    1
+}
+
+pub fn get(state: SharedState) -> BoxedFilter<(impl Reply,)> {
+    warp::get()
+        .map(move || {
+            let corruption = state.get(|s| {
+                s.corruption
+                    .iter()
+                    .map(|(link, c)| CorruptionResponse {
+                        from: link.from.to_string(),
+                        to: link.to.to_string(),
+                        from_name: node_name(s, link.from),
+                        to_name: node_name(s, link.to),
+                        rate: c.settings.rate,
+                        target: c.settings.target.name(),
+                        bits: c.settings.bits,
+                        fix_checksums: c.settings.fix_checksums,
+                        corrupted: c.corrupted,
+                    })
+                    .collect::<Vec<_>>()
+            });
+
+            warp::reply::json(&corruption)
+        })
+        .boxed()
+}
+
+pub fn post(state: SharedState) -> BoxedFilter<(impl Reply,)> {
+    warp::post()
+        .and(warp::body::json())
+        .map(move |c: NewCorruption| {
+            let target = match CorruptionTarget::from_name(&c.target) {
+                Some(target) => target,
+                None => return StatusCode::BAD_REQUEST,
+            };
+
+            if !(0.0..=1.0).contains(&c.rate) || c.bits == 0 || c.bits > MAX_CORRUPTED_BITS {
+                return StatusCode::BAD_REQUEST;
+            }
+
+            let settings = CorruptionSettings {
+                rate: c.rate,
+                target,
+                bits: c.bits,
+                fix_checksums: c.fix_checksums,
+            };
+
+            state.update(|s| {
+                let link = match (s.find_node(&c.from), s.find_node(&c.to)) {
+                    (Some(from), Some(to)) if from != to => LinkKey {
+                        from: from.ip,
+                        to: to.ip,
+                    },
+                    _ => return StatusCode::BAD_REQUEST,
+                };
+
+                s.corruption
+                    .entry(link)
+                    .and_modify(|i| i.settings = settings)
+                    .or_insert(LinkCorruption {
+                        settings,
+                        corrupted: 0,
+                    });
+
+                log::info!(
+                    "set corruption from {} to {} at rate {}",
+                    link.from,
+                    link.to,
+                    settings.rate
+                );
+                StatusCode::OK
+            })
+        })
+        .boxed()
+}
+
+pub fn delete(state: SharedState) -> BoxedFilter<(impl Reply,)> {
+    warp::delete()
+        .and(warp::path::param())
+        .and(warp::path::param())
+        .and(warp::path::end())
+        .map(move |from: Ipv4Addr, to: Ipv4Addr| {
+            state.update(|s| {
+                s.corruption.remove(&LinkKey { from, to });
+            });
+
+            log::info!("removed corruption from {} to {}", from, to);
+            StatusCode::OK
+        })
+        .boxed()
+}
@@ -0,0 +1,96 @@
+use serde::{Deserialize, Serialize};
+use warp::{filters::BoxedFilter, hyper::StatusCode, Filter, Reply};
+
+use crate::state::{Group, SharedState};
+
+use super::topology::node_name;
+
+#[derive(Serialize)]
+struct GroupResponse {
+    name: String,
+    on: bool,
+    gateway: Option<String>,
+    gateway_name: Option<String>,
+    /// Names of the nodes in the order of the group's chain
+    nodes: Vec<String>,
+}
+
+/// Creates or updates the group, leaving out fields keeps their current values
+#[derive(Deserialize)]
+struct GroupRequest {
+    name: String,
+    on: Option<bool>,
+    /// The name or ip of a node in the group, or empty to remove the gateway
+    gateway: Option<String>,
+}
+
+pub fn get(state: SharedState) -> BoxedFilter<(impl Reply,)> {
+    warp::get()
+        .map(move || {
+            let groups = state.get(|s| {
+                s.groups
+                    .iter()
+                    .map(|(name, group)| GroupResponse {
+                        name: name.clone(),
+                        on: group.on,
+                        gateway: group.gateway.map(|i| i.to_string()),
+                        gateway_name: group.gateway.map(|i| node_name(s, i)),
+                        nodes: s.group_nodes(name).into_iter().map(|i| i.name).collect(),
+                    })
+                    .collect::<Vec<_>>()
+            });
+
+            warp::reply::json(&groups)
+        })
+        .boxed()
+}
+
+pub fn post(state: SharedState) -> BoxedFilter<(impl Reply,)> {
+    warp::post()
+        .and(warp::body::json())
+        .map(move |g: GroupRequest| {
+            let GroupRequest { name, on, gateway } = g;
+            if !Group::is_valid_name(&name) {
+                return StatusCode::BAD_REQUEST;
+            }
+
+            state.update(|s| {
+                let gateway = match gateway.as_deref() {
+                    None => s.group(&name).gateway,
+                    Some("") => None,
+                    Some(gateway) => match s.find_node(gateway) {
+                        Some(node) if node.group == name => Some(node.ip),
+                        _ => return StatusCode::BAD_REQUEST,
+                    },
+                };
+
+                let group = s.groups.entry(name.clone()).or_default();
+                group.on = on.unwrap_or(group.on);
+                group.gateway = gateway;
+
+                log::info!("updated group {} to {:?}", name, group);
+                StatusCode::OK
+            })
+        })
+        .boxed()
+}
+
+/// Removes a group once all of its nodes have left
+pub fn delete(state: SharedState) -> BoxedFilter<(impl Reply,)> {
+    warp::delete()
+        .and(warp::path::param())
+        .and(warp::path::end())
+        .map(move |name: String| {
+            state.update(|s| {
+                if s.nodes.iter().any(|i| i.group == name) {
+                    return StatusCode::BAD_REQUEST;
+                }
+
+                s.groups.remove(&name);
+
+                log::info!("removed group {}", name);
+                StatusCode::OK
+            })
+        })
+        .boxed()
+}
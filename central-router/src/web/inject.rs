@@ -0,0 +1,206 @@
+use std::net::Ipv4Addr;
+
+use pnet::{
+    packet::{ip::IpNextHeaderProtocols, tcp::TcpFlags},
+    util::MacAddr,
+};
+use serde::{Deserialize, Serialize};
+use warp::{filters::BoxedFilter, hyper::StatusCode, Filter, Reply};
+
+use crate::{
+    eth::{
+        self,
+        builder::{build_icmp, build_ipv4, build_tcp, build_udp, Ipv4Header, TcpHeader},
+    },
+    state::SharedState,
+};
+
+/// Keeps crafted packets within a standard ethernet frame
+const MAX_PAYLOAD_LEN: usize = 1400;
+
+/// A packet crafted in the web ui, only the nodes and protocol are required
+#[derive(Deserialize)]
+struct InjectRequest {
+    /// Name or ip of the node the packet is injected as if it was sent from
+    from: String,
+    /// Name or ip of the destination node
+    to: String,
+    src_mac: Option<String>,
+    dst_mac: Option<String>,
+    /// Source ip address, defaults to the ip of the node the packet is sent from
+    src_ip: Option<Ipv4Addr>,
+    #[serde(default = "default_ttl")]
+    ttl: u8,
+    #[serde(default)]
+    tos: u8,
+    #[serde(default)]
+    identification: u16,
+    #[serde(default)]
+    dont_fragment: bool,
+    /// One of "icmp", "udp" or "tcp"
+    protocol: String,
+    #[serde(default)]
+    src_port: u16,
+    #[serde(default)]
+    dst_port: u16,
+    #[serde(default = "default_icmp_type")]
+    icmp_type: u8,
+    #[serde(default)]
+    icmp_code: u8,
+    /// The tcp sequence number or the icmp echo sequence number
+    #[serde(default)]
+    seq: u32,
+    #[serde(default)]
+    ack: u32,
+    /// Tcp flag names, eg ["SYN", "ACK"]
+    #[serde(default)]
+    flags: Vec<String>,
+    #[serde(default = "default_window")]
+    window: u16,
+    #[serde(default)]
+    payload: String,
+}
+
+#[derive(Serialize)]
+struct InjectResponse {
+    next_hop: Option<String>,
+    error: Option<String>,
+}
+
+fn default_ttl() -> u8 {
+    64
+}
+
+fn default_icmp_type() -> u8 {
+    8
+}
+
+fn default_window() -> u16 {
+    64240
+}
+
+pub fn post(state: SharedState) -> BoxedFilter<(impl Reply,)> {
+    warp::post()
+        .and(warp::body::json())
+        .map(move |req: InjectRequest| {
+            let (status, res) = match inject(&state, req) {
+                Ok(next_hop) => (
+                    StatusCode::OK,
+                    InjectResponse {
+                        next_hop: Some(next_hop),
+                        error: None,
+                    },
+                ),
+                Err(err) => {
+                    log::warn!("failed to inject packet: {}", err);
+                    (
+                        StatusCode::BAD_REQUEST,
+                        InjectResponse {
+                            next_hop: None,
+                            error: Some(err),
+                        },
+                    )
+                }
+            };
+
+            warp::reply::with_status(warp::reply::json(&res), status)
+        })
+        .boxed()
+}
+
+fn inject(state: &SharedState, req: InjectRequest) -> Result<String, String> {
+    let from = find_node(state, &req.from)?;
+    let to = find_node(state, &req.to)?;
+    let src_ip = req.src_ip.unwrap_or(from);
+    let src_mac = parse_mac(&req.src_mac)?;
+    let dst_mac = parse_mac(&req.dst_mac)?;
+    let payload = req.payload.as_bytes();
+
+    if payload.len() > MAX_PAYLOAD_LEN {
+        return Err(format!("payload cannot be over {} bytes", MAX_PAYLOAD_LEN));
+    }
+
+    let (protocol, transport) = match req.protocol.to_lowercase().as_str() {
+        "icmp" => {
+            // Echo messages carry an identifier and sequence number in the rest of the header
+            let mut rest = [0u8; 4];
+            rest[2..].copy_from_slice(&(req.seq as u16).to_be_bytes());
+            (
+                IpNextHeaderProtocols::Icmp,
+                build_icmp(req.icmp_type, req.icmp_code, rest, payload),
+            )
+        }
+        "udp" => (
+            IpNextHeaderProtocols::Udp,
+            build_udp(src_ip, to, req.src_port, req.dst_port, payload),
+        ),
+        "tcp" => {
+            let header = TcpHeader {
+                src_port: req.src_port,
+                dst_port: req.dst_port,
+                sequence: req.seq,
+                acknowledgement: req.ack,
+                flags: parse_tcp_flags(&req.flags)?,
+                window: req.window,
+            };
+            (
+                IpNextHeaderProtocols::Tcp,
+                build_tcp(src_ip, to, &header, payload),
+            )
+        }
+        other => return Err(format!("unsupported protocol {}", other)),
+    };
+
+    let header = Ipv4Header {
+        src: src_ip,
+        dst: to,
+        ttl: req.ttl,
+        tos: req.tos,
+        identification: req.identification,
+        dont_fragment: req.dont_fragment,
+        protocol,
+    };
+
+    let ip = build_ipv4(&header, &transport);
+
+    eth::inject_packet(state, from, &ip, src_mac, dst_mac).map_err(|i| i.to_string())
+}
+
+/// Finds the ip of a node by its name, any ip address is also accepted
+fn find_node(state: &SharedState, node: &str) -> Result<Ipv4Addr, String> {
+    if let Ok(ip) = node.parse() {
+        return Ok(ip);
+    }
+
+    state
+        .get(|s| s.find_node(node).map(|i| i.ip))
+        .ok_or_else(|| format!("could not find node {}", node))
+}
+
+fn parse_mac(mac: &Option<String>) -> Result<Option<MacAddr>, String> {
+    match mac.as_deref() {
+        None | Some("") => Ok(None),
+        Some(mac) => mac
+            .parse()
+            .map(Some)
+            .map_err(|_| format!("invalid mac address {}", mac)),
+    }
+}
+
+fn parse_tcp_flags(flags: &[String]) -> Result<u16, String> {
+    flags.iter().try_fold(0, |acc, flag| {
+        let flag = match flag.to_uppercase().as_str() {
+            "FIN" => TcpFlags::FIN,
+            "SYN" => TcpFlags::SYN,
+            "RST" => TcpFlags::RST,
+            "PSH" => TcpFlags::PSH,
+            "ACK" => TcpFlags::ACK,
+            "URG" => TcpFlags::URG,
+            "ECE" => TcpFlags::ECE,
+            "CWR" => TcpFlags::CWR,
+            other => return Err(format!("unknown tcp flag {}", other)),
+        };
+
+        Ok(acc | flag)
+    })
+}
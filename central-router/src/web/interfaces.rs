@@ -0,0 +1,85 @@
+use serde::{Deserialize, Serialize};
+use warp::{filters::BoxedFilter, hyper::StatusCode, Filter, Reply};
+
+use crate::state::SharedState;
+
+use super::topology::node_name;
+
+#[derive(Serialize)]
+struct InterfaceResponse {
+    name: String,
+    mac: Option<String>,
+    ip: String,
+    prefix: u8,
+    gateway: Option<String>,
+    gateway_name: Option<String>,
+    /// Names of the nodes in the interface's subnet
+    nodes: Vec<String>,
+}
+
+/// Sets the gateway of the interface's subnet
+#[derive(Deserialize)]
+struct GatewayRequest {
+    name: String,
+    /// The name or ip of a node in the subnet, or empty to remove the gateway
+    gateway: String,
+}
+
+pub fn get(state: SharedState) -> BoxedFilter<(impl Reply,)> {
+    warp::get()
+        .map(move || {
+            let interfaces = state.get(|s| {
+                s.interfaces
+                    .iter()
+                    .map(|i| InterfaceResponse {
+                        name: i.name.clone(),
+                        mac: i.mac.map(|i| i.to_string()),
+                        ip: i.ip.to_string(),
+                        prefix: i.prefix,
+                        gateway: i.gateway.map(|i| i.to_string()),
+                        gateway_name: i.gateway.map(|ip| node_name(s, ip)),
+                        nodes: s
+                            .nodes
+                            .iter()
+                            .filter(|n| i.contains(n.ip))
+                            .map(|n| n.name.clone())
+                            .collect(),
+                    })
+                    .collect::<Vec<_>>()
+            });
+
+            warp::reply::json(&interfaces)
+        })
+        .boxed()
+}
+
+pub fn post(state: SharedState) -> BoxedFilter<(impl Reply,)> {
+    warp::post()
+        .and(warp::body::json())
+        .map(move |g: GatewayRequest| {
+            state.update(|s| {
+                let gateway = match g.gateway.as_str() {
+                    "" => None,
+                    gateway => match s.find_node(gateway) {
+                        Some(node) => Some(node.ip),
+                        None => return StatusCode::BAD_REQUEST,
+                    },
+                };
+
+                let interface = match s.interfaces.iter_mut().find(|i| i.name == g.name) {
+                    Some(interface) => interface,
+                    None => return StatusCode::NOT_FOUND,
+                };
+
+                if !gateway.map_or(true, |ip| interface.contains(ip)) {
+                    return StatusCode::BAD_REQUEST;
+                }
+
+                interface.gateway = gateway;
+
+                log::info!("set gateway of {} to {:?}", interface.name, gateway);
+                StatusCode::OK
+            })
+        })
+        .boxed()
+}
@@ -1,6 +1,21 @@
+mod breakpoints;
+mod corruption;
+mod groups;
+mod inject;
+mod interfaces;
+mod mtus;
+mod multicast;
 mod nodes;
+mod packets;
+mod queues;
+mod routes;
+mod scenario;
+mod session;
 mod status;
+mod switching;
+mod topology;
 mod ui;
+mod vlans;
 
 use std::time::Duration;
 
@@ -35,7 +50,114 @@ async fn start_warp(args: Args, state: SharedState) -> Result<()> {
             .or(nodes::delete(state.clone())),
     );
 
-    warp::serve(api_status.or(api_nodes).or(ui::get()))
+    let api_groups = warp::path("api").and(warp::path("groups")).and(
+        warp::path::end()
+            .and(groups::get(state.clone()).or(groups::post(state.clone())))
+            .or(groups::delete(state.clone())),
+    );
+
+    let api_interfaces = warp::path!("api" / "interfaces")
+        .and(interfaces::get(state.clone()).or(interfaces::post(state.clone())));
+
+    let api_vlans = warp::path("api").and(warp::path("vlans")).and(
+        warp::path::end()
+            .and(
+                vlans::get(state.clone())
+                    .or(vlans::post(state.clone()))
+                    .or(vlans::put(state.clone())),
+            )
+            .or(vlans::delete(state.clone())),
+    );
+
+    let api_switching = warp::path!("api" / "switching")
+        .and(switching::get(state.clone()).or(switching::post(state.clone())));
+
+    let api_multicast = warp::path!("api" / "multicast")
+        .and(multicast::get(state.clone()).or(multicast::post(state.clone())));
+
+    let api_packets = warp::path!("api" / "packets").and(packets::get(state.clone()));
+
+    let api_inject = warp::path!("api" / "packets" / "inject").and(inject::post(state.clone()));
+
+    let api_breakpoints = warp::path("api").and(warp::path("breakpoints")).and(
+        warp::path::end()
+            .and(breakpoints::get(state.clone()).or(breakpoints::post(state.clone())))
+            .or(breakpoints::delete(state.clone())),
+    );
+
+    let api_paused = warp::path("api").and(warp::path("paused")).and(
+        warp::path::end()
+            .and(breakpoints::get_paused(state.clone()))
+            .or(breakpoints::release(state.clone())),
+    );
+
+    let api_routes = warp::path("api").and(warp::path("routes")).and(
+        warp::path::end()
+            .and(routes::get(state.clone()).or(routes::post(state.clone())))
+            .or(routes::delete(state.clone())),
+    );
+
+    let api_links = warp::path("api").and(warp::path("links")).and(
+        warp::path::end()
+            .and(topology::get_links(state.clone()).or(topology::post_link(state.clone())))
+            .or(topology::delete_link(state.clone())),
+    );
+
+    let api_ecmp = warp::path!("api" / "ecmp")
+        .and(topology::get_ecmp(state.clone()).or(topology::post_ecmp(state.clone())));
+
+    let api_queues = warp::path("api").and(warp::path("queues")).and(
+        warp::path::end()
+            .and(queues::get(state.clone()).or(queues::post(state.clone())))
+            .or(queues::delete(state.clone())),
+    );
+
+    let api_mtus = warp::path("api").and(warp::path("mtus")).and(
+        warp::path::end()
+            .and(mtus::get(state.clone()).or(mtus::post(state.clone())))
+            .or(mtus::delete(state.clone())),
+    );
+
+    let api_corruption = warp::path("api").and(warp::path("corruption")).and(
+        warp::path::end()
+            .and(corruption::get(state.clone()).or(corruption::post(state.clone())))
+            .or(corruption::delete(state.clone())),
+    );
+
+    let api_scenario = warp::path!("api" / "scenario").and(
+        scenario::get(state.clone())
+            .or(scenario::post(state.clone()))
+            .or(scenario::delete(state.clone())),
+    );
+
+    let api_scenario_control =
+        warp::path!("api" / "scenario" / "control").and(scenario::control(state.clone()));
+
+    let api_session = warp::path!("api" / "session").and(session::get(state.clone()));
+
+    warp::serve(
+        api_status
+            .or(api_nodes)
+            .or(api_groups)
+            .or(api_interfaces)
+            .or(api_vlans)
+            .or(api_switching)
+            .or(api_multicast)
+            .or(api_packets)
+            .or(api_inject)
+            .or(api_breakpoints)
+            .or(api_paused)
+            .or(api_routes)
+            .or(api_links)
+            .or(api_ecmp)
+            .or(api_queues)
+            .or(api_mtus)
+            .or(api_corruption)
+            .or(api_scenario)
+            .or(api_scenario_control)
+            .or(api_session)
+            .or(ui::get()),
+    )
         .run(([0, 0, 0, 0], args.port))
         .await;
 
@@ -0,0 +1,87 @@
+use std::net::Ipv4Addr;
+
+use serde::{Deserialize, Serialize};
+use warp::{filters::BoxedFilter, hyper::StatusCode, Filter, Reply};
+
+use crate::state::{LinkKey, SharedState, MIN_MTU};
+
+use super::topology::node_name;
+
+#[derive(Serialize)]
+struct MtuResponse {
+    from: String,
+    to: String,
+    from_name: String,
+    to_name: String,
+    mtu: u16,
+}
+
+/// Sets the mtu of the link from one node to another by their names or ips
+#[derive(Deserialize)]
+struct NewMtu {
+    from: String,
+    to: String,
+    mtu: u16,
+}
+
+pub fn get(state: SharedState) -> BoxedFilter<(impl Reply,)> {
+    warp::get()
+        .map(move || {
+            let mtus = state.get(|s| {
+                s.mtus
+                    .iter()
+                    .map(|(link, mtu)| MtuResponse {
+                        from: link.from.to_string(),
+                        to: link.to.to_string(),
+                        from_name: node_name(s, link.from),
+                        to_name: node_name(s, link.to),
+                        mtu: *mtu,
+                    })
+                    .collect::<Vec<_>>()
+            });
+
+            warp::reply::json(&mtus)
+        })
+        .boxed()
+}
+
+pub fn post(state: SharedState) -> BoxedFilter<(impl Reply,)> {
+    warp::post()
+        .and(warp::body::json())
+        .map(move |m: NewMtu| {
+            if m.mtu < MIN_MTU {
+                return StatusCode::BAD_REQUEST;
+            }
+
+            state.update(|s| {
+                let link = match (s.find_node(&m.from), s.find_node(&m.to)) {
+                    (Some(from), Some(to)) if from != to => LinkKey {
+                        from: from.ip,
+                        to: to.ip,
+                    },
+                    _ => return StatusCode::BAD_REQUEST,
+                };
+
+                log::info!("set mtu from {} to {} to {}", link.from, link.to, m.mtu);
+                s.mtus.insert(link, m.mtu);
+                StatusCode::OK
+            })
+        })
+        .boxed()
+}
+
+pub fn delete(state: SharedState) -> BoxedFilter<(impl Reply,)> {
+    warp::delete()
+        .and(warp::path::param())
+        .and(warp::path::param())
+        .and(warp::path::end())
+        .map(move |from: Ipv4Addr, to: Ipv4Addr| {
+            state.update(|s| {
+                s.mtus.remove(&LinkKey { from, to });
+            });
+
+            log::info!("removed mtu from {} to {}", from, to);
+            StatusCode::OK
+        })
+        .boxed()
+}
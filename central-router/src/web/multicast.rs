@@ -0,0 +1,93 @@
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+use warp::{filters::BoxedFilter, hyper::StatusCode, Filter, Reply};
+
+use crate::state::{SharedState, MULTICAST_MEMBERSHIP};
+
+use super::topology::node_name;
+
+#[derive(Serialize)]
+struct MulticastResponse {
+    /// When on, multicast packets only reach the members of their group
+    snooping: bool,
+    groups: Vec<GroupResponse>,
+}
+
+#[derive(Serialize)]
+struct GroupResponse {
+    group: String,
+    members: Vec<MemberResponse>,
+}
+
+#[derive(Serialize)]
+struct MemberResponse {
+    node: String,
+    name: String,
+    /// Seconds since the node last reported membership of the group
+    age: f64,
+}
+
+#[derive(Deserialize)]
+struct MulticastRequest {
+    snooping: Option<bool>,
+    /// Forgets the members of every group
+    #[serde(default)]
+    clear: bool,
+}
+
+pub fn get(state: SharedState) -> BoxedFilter<(impl Reply,)> {
+    warp::get()
+        .map(move || {
+            let now = Instant::now();
+
+            let multicast = state.get(|s| MulticastResponse {
+                snooping: s.igmp_snooping,
+                groups: s
+                    .multicast_groups
+                    .iter()
+                    .map(|(group, members)| GroupResponse {
+                        group: group.to_string(),
+                        members: members
+                            .iter()
+                            .filter(|(_, reported)| {
+                                now.duration_since(**reported) < MULTICAST_MEMBERSHIP
+                            })
+                            .map(|(ip, reported)| MemberResponse {
+                                node: ip.to_string(),
+                                name: node_name(s, *ip),
+                                age: now.duration_since(*reported).as_secs_f64(),
+                            })
+                            .collect(),
+                    })
+                    .filter(|i| !i.members.is_empty())
+                    .collect(),
+            });
+
+            warp::reply::json(&multicast)
+        })
+        .boxed()
+}
+
+pub fn post(state: SharedState) -> BoxedFilter<(impl Reply,)> {
+    warp::post()
+        .and(warp::body::json())
+        .map(move |r: MulticastRequest| {
+            state.update(|s| {
+                if let Some(snooping) = r.snooping {
+                    log::info!(
+                        "turned igmp snooping {}",
+                        if snooping { "on" } else { "off" }
+                    );
+                    s.igmp_snooping = snooping;
+                }
+
+                if r.clear {
+                    s.multicast_groups.clear();
+                }
+            });
+
+            StatusCode::OK
+        })
+        .boxed()
+}
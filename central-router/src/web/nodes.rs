@@ -6,7 +6,7 @@ use std::{
 use serde::{Deserialize, Serialize};
 use warp::{filters::BoxedFilter, hyper::StatusCode, Filter, Reply};
 
-use crate::state::{Node, SharedState};
+use crate::state::{Group, Node, SharedState, State, DEFAULT_GROUP, NATIVE_VLAN};
 
 #[derive(Serialize)]
 struct NodeResponse {
@@ -14,12 +14,18 @@ struct NodeResponse {
     ip: String,
     mac: Option<String>,
     created: SystemTime,
+    group: String,
+    vlan: u16,
+    /// The router interface on the node's subnet
+    interface: Option<String>,
     you: bool,
 }
 
 #[derive(Deserialize)]
 struct NewNode {
     name: String,
+    /// The group to join, nodes join the default group otherwise
+    group: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -35,6 +41,9 @@ impl From<&Node> for NodeResponse {
             ip: c.ip.to_string(),
             mac: c.mac.map(|i| i.to_string()),
             created: c.created,
+            group: c.group.clone(),
+            vlan: c.vlan,
+            interface: None,
             you: false,
         }
     }
@@ -57,6 +66,10 @@ pub fn post(state: SharedState) -> BoxedFilter<(impl Reply,)> {
                 None => return StatusCode::BAD_REQUEST,
             };
 
+            if !n.group.as_deref().map_or(true, Group::is_valid_name) {
+                return StatusCode::BAD_REQUEST;
+            }
+
             upsert_node(&state, n, ip);
             StatusCode::OK
         })
@@ -111,7 +124,7 @@ fn get_client_ip(addr: Option<SocketAddr>) -> Option<Ipv4Addr> {
         }
     }
 
-    return ip;
+    ip
 }
 
 fn get_nodes(state: &SharedState, client_addr: Option<SocketAddr>) -> Vec<NodeResponse> {
@@ -119,27 +132,42 @@ fn get_nodes(state: &SharedState, client_addr: Option<SocketAddr>) -> Vec<NodeRe
         .map(|i| i.to_string())
         .unwrap_or("".to_string());
 
-    state
-        .get(|s| s.nodes.clone())
-        .iter()
-        .map(NodeResponse::from)
-        .map(move |mut i| {
-            i.you = i.ip == client_ip;
-            i
-        })
-        .collect::<Vec<_>>()
+    state.get(|s| {
+        s.nodes
+            .iter()
+            .map(|n| {
+                let mut i = NodeResponse::from(n);
+                i.interface = s.interface_for(n.ip).map(|i| i.name.clone());
+                i.you = i.ip == client_ip;
+                i
+            })
+            .collect::<Vec<_>>()
+    })
 }
 
 fn upsert_node(state: &SharedState, n: NewNode, ip: Ipv4Addr) {
+    let NewNode { name, group } = n;
+    let group = group.unwrap_or_else(|| DEFAULT_GROUP.to_string());
+
     state.update(|state| {
-        if let Some(node) = state.nodes.iter_mut().filter(|i| i.ip == ip).next() {
-            node.name = n.name
+        state.groups.entry(group.clone()).or_default();
+
+        if let Some(node) = state.nodes.iter_mut().find(|i| i.ip == ip) {
+            node.name = name;
+
+            if node.group != group {
+                log::info!("moved node {} to group {}", ip, group);
+                node.group = group;
+                clear_gateway(state, ip);
+            }
         } else {
             let node = Node {
-                name: n.name.clone(),
+                name,
                 ip,
                 mac: None,
                 created: SystemTime::now(),
+                group,
+                vlan: NATIVE_VLAN,
             };
             log::info!("added node {:?}", node);
             state.nodes.push(node);
@@ -147,8 +175,32 @@ fn upsert_node(state: &SharedState, n: NewNode, ip: Ipv4Addr) {
     })
 }
 
-fn delete_node(state: &SharedState, ip: Ipv4Addr) -> () {
-    state.update(|s| s.nodes.retain(|i| i.ip != ip))
+fn delete_node(state: &SharedState, ip: Ipv4Addr) {
+    state.update(|s| {
+        s.nodes.retain(|i| i.ip != ip);
+        clear_gateway(s, ip);
+    })
+}
+
+/// Stops the node being the gateway of the group, vlan or subnet it has left
+fn clear_gateway(state: &mut State, ip: Ipv4Addr) {
+    for group in state.groups.values_mut() {
+        if group.gateway == Some(ip) {
+            group.gateway = None;
+        }
+    }
+
+    for (id, vlan) in state.vlans.iter_mut() {
+        if vlan.gateway == Some(ip) && !state.nodes.iter().any(|i| i.ip == ip && i.vlan == *id) {
+            vlan.gateway = None;
+        }
+    }
+
+    for interface in state.interfaces.iter_mut() {
+        if interface.gateway == Some(ip) && !state.nodes.iter().any(|i| i.ip == ip) {
+            interface.gateway = None;
+        }
+    }
 }
 
 fn reorder_node(state: &SharedState, req: ReorderRequest) {
@@ -0,0 +1,123 @@
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+use warp::{filters::BoxedFilter, Filter, Reply};
+
+use crate::state::{Decision, PacketFilter, PacketRecord, SharedState};
+
+const DEFAULT_LIMIT: usize = 100;
+
+#[derive(Serialize)]
+pub struct PacketResponse {
+    id: u64,
+    time: SystemTime,
+    src_mac: String,
+    dst_mac: String,
+    src_ip: String,
+    dst_ip: String,
+    src_node: Option<String>,
+    dst_node: Option<String>,
+    protocol: String,
+    src_port: Option<u16>,
+    dst_port: Option<u16>,
+    ttl: u8,
+    length: usize,
+    info: String,
+    decision: &'static str,
+    reason: Option<String>,
+    next_hop: Option<String>,
+    tags: Vec<String>,
+}
+
+/// Filters for the packet inspector, all of which are optional
+#[derive(Deserialize)]
+struct PacketQuery {
+    /// Only packets sent from or to the node with this name or ip
+    node: Option<String>,
+    protocol: Option<String>,
+    /// Only packets sent from or to this port
+    port: Option<u16>,
+    /// One of "forwarded", "dropped" or "paused"
+    decision: Option<String>,
+    /// Only packets recorded after the packet with this id
+    after: Option<u64>,
+    limit: Option<usize>,
+}
+
+impl From<&PacketRecord> for PacketResponse {
+    fn from(p: &PacketRecord) -> Self {
+        let (reason, next_hop) = match &p.decision {
+            Decision::Forwarded { next_hop } => (None, Some(next_hop.clone())),
+            Decision::Dropped { reason } => (Some(reason.clone()), None),
+            Decision::Paused { breakpoint } => (Some(format!("breakpoint #{}", breakpoint)), None),
+        };
+
+        Self {
+            id: p.id,
+            time: p.time,
+            src_mac: p.src_mac.to_string(),
+            dst_mac: p.dst_mac.to_string(),
+            src_ip: p.src_ip.to_string(),
+            dst_ip: p.dst_ip.to_string(),
+            src_node: p.src_node.clone(),
+            dst_node: p.dst_node.clone(),
+            protocol: p.protocol.clone(),
+            src_port: p.src_port,
+            dst_port: p.dst_port,
+            ttl: p.ttl,
+            length: p.length,
+            info: p.info.clone(),
+            decision: p.decision.name(),
+            reason,
+            next_hop,
+            tags: p.tags.clone(),
+        }
+    }
+}
+
+pub fn get(state: SharedState) -> BoxedFilter<(impl Reply,)> {
+    warp::get()
+        .and(warp::query::<PacketQuery>())
+        .map(move |q: PacketQuery| warp::reply::json(&get_packets(&state, q)))
+        .boxed()
+}
+
+/// Returns the most recent packets matching the query, oldest first
+fn get_packets(state: &SharedState, q: PacketQuery) -> Vec<PacketResponse> {
+    let limit = q.limit.unwrap_or(DEFAULT_LIMIT);
+
+    let mut packets = state.get(|s| {
+        s.packets
+            .iter()
+            .rev()
+            .filter(|p| matches_query(p, &q))
+            .take(limit)
+            .map(PacketResponse::from)
+            .collect::<Vec<_>>()
+    });
+
+    packets.reverse();
+    packets
+}
+
+fn matches_query(p: &PacketRecord, q: &PacketQuery) -> bool {
+    if let Some(after) = q.after {
+        if p.id <= after {
+            return false;
+        }
+    }
+
+    if let Some(decision) = &q.decision {
+        if !p.decision.name().eq_ignore_ascii_case(decision) {
+            return false;
+        }
+    }
+
+    let filter = PacketFilter {
+        node: q.node.clone(),
+        protocol: q.protocol.clone(),
+        port: q.port,
+    };
+
+    filter.matches(p)
+}
@@ -0,0 +1,199 @@
+use std::net::Ipv4Addr;
+
+use pnet::packet::ethernet::EthernetPacket;
+use serde::{Deserialize, Serialize};
+use warp::{filters::BoxedFilter, hyper::StatusCode, Filter, Reply};
+
+use crate::{
+    eth::event::Event,
+    state::{
+        LinkKey, LinkQueue, QueueDiscipline, QueueSettings, SharedState, State,
+        DEFAULT_QUEUE_CAPACITY, DEFAULT_QUEUE_RATE, DEFAULT_RED_MAX_PROBABILITY,
+        DEFAULT_RED_MAX_THRESHOLD, DEFAULT_RED_MIN_THRESHOLD,
+    },
+};
+
+use super::topology::node_name;
+
+#[derive(Serialize)]
+struct QueueResponse {
+    from: String,
+    to: String,
+    from_name: String,
+    to_name: String,
+    discipline: &'static str,
+    capacity: usize,
+    rate: f64,
+    min_threshold: Option<f64>,
+    max_threshold: Option<f64>,
+    max_probability: Option<f64>,
+    depth: usize,
+    average: f64,
+    enqueued: u64,
+    sent: u64,
+    tail_drops: u64,
+    early_drops: u64,
+}
+
+/// Adds or replaces the queue on the link from one node to another, packets
+/// already waiting in a replaced queue are kept
+#[derive(Deserialize)]
+struct NewQueue {
+    /// Names or ips of the nodes at each end of the link
+    from: String,
+    to: String,
+    /// One of "tail-drop", "red" or "priority"
+    discipline: String,
+    #[serde(default = "default_capacity")]
+    capacity: usize,
+    #[serde(default = "default_rate")]
+    rate: f64,
+    #[serde(default = "default_min_threshold")]
+    min_threshold: f64,
+    #[serde(default = "default_max_threshold")]
+    max_threshold: f64,
+    #[serde(default = "default_max_probability")]
+    max_probability: f64,
+}
+
+fn default_capacity() -> usize {
+    DEFAULT_QUEUE_CAPACITY
+}
+
+fn default_rate() -> f64 {
+    DEFAULT_QUEUE_RATE
+}
+
+fn default_min_threshold() -> f64 {
+    DEFAULT_RED_MIN_THRESHOLD
+}
+
+fn default_max_threshold() -> f64 {
+    DEFAULT_RED_MAX_THRESHOLD
+}
+
+fn default_max_probability() -> f64 {
+    DEFAULT_RED_MAX_PROBABILITY
+}
+
+impl QueueResponse {
+    fn new(state: &State, link: &LinkKey, queue: &LinkQueue) -> Self {
+        let (min_threshold, max_threshold, max_probability) = match queue.settings.discipline {
+            QueueDiscipline::Red {
+                min_threshold,
+                max_threshold,
+                max_probability,
+            } => (
+                Some(min_threshold),
+                Some(max_threshold),
+                Some(max_probability),
+            ),
+            _ => (None, None, None),
+        };
+
+        Self {
+            from: link.from.to_string(),
+            to: link.to.to_string(),
+            from_name: node_name(state, link.from),
+            to_name: node_name(state, link.to),
+            discipline: queue.settings.discipline.name(),
+            capacity: queue.settings.capacity,
+            rate: queue.settings.rate,
+            min_threshold,
+            max_threshold,
+            max_probability,
+            depth: queue.packets.len(),
+            average: queue.average,
+            enqueued: queue.stats.enqueued,
+            sent: queue.stats.sent,
+            tail_drops: queue.stats.tail_drops,
+            early_drops: queue.stats.early_drops,
+        }
+    }
+}
+
+pub fn get(state: SharedState) -> BoxedFilter<(impl Reply,)> {
+    warp::get()
+        .map(move || {
+            let queues = state.get(|s| {
+                s.queues
+                    .iter()
+                    .map(|(link, queue)| QueueResponse::new(s, link, queue))
+                    .collect::<Vec<_>>()
+            });
+
+            warp::reply::json(&queues)
+        })
+        .boxed()
+}
+
+pub fn post(state: SharedState) -> BoxedFilter<(impl Reply,)> {
+    warp::post()
+        .and(warp::body::json())
+        .map(move |q: NewQueue| match add_queue(&state, q) {
+            Ok(()) => StatusCode::OK,
+            Err(err) => {
+                log::warn!("failed to add queue: {}", err);
+                StatusCode::BAD_REQUEST
+            }
+        })
+        .boxed()
+}
+
+pub fn delete(state: SharedState) -> BoxedFilter<(impl Reply,)> {
+    warp::delete()
+        .and(warp::path::param())
+        .and(warp::path::param())
+        .and(warp::path::end())
+        .map(move |from: Ipv4Addr, to: Ipv4Addr| {
+            let queue = state.update(|s| s.queues.remove(&LinkKey { from, to }));
+
+            let queue = match queue {
+                Some(queue) => queue,
+                None => return StatusCode::NOT_FOUND,
+            };
+
+            // Packets still waiting are sent rather than silently lost
+            for packet in queue.packets {
+                let eth = EthernetPacket::owned(packet.frame).unwrap();
+                state.send_event(Event::SendPacket(eth));
+            }
+
+            log::info!("removed queue from {} to {}", from, to);
+            StatusCode::OK
+        })
+        .boxed()
+}
+
+fn add_queue(state: &SharedState, q: NewQueue) -> Result<(), String> {
+    let discipline = QueueDiscipline::from_name(
+        &q.discipline,
+        q.min_threshold,
+        q.max_threshold,
+        q.max_probability,
+    )?;
+    let settings = QueueSettings::new(discipline, q.capacity, q.rate)?;
+
+    state.update(|s| {
+        let link = match (s.find_node(&q.from), s.find_node(&q.to)) {
+            (Some(from), Some(to)) if from != to => LinkKey {
+                from: from.ip,
+                to: to.ip,
+            },
+            _ => return Err("could not find both nodes".to_string()),
+        };
+
+        s.queues
+            .entry(link)
+            .and_modify(|i| i.settings = settings)
+            .or_insert_with(|| LinkQueue::new(settings));
+
+        log::info!(
+            "set {} queue from {} to {}",
+            discipline.name(),
+            link.from,
+            link.to
+        );
+        Ok(())
+    })
+}
@@ -0,0 +1,87 @@
+use serde::{Deserialize, Serialize};
+use warp::{filters::BoxedFilter, hyper::StatusCode, Filter, Reply};
+
+use crate::state::{PacketFilter, Path, Route, SharedState};
+
+#[derive(Serialize)]
+struct RouteResponse {
+    id: u64,
+    node: Option<String>,
+    protocol: Option<String>,
+    port: Option<u16>,
+    path: &'static str,
+}
+
+/// Packets matching all of the given criteria take the path, routes without
+/// criteria match every packet
+#[derive(Deserialize)]
+struct NewRoute {
+    node: Option<String>,
+    protocol: Option<String>,
+    port: Option<u16>,
+    /// One of "chain", "direct" or "reverse"
+    path: String,
+}
+
+impl From<&Route> for RouteResponse {
+    fn from(r: &Route) -> Self {
+        Self {
+            id: r.id,
+            node: r.filter.node.clone(),
+            protocol: r.filter.protocol.clone(),
+            port: r.filter.port,
+            path: r.path.name(),
+        }
+    }
+}
+
+pub fn get(state: SharedState) -> BoxedFilter<(impl Reply,)> {
+    warp::get()
+        .map(move || {
+            let routes =
+                state.get(|s| s.routes.iter().map(RouteResponse::from).collect::<Vec<_>>());
+
+            warp::reply::json(&routes)
+        })
+        .boxed()
+}
+
+pub fn post(state: SharedState) -> BoxedFilter<(impl Reply,)> {
+    warp::post()
+        .and(warp::body::json())
+        .map(move |r: NewRoute| {
+            let path = match Path::from_name(&r.path) {
+                Some(path) => path,
+                None => return StatusCode::BAD_REQUEST,
+            };
+
+            let filter = PacketFilter {
+                node: r.node.filter(|i| !i.is_empty()),
+                protocol: r.protocol.filter(|i| !i.is_empty()),
+                port: r.port,
+            };
+
+            state.update(|s| {
+                let id = s.add_route(filter, path);
+                log::info!("added route #{} along {} path", id, path.name());
+            });
+
+            StatusCode::OK
+        })
+        .boxed()
+}
+
+pub fn delete(state: SharedState) -> BoxedFilter<(impl Reply,)> {
+    warp::delete()
+        .and(warp::path::param())
+        .and(warp::path::end())
+        .map(move |id: u64| {
+            state.update(|s| {
+                s.routes.retain(|i| i.id != id);
+            });
+
+            log::info!("removed route #{}", id);
+            StatusCode::OK
+        })
+        .boxed()
+}
@@ -0,0 +1,150 @@
+use serde::{Deserialize, Serialize};
+use warp::{filters::BoxedFilter, hyper::StatusCode, Filter, Reply};
+
+use crate::{
+    scenario::{self, Scenario, ScenarioRun, StepStatus},
+    state::SharedState,
+};
+
+/// The largest scenario file which can be uploaded
+const MAX_SCENARIO_LEN: u64 = 64 * 1024;
+
+#[derive(Serialize)]
+struct ScenarioResponse {
+    name: String,
+    description: String,
+    status: &'static str,
+    elapsed: f64,
+    duration: f64,
+    steps: Vec<StepResponse>,
+}
+
+#[derive(Serialize)]
+struct StepResponse {
+    at: f64,
+    action: String,
+    /// One of "pending", "done" or "failed"
+    status: &'static str,
+    error: Option<String>,
+}
+
+/// Plays, pauses or resets the loaded scenario
+#[derive(Deserialize)]
+struct ControlRequest {
+    /// One of "play", "pause" or "reset"
+    command: String,
+}
+
+impl From<&ScenarioRun> for ScenarioResponse {
+    fn from(run: &ScenarioRun) -> Self {
+        Self {
+            name: run.scenario.name.clone(),
+            description: run.scenario.description.clone(),
+            status: run.status.name(),
+            elapsed: run.elapsed(),
+            duration: run.scenario.duration(),
+            steps: run
+                .scenario
+                .steps
+                .iter()
+                .zip(run.steps.iter())
+                .map(|(step, status)| StepResponse {
+                    at: step.at,
+                    action: step.action.describe(),
+                    status: match status {
+                        StepStatus::Pending => "pending",
+                        StepStatus::Done => "done",
+                        StepStatus::Failed(_) => "failed",
+                    },
+                    error: match status {
+                        StepStatus::Failed(err) => Some(err.clone()),
+                        _ => None,
+                    },
+                })
+                .collect(),
+        }
+    }
+}
+
+pub fn get(state: SharedState) -> BoxedFilter<(impl Reply,)> {
+    warp::get()
+        .map(move || {
+            let scenario = state.get(|s| s.scenario.as_ref().map(ScenarioResponse::from));
+
+            warp::reply::json(&scenario)
+        })
+        .boxed()
+}
+
+/// Loads the scenario from the toml in the request body and starts playing it
+pub fn post(state: SharedState) -> BoxedFilter<(impl Reply,)> {
+    warp::post()
+        .and(warp::body::content_length_limit(MAX_SCENARIO_LEN))
+        .and(warp::body::bytes())
+        .map(move |body: warp::hyper::body::Bytes| {
+            let scenario = match std::str::from_utf8(&body)
+                .map_err(anyhow::Error::from)
+                .and_then(Scenario::parse)
+            {
+                Ok(scenario) => scenario,
+                Err(err) => {
+                    log::warn!("failed to load scenario: {}", err);
+                    return warp::reply::with_status(err.to_string(), StatusCode::BAD_REQUEST);
+                }
+            };
+
+            log::info!("starting scenario {}", scenario.name);
+            state.update(|s| {
+                let mut run = ScenarioRun::new(s, scenario);
+                run.play();
+                s.scenario = Some(run);
+            });
+
+            warp::reply::with_status(String::new(), StatusCode::OK)
+        })
+        .boxed()
+}
+
+pub fn control(state: SharedState) -> BoxedFilter<(impl Reply,)> {
+    warp::post()
+        .and(warp::body::json())
+        .map(move |c: ControlRequest| {
+            let (status, released) = state.update(|s| {
+                if s.scenario.is_none() {
+                    return (StatusCode::NOT_FOUND, vec![]);
+                }
+
+                let released = match c.command.as_str() {
+                    "play" => {
+                        s.scenario.as_mut().unwrap().play();
+                        vec![]
+                    }
+                    "pause" => {
+                        s.scenario.as_mut().unwrap().pause();
+                        vec![]
+                    }
+                    "reset" => scenario::reset(s),
+                    _ => return (StatusCode::BAD_REQUEST, vec![]),
+                };
+
+                log::info!("scenario {}", c.command);
+                (StatusCode::OK, released)
+            });
+
+            scenario::send_released(&state, released);
+            status
+        })
+        .boxed()
+}
+
+/// Unloads the scenario, leaving the router as the scenario last changed it
+pub fn delete(state: SharedState) -> BoxedFilter<(impl Reply,)> {
+    warp::delete()
+        .map(move || {
+            state.update(|s| s.scenario = None);
+
+            log::info!("removed scenario");
+            StatusCode::OK
+        })
+        .boxed()
+}
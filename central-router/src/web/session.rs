@@ -0,0 +1,65 @@
+use std::fs::File;
+
+use tokio::io::AsyncReadExt;
+use warp::{
+    filters::BoxedFilter,
+    hyper::{body::Bytes, Body, Response, StatusCode},
+    Filter, Reply,
+};
+
+use crate::state::SharedState;
+
+/// The size of the chunks the recording is sent in
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Returns the recording of the session so far, one json event per line
+pub fn get(state: SharedState) -> BoxedFilter<(impl Reply,)> {
+    warp::get()
+        .map(move || {
+            let path = match state.get(|s| s.recording.clone()) {
+                Some(path) => path,
+                None => return StatusCode::NOT_FOUND.into_response(),
+            };
+
+            match File::open(&path) {
+                Ok(file) => Response::builder()
+                    .header("Content-Type", "application/x-ndjson")
+                    .body(stream(path, file))
+                    .into_response(),
+                Err(err) => {
+                    log::error!("failed to read recording {}: {}", path, err);
+                    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+                }
+            }
+        })
+        .boxed()
+}
+
+/// Sends the file in chunks as it is read, as a long session is too large to hold in memory
+fn stream(path: String, file: File) -> Body {
+    let (mut tx, body) = Body::channel();
+    let mut file = tokio::fs::File::from_std(file);
+
+    tokio::spawn(async move {
+        let mut buf = vec![0; CHUNK_SIZE];
+
+        loop {
+            match file.read(&mut buf).await {
+                Ok(0) => break,
+                Ok(len) => {
+                    let chunk = Bytes::copy_from_slice(&buf[..len]);
+                    if tx.send_data(chunk).await.is_err() {
+                        break;
+                    }
+                }
+                Err(err) => {
+                    log::error!("failed to read recording {}: {}", path, err);
+                    tx.abort();
+                    break;
+                }
+            }
+        }
+    });
+
+    body
+}
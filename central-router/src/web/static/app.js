@@ -6,8 +6,107 @@ const e = {
     footer: {
       container: document.querySelector("main table tfoot"),
       button: document.querySelector("main table tfoot button"),
+      group: document.querySelector("main table tfoot input[name=group]"),
     },
   },
+  groups: {
+    body: document.querySelector("main .groups table tbody"),
+  },
+  interfaces: {
+    body: document.querySelector("main .interfaces table tbody"),
+  },
+  vlans: {
+    form: document.querySelector("main .vlans form"),
+    node: document.querySelector("main .vlans select[name=node]"),
+    body: document.querySelector("main .vlans table tbody"),
+  },
+  switching: {
+    form: document.querySelector("main .switching form.settings"),
+    mode: document.querySelector("main .switching select[name=mode]"),
+    aging: document.querySelector("main .switching input[name=aging]"),
+    stp: document.querySelector("main .switching select[name=stp]"),
+    clear: document.querySelector("main .switching button.clear"),
+    body: document.querySelector("main .switching table.macs tbody"),
+    priority: document.querySelector("main .switching form.priority"),
+    node: document.querySelector("main .switching form.priority select[name=node]"),
+    bridges: document.querySelector("main .switching table.stp tbody"),
+  },
+  multicast: {
+    snooping: document.querySelector("main .multicast input[name=snooping]"),
+    clear: document.querySelector("main .multicast button.clear"),
+    body: document.querySelector("main .multicast table tbody"),
+  },
+  inject: {
+    form: document.querySelector("main .inject form"),
+    from: document.querySelector("main .inject select[name=from]"),
+    to: document.querySelector("main .inject select[name=to]"),
+    protocol: document.querySelector("main .inject select[name=protocol]"),
+    result: document.querySelector("main .inject .result"),
+  },
+  paused: document.querySelector("main .paused"),
+  scenario: {
+    container: document.querySelector("main .scenario"),
+    form: document.querySelector("main .scenario form"),
+    result: document.querySelector("main .scenario .result"),
+    summary: document.querySelector("main .scenario .summary"),
+    play: document.querySelector("main .scenario .play"),
+    reset: document.querySelector("main .scenario .reset"),
+    remove: document.querySelector("main .scenario .remove"),
+    body: document.querySelector("main .scenario table tbody"),
+  },
+  topology: {
+    links: document.querySelector("main .topology form.links"),
+    a: document.querySelector("main .topology select[name=a]"),
+    b: document.querySelector("main .topology select[name=b]"),
+    list: document.querySelector("main .topology ul"),
+    mode: document.querySelector("main .topology select[name=mode]"),
+    reset: document.querySelector("main .topology .reset"),
+    paths: document.querySelector("main .topology table tbody"),
+  },
+  replay: {
+    container: document.querySelector("main .replay"),
+    load: document.querySelector("main .replay .load"),
+    file: document.querySelector("main .replay input[name=file]"),
+    result: document.querySelector("main .replay .result"),
+    play: document.querySelector("main .replay .play"),
+    speed: document.querySelector("main .replay select[name=speed]"),
+    seek: document.querySelector("main .replay .seek"),
+    time: document.querySelector("main .replay .time"),
+    chain: document.querySelector("main .replay .chain"),
+    links: document.querySelector("main .replay .links"),
+    body: document.querySelector("main .replay table tbody"),
+  },
+  queues: {
+    form: document.querySelector("main .queues form"),
+    from: document.querySelector("main .queues select[name=from]"),
+    to: document.querySelector("main .queues select[name=to]"),
+    discipline: document.querySelector("main .queues select[name=discipline]"),
+    body: document.querySelector("main .queues table tbody"),
+  },
+  mtus: {
+    form: document.querySelector("main .mtus form"),
+    from: document.querySelector("main .mtus select[name=from]"),
+    to: document.querySelector("main .mtus select[name=to]"),
+    body: document.querySelector("main .mtus table tbody"),
+  },
+  corruption: {
+    form: document.querySelector("main .corruption form"),
+    from: document.querySelector("main .corruption select[name=from]"),
+    to: document.querySelector("main .corruption select[name=to]"),
+    body: document.querySelector("main .corruption table tbody"),
+  },
+  routes: {
+    form: document.querySelector("main .routes form"),
+    list: document.querySelector("main .routes ul"),
+  },
+  breakpoints: {
+    form: document.querySelector("main .breakpoints form"),
+    list: document.querySelector("main .breakpoints ul"),
+  },
+  packets: {
+    filters: document.querySelector("main .packets form"),
+    body: document.querySelector("main .packets table tbody"),
+  },
   status: {
     container: document.querySelector(".status"),
     button: document.querySelector(".status button"),
@@ -22,8 +121,28 @@ const s = {
   loading: false,
   status: false,
   nodes: [],
+  groups: [],
+  interfaces: [],
+  vlans: [],
+  switching: { mode: "routing", aging: 60, stp: "rstp", switches: [] },
+  multicast: { snooping: true, groups: [] },
+  packets: [],
+  links: [],
+  ecmp: { mode: "hash", paths: [] },
+  queues: [],
+  mtus: [],
+  corruption: [],
+  routes: [],
+  breakpoints: [],
+  paused: [],
+  scenario: null,
+  replay: { entries: [], position: 0, playing: false, timer: null },
 };
 
+// How often the replay advances and how long packets stay highlighted on the chain, in ms
+const REPLAY_INTERVAL = 100;
+const REPLAY_HIGHLIGHT = 1000;
+
 const run = () => {
   const refresh = () => {
     if (s.loading) {
@@ -33,7 +152,22 @@ const run = () => {
     s.loading = true;
     renderLoading();
     refreshNodes();
+    refreshGroups();
+    refreshInterfaces();
+    refreshVlans();
+    refreshSwitching();
+    refreshMulticast();
     refreshStatus();
+    refreshPackets();
+    refreshLinks();
+    refreshEcmp();
+    refreshQueues();
+    refreshMtus();
+    refreshCorruption();
+    refreshRoutes();
+    refreshBreakpoints();
+    refreshPaused();
+    refreshScenario();
     updateRefreshedAt();
     s.loading = false;
     renderLoading();
@@ -43,6 +177,39 @@ const run = () => {
 
   e.status.button.addEventListener("click", toggleStatus);
   e.nodes.footer.button.addEventListener("click", toggleRegistered);
+  e.packets.filters.addEventListener("input", refreshPackets);
+  e.inject.form.addEventListener("submit", injectPacket);
+  e.topology.links.addEventListener("submit", addLink);
+  e.topology.mode.addEventListener("change", () => updateEcmp({ mode: e.topology.mode.value }));
+  e.topology.reset.addEventListener("click", () => updateEcmp({ reset: true }));
+  e.queues.form.addEventListener("submit", setQueue);
+  e.queues.discipline.addEventListener("change", () => {
+    e.queues.form.dataset.discipline = e.queues.discipline.value;
+  });
+  e.vlans.form.addEventListener("submit", assignVlan);
+  e.switching.form.addEventListener("submit", updateSwitching);
+  e.switching.clear.addEventListener("click", () => postSwitching({ clear: true }));
+  e.switching.priority.addEventListener("submit", setBridgePriority);
+  e.multicast.snooping.addEventListener("change", () => postMulticast({ snooping: e.multicast.snooping.checked }));
+  e.multicast.clear.addEventListener("click", () => postMulticast({ clear: true }));
+  e.mtus.form.addEventListener("submit", setMtu);
+  e.corruption.form.addEventListener("submit", setCorruption);
+  e.routes.form.addEventListener("submit", addRoute);
+  e.breakpoints.form.addEventListener("submit", addBreakpoint);
+  e.replay.load.addEventListener("click", loadSession);
+  e.replay.file.addEventListener("change", () => e.replay.file.files[0].text().then(loadReplay));
+  e.replay.play.addEventListener("click", toggleReplay);
+  e.replay.seek.addEventListener("input", seekReplay);
+  e.scenario.form.addEventListener("submit", startScenario);
+  e.scenario.play.addEventListener("click", () =>
+    controlScenario(s.scenario && s.scenario.status === "playing" ? "pause" : "play")
+  );
+  e.scenario.reset.addEventListener("click", () => controlScenario("reset"));
+  e.scenario.remove.addEventListener("click", removeScenario);
+  e.inject.protocol.addEventListener("change", () => {
+    e.inject.form.dataset.protocol = e.inject.protocol.value;
+  });
+  e.packets.filters.addEventListener("submit", (ev) => ev.preventDefault());
 };
 
 const isRegistered = () => {
@@ -60,7 +227,7 @@ const join = (name) => {
   fetch("/api/nodes", {
     method: "POST",
     headers: { "Content-Type": "application/json" },
-    body: JSON.stringify({ name: name }),
+    body: JSON.stringify({ name: name, group: e.nodes.footer.group.value || null }),
   }).then(refreshNodes);
 };
 
@@ -78,6 +245,510 @@ const reorder = (curIndex, newIndex) => {
   }).then(refreshNodes);
 };
 
+const refreshPackets = () => {
+  const query = new URLSearchParams();
+  for (const [key, value] of new FormData(e.packets.filters)) {
+    if (value) {
+      query.set(key, value);
+    }
+  }
+
+  fetch(`/api/packets?${query}`)
+    .then((r) => r.json())
+    .then((r) => (s.packets = r.reverse()))
+    .then(renderPackets);
+};
+
+const injectPacket = (ev) => {
+  ev.preventDefault();
+
+  const data = new FormData(e.inject.form);
+  const numbers = ["ttl", "tos", "identification", "icmp_type", "icmp_code", "src_port", "dst_port", "seq", "ack"];
+  const packet = { flags: data.getAll("flags"), dont_fragment: data.has("dont_fragment") };
+
+  for (const [key, value] of data) {
+    if (key === "flags" || key === "dont_fragment" || value === "") {
+      continue;
+    }
+    packet[key] = numbers.includes(key) ? Number(value) : value;
+  }
+
+  fetch("/api/packets/inject", {
+    method: "POST",
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify(packet),
+  })
+    .then((r) => r.json())
+    .then((r) => {
+      e.inject.result.innerText = r.error ? `Failed: ${r.error}` : `Injected via ${r.next_hop}`;
+      e.inject.result.classList.toggle("error", !!r.error);
+    })
+    .then(refreshPackets);
+};
+
+const refreshLinks = () => {
+  fetch("/api/links")
+    .then((r) => r.json())
+    .then((r) => (s.links = r))
+    .then(renderLinks);
+};
+
+const addLink = (ev) => {
+  ev.preventDefault();
+
+  fetch("/api/links", {
+    method: "POST",
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify({ a: e.topology.a.value, b: e.topology.b.value }),
+  }).then(refreshLinks);
+};
+
+const removeLink = (id) => {
+  fetch(`/api/links/${id}`, {
+    method: "DELETE",
+  }).then(refreshLinks);
+};
+
+const refreshEcmp = () => {
+  fetch("/api/ecmp")
+    .then((r) => r.json())
+    .then((r) => (s.ecmp = r))
+    .then(renderEcmp);
+};
+
+const updateEcmp = (ecmp) => {
+  fetch("/api/ecmp", {
+    method: "POST",
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify(ecmp),
+  }).then(refreshEcmp);
+};
+
+const refreshQueues = () => {
+  fetch("/api/queues")
+    .then((r) => r.json())
+    .then((r) => (s.queues = r))
+    .then(renderQueues);
+};
+
+const setQueue = (ev) => {
+  ev.preventDefault();
+
+  const numbers = ["capacity", "rate", "min_threshold", "max_threshold", "max_probability"];
+  const queue = {};
+  for (const [key, value] of new FormData(e.queues.form)) {
+    queue[key] = numbers.includes(key) ? Number(value) : value;
+  }
+
+  fetch("/api/queues", {
+    method: "POST",
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify(queue),
+  }).then(refreshQueues);
+};
+
+const removeQueue = (from, to) => {
+  fetch(`/api/queues/${from}/${to}`, {
+    method: "DELETE",
+  }).then(refreshQueues);
+};
+
+const refreshMtus = () => {
+  fetch("/api/mtus")
+    .then((r) => r.json())
+    .then((r) => (s.mtus = r))
+    .then(renderMtus);
+};
+
+const setMtu = (ev) => {
+  ev.preventDefault();
+
+  const data = new FormData(e.mtus.form);
+  fetch("/api/mtus", {
+    method: "POST",
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify({ from: data.get("from"), to: data.get("to"), mtu: Number(data.get("mtu")) }),
+  }).then(refreshMtus);
+};
+
+const removeMtu = (from, to) => {
+  fetch(`/api/mtus/${from}/${to}`, {
+    method: "DELETE",
+  }).then(refreshMtus);
+};
+
+const refreshCorruption = () => {
+  fetch("/api/corruption")
+    .then((r) => r.json())
+    .then((r) => (s.corruption = r))
+    .then(renderCorruption);
+};
+
+const setCorruption = (ev) => {
+  ev.preventDefault();
+
+  const data = new FormData(e.corruption.form);
+  fetch("/api/corruption", {
+    method: "POST",
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify({
+      from: data.get("from"),
+      to: data.get("to"),
+      rate: Number(data.get("rate")),
+      target: data.get("target"),
+      bits: Number(data.get("bits")),
+      fix_checksums: data.has("fix_checksums"),
+    }),
+  }).then(refreshCorruption);
+};
+
+const removeCorruption = (from, to) => {
+  fetch(`/api/corruption/${from}/${to}`, {
+    method: "DELETE",
+  }).then(refreshCorruption);
+};
+
+const refreshRoutes = () => {
+  fetch("/api/routes")
+    .then((r) => r.json())
+    .then((r) => (s.routes = r))
+    .then(renderRoutes);
+};
+
+const addRoute = (ev) => {
+  ev.preventDefault();
+
+  const route = {};
+  for (const [key, value] of new FormData(e.routes.form)) {
+    if (value) {
+      route[key] = key === "port" ? Number(value) : value;
+    }
+  }
+
+  fetch("/api/routes", {
+    method: "POST",
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify(route),
+  }).then(refreshRoutes);
+};
+
+const removeRoute = (id) => {
+  fetch(`/api/routes/${id}`, {
+    method: "DELETE",
+  }).then(refreshRoutes);
+};
+
+const refreshBreakpoints = () => {
+  fetch("/api/breakpoints")
+    .then((r) => r.json())
+    .then((r) => (s.breakpoints = r))
+    .then(renderBreakpoints);
+};
+
+const addBreakpoint = (ev) => {
+  ev.preventDefault();
+
+  const breakpoint = {};
+  for (const [key, value] of new FormData(e.breakpoints.form)) {
+    if (value) {
+      breakpoint[key] = key === "port" ? Number(value) : value;
+    }
+  }
+
+  fetch("/api/breakpoints", {
+    method: "POST",
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify(breakpoint),
+  }).then(refreshBreakpoints);
+};
+
+const removeBreakpoint = (id) => {
+  fetch(`/api/breakpoints/${id}`, {
+    method: "DELETE",
+  }).then(refreshBreakpoints);
+};
+
+const refreshPaused = () => {
+  fetch("/api/paused")
+    .then((r) => r.json())
+    .then((r) => (s.paused = r))
+    .then(renderPaused);
+};
+
+const releasePacket = (id, forward) => {
+  fetch(`/api/paused/${id}`, {
+    method: "POST",
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify({ forward: forward }),
+  })
+    .then(refreshPaused)
+    .then(refreshPackets);
+};
+
+const refreshScenario = () => {
+  fetch("/api/scenario")
+    .then((r) => r.json())
+    .then((r) => (s.scenario = r))
+    .then(renderScenario);
+};
+
+const startScenario = (ev) => {
+  ev.preventDefault();
+
+  const file = new FormData(e.scenario.form).get("file");
+  file
+    .text()
+    .then((body) => fetch("/api/scenario", { method: "POST", body }))
+    .then((r) => r.text().then((error) => ({ ok: r.ok, error })))
+    .then((r) => {
+      e.scenario.result.innerText = r.ok ? "" : `Failed: ${r.error}`;
+      e.scenario.result.classList.toggle("error", !r.ok);
+    })
+    .then(refreshScenario);
+};
+
+const controlScenario = (command) => {
+  fetch("/api/scenario/control", {
+    method: "POST",
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify({ command }),
+  })
+    .then(refreshScenario)
+    .then(refreshStatus);
+};
+
+const removeScenario = () => {
+  fetch("/api/scenario", {
+    method: "DELETE",
+  }).then(refreshScenario);
+};
+
+const loadSession = () => {
+  fetch("/api/session")
+    .then((r) => (r.ok ? r.text() : Promise.reject("the router is not recording, start it with --record")))
+    .then(loadReplay)
+    .catch((err) => {
+      e.replay.result.innerText = `Failed: ${err}`;
+      e.replay.result.classList.add("error");
+    });
+};
+
+const loadReplay = (text) => {
+  const entries = text
+    .split("\n")
+    .filter((line) => line.trim())
+    .map((line) => JSON.parse(line))
+    .sort((a, b) => a.time - b.time);
+
+  pauseReplay();
+  s.replay.entries = entries;
+  s.replay.position = entries.length ? entries[0].time : 0;
+  e.replay.result.innerText = `Loaded ${entries.length} events`;
+  e.replay.result.classList.remove("error");
+  renderReplay();
+};
+
+const toggleReplay = () => {
+  if (s.replay.playing) {
+    pauseReplay();
+  } else {
+    s.replay.playing = true;
+    s.replay.timer = setInterval(() => {
+      const { entries } = s.replay;
+      s.replay.position += REPLAY_INTERVAL * Number(e.replay.speed.value);
+
+      if (!entries.length || s.replay.position >= entries[entries.length - 1].time) {
+        pauseReplay();
+      }
+
+      renderReplay();
+    }, REPLAY_INTERVAL);
+  }
+
+  renderReplay();
+};
+
+const pauseReplay = () => {
+  clearInterval(s.replay.timer);
+  s.replay.playing = false;
+};
+
+const seekReplay = () => {
+  const { entries } = s.replay;
+  if (!entries.length) {
+    return;
+  }
+
+  const start = entries[0].time;
+  const end = entries[entries.length - 1].time;
+  s.replay.position = start + ((end - start) * e.replay.seek.value) / 1000;
+  renderReplay();
+};
+
+// Rebuilds the state of the router at the time by applying each recorded event up to it
+const replayStateAt = (time) => {
+  let r = { on: false, nodes: [], links: {}, packets: [] };
+
+  for (const entry of s.replay.entries) {
+    if (entry.time > time) {
+      break;
+    }
+
+    switch (entry.type) {
+      case "started":
+        r = { on: false, nodes: [], links: {}, packets: [] };
+        break;
+      case "status":
+        r.on = entry.on;
+        break;
+      case "node-joined": {
+        const node = r.nodes.find((n) => n.ip === entry.ip);
+        if (node) {
+          node.name = entry.name;
+          node.group = entry.group;
+        } else {
+          r.nodes.push({ ip: entry.ip, name: entry.name, group: entry.group });
+        }
+        break;
+      }
+      case "node-left":
+        r.nodes = r.nodes.filter((n) => n.ip !== entry.ip);
+        break;
+      case "nodes-reordered":
+        r.nodes = entry.order.map((ip) => r.nodes.find((n) => n.ip === ip) || { ip, name: ip });
+        break;
+      case "link-added":
+        r.links[entry.id] = entry;
+        break;
+      case "link-removed":
+        delete r.links[entry.id];
+        break;
+      case "packet":
+        r.packets.push(entry);
+        if (r.packets.length > 10) {
+          r.packets.shift();
+        }
+        break;
+    }
+  }
+
+  return r;
+};
+
+const refreshGroups = () => {
+  fetch("/api/groups")
+    .then((r) => r.json())
+    .then((r) => (s.groups = r))
+    .then(renderGroups);
+};
+
+const updateGroup = (group) => {
+  fetch("/api/groups", {
+    method: "POST",
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify(group),
+  }).then(refreshGroups);
+};
+
+const removeGroup = (name) => {
+  fetch(`/api/groups/${name}`, {
+    method: "DELETE",
+  }).then(refreshGroups);
+};
+
+const refreshInterfaces = () => {
+  fetch("/api/interfaces")
+    .then((r) => r.json())
+    .then((r) => (s.interfaces = r))
+    .then(renderInterfaces);
+};
+
+const setInterfaceGateway = (name, gateway) => {
+  fetch("/api/interfaces", {
+    method: "POST",
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify({ name, gateway }),
+  }).then(refreshInterfaces);
+};
+
+const refreshVlans = () => {
+  fetch("/api/vlans")
+    .then((r) => r.json())
+    .then((r) => (s.vlans = r))
+    .then(renderVlans);
+};
+
+const setVlanGateway = (id, gateway) => {
+  fetch("/api/vlans", {
+    method: "POST",
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify({ id, gateway }),
+  }).then(refreshVlans);
+};
+
+const assignVlan = (ev) => {
+  ev.preventDefault();
+
+  const data = new FormData(e.vlans.form);
+  fetch("/api/vlans", {
+    method: "PUT",
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify({ node: data.get("node"), vlan: Number(data.get("vlan")) }),
+  })
+    .then(refreshNodes)
+    .then(refreshVlans);
+};
+
+const removeVlan = (id) => {
+  fetch(`/api/vlans/${id}`, {
+    method: "DELETE",
+  }).then(refreshVlans);
+};
+
+const refreshSwitching = () => {
+  fetch("/api/switching")
+    .then((r) => r.json())
+    .then((r) => (s.switching = r))
+    .then(renderSwitching);
+};
+
+const postSwitching = (body) => {
+  fetch("/api/switching", {
+    method: "POST",
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify(body),
+  }).then(refreshSwitching);
+};
+
+const updateSwitching = (ev) => {
+  ev.preventDefault();
+
+  const data = new FormData(e.switching.form);
+  postSwitching({ mode: data.get("mode"), aging: Number(data.get("aging")), stp: data.get("stp") });
+};
+
+const refreshMulticast = () => {
+  fetch("/api/multicast")
+    .then((r) => r.json())
+    .then((r) => (s.multicast = r))
+    .then(renderMulticast);
+};
+
+const postMulticast = (body) => {
+  fetch("/api/multicast", {
+    method: "POST",
+    headers: { "Content-Type": "application/json" },
+    body: JSON.stringify(body),
+  }).then(refreshMulticast);
+};
+
+const setBridgePriority = (ev) => {
+  ev.preventDefault();
+
+  const data = new FormData(e.switching.priority);
+  postSwitching({ node: data.get("node"), priority: Number(data.get("priority")) });
+};
+
 const refreshStatus = () => {
   fetch("/api/status")
     .then((r) => r.json())
@@ -119,6 +790,9 @@ const renderNodes = () => {
       (n, i) => `<tr class="${n.you ? "you" : ""}">
             <td>${i + 1}</td>
             <td>${escapeHtml(n.name.substring(0, 20))}</td>
+            <td>${escapeHtml(n.group)}</td>
+            <td>${escapeHtml(n.interface || "N/A")}</td>
+            <td>${n.vlan}</td>
             <td>${n.mac || 'N/A'}</td>
             <td>${n.ip}</td>
             <td>${new Date(n.created.secs_since_epoch * 1000).toISOString()}</td>
@@ -139,8 +813,478 @@ const renderNodes = () => {
   e.nodes.footer.button.innerHTML = isRegistered() ? "Unregister" : "Register";
 
   registerNodeHandlers()
+  renderInjectNodes();
+};
+
+const renderInjectNodes = () => {
+  for (const select of [e.inject.from, e.inject.to, e.vlans.node, e.switching.node, e.topology.a, e.topology.b, e.queues.from, e.queues.to, e.mtus.from, e.mtus.to, e.corruption.from, e.corruption.to]) {
+    const selected = select.value;
+    select.innerHTML = s.nodes
+      .map((n) => `<option value="${n.ip}">${escapeHtml(n.name.substring(0, 20))} (${n.ip})</option>`)
+      .join(`\n`);
+    if (s.nodes.some((n) => n.ip === selected)) {
+      select.value = selected;
+    }
+  }
+};
+
+const renderPackets = () => {
+  const endpoint = (node, ip, port) =>
+    `${node ? `${escapeHtml(node.substring(0, 20))} ` : ""}<span class="ip">${ip}${port === null ? "" : `:${port}`}</span>`;
+
+  let html = s.packets
+    .map(
+      (p) => `<tr class="${p.decision}">
+            <td>${p.id}</td>
+            <td>${new Date(p.time.secs_since_epoch * 1000).toLocaleTimeString()}</td>
+            <td>${endpoint(p.src_node, p.src_ip, p.src_port)}</td>
+            <td>${endpoint(p.dst_node, p.dst_ip, p.dst_port)}</td>
+            <td>${escapeHtml(p.protocol.toUpperCase())}</td>
+            <td>${p.ttl}</td>
+            <td>${escapeHtml(p.info)}</td>
+            <td>${p.decision === "forwarded" ? `via ${escapeHtml(p.next_hop)}` : `${p.decision}: ${escapeHtml(p.reason)}`}${p.tags.map((t) => ` <span class="tag">${escapeHtml(t)}</span>`).join("")}</td>
+        </tr>`
+    )
+    .join(`\n`);
+
+  if (!html) {
+    html = `<tr class="none"><td colspan="100">No packets have been seen</td></tr>`;
+  }
+
+  e.packets.body.innerHTML = html;
+};
+
+const describeFilter = (f) =>
+  [f.protocol && f.protocol.toUpperCase(), f.node && `node ${f.node}`, f.port !== null && `port ${f.port}`]
+    .filter((i) => i)
+    .join(", ") || "All packets";
+
+const renderLinks = () => {
+  e.topology.list.innerHTML = s.links
+    .map((l) => `<li>#${l.id} ${escapeHtml(l.a)} &harr; ${escapeHtml(l.b)} <button data-id="${l.id}">&times;</button></li>`)
+    .join(`\n`);
+
+  for (const button of e.topology.list.querySelectorAll("button")) {
+    button.addEventListener("click", () => removeLink(button.dataset.id));
+  }
+};
+
+const renderEcmp = () => {
+  if (document.activeElement !== e.topology.mode) {
+    e.topology.mode.value = s.ecmp.mode;
+  }
+
+  let html = s.ecmp.paths
+    .map(
+      (p) => `<tr>
+            <td>${escapeHtml(p.from)}</td>
+            <td>${escapeHtml(p.to)}</td>
+            <td>${escapeHtml(p.via)}</td>
+            <td>${p.packets}</td>
+        </tr>`
+    )
+    .join(`\n`);
+
+  if (!html) {
+    html = `<tr class="none"><td colspan="100">No packets have been load balanced</td></tr>`;
+  }
+
+  e.topology.paths.innerHTML = html;
+};
+
+const renderQueues = () => {
+  let html = s.queues
+    .map(
+      (q) => `<tr>
+            <td>${escapeHtml(q.from_name)} &rarr; ${escapeHtml(q.to_name)}</td>
+            <td>${q.discipline}${q.discipline === "red" ? ` (${q.min_threshold}-${q.max_threshold}, avg ${q.average.toFixed(1)})` : ""}</td>
+            <td>${q.rate} pps</td>
+            <td>
+                <progress max="${q.capacity}" value="${q.depth}"></progress>
+                ${q.depth}/${q.capacity}
+            </td>
+            <td>${q.sent}</td>
+            <td>${q.tail_drops}</td>
+            <td>${q.early_drops}</td>
+            <td><button data-from="${q.from}" data-to="${q.to}">&times;</button></td>
+        </tr>`
+    )
+    .join(`\n`);
+
+  if (!html) {
+    html = `<tr class="none"><td colspan="100">No links have queues</td></tr>`;
+  }
+
+  e.queues.body.innerHTML = html;
+
+  for (const button of e.queues.body.querySelectorAll("button")) {
+    button.addEventListener("click", () => removeQueue(button.dataset.from, button.dataset.to));
+  }
+};
+
+const renderMtus = () => {
+  let html = s.mtus
+    .map(
+      (m) => `<tr>
+            <td>${escapeHtml(m.from_name)} &rarr; ${escapeHtml(m.to_name)}</td>
+            <td>${m.mtu}</td>
+            <td><button data-from="${m.from}" data-to="${m.to}">&times;</button></td>
+        </tr>`
+    )
+    .join(`\n`);
+
+  if (!html) {
+    html = `<tr class="none"><td colspan="100">All links carry packets of any size</td></tr>`;
+  }
+
+  e.mtus.body.innerHTML = html;
+
+  for (const button of e.mtus.body.querySelectorAll("button")) {
+    button.addEventListener("click", () => removeMtu(button.dataset.from, button.dataset.to));
+  }
+};
+
+const renderCorruption = () => {
+  let html = s.corruption
+    .map(
+      (c) => `<tr>
+            <td>${escapeHtml(c.from_name)} &rarr; ${escapeHtml(c.to_name)}</td>
+            <td>${c.rate * 100}%</td>
+            <td>${c.target}</td>
+            <td>${c.bits}</td>
+            <td>${c.fix_checksums ? "fixed" : "left bad"}</td>
+            <td>${c.corrupted}</td>
+            <td><button data-from="${c.from}" data-to="${c.to}">&times;</button></td>
+        </tr>`
+    )
+    .join(`\n`);
+
+  if (!html) {
+    html = `<tr class="none"><td colspan="100">No links corrupt packets</td></tr>`;
+  }
+
+  e.corruption.body.innerHTML = html;
+
+  for (const button of e.corruption.body.querySelectorAll("button")) {
+    button.addEventListener("click", () => removeCorruption(button.dataset.from, button.dataset.to));
+  }
+};
+
+const renderRoutes = () => {
+  e.routes.list.innerHTML = s.routes
+    .map((r) => `<li>#${r.id} ${escapeHtml(describeFilter(r))} &rarr; ${r.path} <button data-id="${r.id}">&times;</button></li>`)
+    .join(`\n`);
+
+  for (const button of e.routes.list.querySelectorAll("button")) {
+    button.addEventListener("click", () => removeRoute(button.dataset.id));
+  }
+};
+
+const renderBreakpoints = () => {
+  e.breakpoints.list.innerHTML = s.breakpoints
+    .map((b) => `<li>#${b.id} ${escapeHtml(describeFilter(b))} <button data-id="${b.id}">&times;</button></li>`)
+    .join(`\n`);
+
+  for (const button of e.breakpoints.list.querySelectorAll("button")) {
+    button.addEventListener("click", () => removeBreakpoint(button.dataset.id));
+  }
+};
+
+const renderPaused = () => {
+  const hexdump = (hex) =>
+    (hex.match(/.{1,32}/g) || [])
+      .map((line, i) => `${(i * 16).toString(16).padStart(4, "0")}  ${line.match(/../g).join(" ")}`)
+      .join(`\n`);
+
+  e.paused.innerHTML = s.paused
+    .map(
+      ({ breakpoint, packet: p, frame }) => `<div class="alert">
+            <h3>Packet #${p.id} paused at breakpoint #${breakpoint}</h3>
+            <p>
+                ${escapeHtml(p.src_node || p.src_ip)} &rarr; ${escapeHtml(p.dst_node || p.dst_ip)}
+                ${escapeHtml(p.protocol.toUpperCase())} TTL ${p.ttl} ${escapeHtml(p.info)}
+            </p>
+            <pre>${hexdump(frame)}</pre>
+            <button class="forward" data-id="${p.id}">Release</button>
+            <button class="drop" data-id="${p.id}">Drop</button>
+        </div>`
+    )
+    .join(`\n`);
+
+  for (const button of e.paused.querySelectorAll("button")) {
+    button.addEventListener("click", () => releasePacket(button.dataset.id, button.classList.contains("forward")));
+  }
+
+  document.title = s.paused.length ? `(${s.paused.length} paused) ChainNet` : "ChainNet";
+};
+
+const renderScenario = () => {
+  const sc = s.scenario;
+  e.scenario.container.classList.toggle("loaded", !!sc);
+
+  if (!sc) {
+    e.scenario.body.innerHTML = "";
+    return;
+  }
+
+  e.scenario.summary.innerText = `${sc.name}: ${sc.status} ${sc.elapsed.toFixed(1)}s of ${sc.duration}s`;
+  e.scenario.summary.title = sc.description;
+  e.scenario.play.innerText = sc.status === "playing" ? "Pause" : "Play";
+  e.scenario.play.disabled = sc.status === "finished";
+
+  e.scenario.body.innerHTML = sc.steps
+    .map(
+      (step) => `<tr class="${step.status}">
+            <td>${step.at}s</td>
+            <td>${escapeHtml(step.action)}</td>
+            <td>${step.error ? `failed: ${escapeHtml(step.error)}` : step.status}</td>
+        </tr>`
+    )
+    .join(`\n`);
+};
+
+const renderReplay = () => {
+  const { entries, position } = s.replay;
+  e.replay.container.classList.toggle("loaded", entries.length > 0);
+  e.replay.play.innerText = s.replay.playing ? "Pause" : "Play";
+
+  if (!entries.length) {
+    return;
+  }
+
+  const start = entries[0].time;
+  const end = entries[entries.length - 1].time;
+  e.replay.seek.value = end > start ? ((position - start) * 1000) / (end - start) : 0;
+  e.replay.time.innerText = `${new Date(position).toLocaleString()} (${Math.round((position - start) / 1000)}s of ${Math.round(
+    (end - start) / 1000
+  )}s)`;
+
+  const r = replayStateAt(position);
+  const name = (ip) => (r.nodes.find((n) => n.ip === ip) || { name: ip }).name;
+  const highlight = r.packets.filter((p) => p.time > position - REPLAY_HIGHLIGHT * Number(e.replay.speed.value));
+  const sending = new Set(highlight.map((p) => p.src_ip));
+  const receiving = new Set(highlight.map((p) => p.dst_ip));
+
+  e.replay.chain.classList.toggle("off", !r.on);
+  e.replay.chain.innerHTML = r.nodes
+    .map(
+      (n) => `<li class="${sending.has(n.ip) ? "sending" : ""} ${receiving.has(n.ip) ? "receiving" : ""}">
+            ${escapeHtml(n.name)}<br /><small>${n.ip}${n.group ? ` in ${escapeHtml(n.group)}` : ""}</small>
+        </li>`
+    )
+    .join(`\n`);
+
+  e.replay.links.innerHTML = Object.values(r.links)
+    .map((l) => `<li>${escapeHtml(name(l.a))} &harr; ${escapeHtml(name(l.b))}</li>`)
+    .join(`\n`);
+
+  let html = r.packets
+    .slice()
+    .reverse()
+    .map(
+      (p) => `<tr class="${p.decision}">
+            <td>${new Date(p.time).toLocaleTimeString()}</td>
+            <td>${escapeHtml(p.src_node || p.src_ip)} &rarr; ${escapeHtml(p.dst_node || p.dst_ip)}</td>
+            <td>${escapeHtml(p.protocol.toUpperCase())} ${escapeHtml(p.info)}</td>
+            <td>${p.decision}: ${escapeHtml(p.detail)}</td>
+        </tr>`
+    )
+    .join(`\n`);
+
+  if (!html) {
+    html = `<tr class="none"><td colspan="100">No packets yet</td></tr>`;
+  }
+
+  e.replay.body.innerHTML = html;
+};
+
+const renderGroups = () => {
+  let html = s.groups
+    .map(
+      (g) => `<tr>
+            <td>${escapeHtml(g.name)}</td>
+            <td>${g.nodes.map(escapeHtml).join(" &harr; ") || "No nodes"}</td>
+            <td>
+                <select data-name="${g.name}">
+                    <option value="">None</option>
+                    ${s.nodes
+                      .filter((n) => n.group === g.name)
+                      .map(
+                        (n) =>
+                          `<option value="${n.ip}" ${n.ip === g.gateway ? "selected" : ""}>${escapeHtml(n.name.substring(0, 20))}</option>`
+                      )
+                      .join("")}
+                </select>
+            </td>
+            <td><button class="toggle ${g.on ? "on" : "off"}" data-name="${g.name}">${g.on ? "ON" : "OFF"}</button></td>
+            <td><button class="remove" data-name="${g.name}" ${g.nodes.length ? "disabled" : ""}>&times;</button></td>
+        </tr>`
+    )
+    .join(`\n`);
+
+  if (!html) {
+    html = `<tr class="none"><td colspan="100">No groups yet</td></tr>`;
+  }
+
+  e.groups.body.innerHTML = html;
+
+  for (const select of e.groups.body.querySelectorAll("select")) {
+    select.addEventListener("change", () => updateGroup({ name: select.dataset.name, gateway: select.value }));
+  }
+
+  for (const button of e.groups.body.querySelectorAll("button.toggle")) {
+    const group = s.groups.find((g) => g.name === button.dataset.name);
+    button.addEventListener("click", () => updateGroup({ name: group.name, on: !group.on }));
+  }
+
+  for (const button of e.groups.body.querySelectorAll("button.remove")) {
+    button.addEventListener("click", () => removeGroup(button.dataset.name));
+  }
+};
+
+const renderInterfaces = () => {
+  let html = s.interfaces
+    .map(
+      (i) => `<tr>
+            <td>${escapeHtml(i.name)}</td>
+            <td>${i.ip}/${i.prefix}</td>
+            <td>${i.mac || "N/A"}</td>
+            <td>${i.nodes.map(escapeHtml).join(", ") || "No nodes"}</td>
+            <td>
+                <select data-name="${escapeHtml(i.name)}">
+                    <option value="">None</option>
+                    ${s.nodes
+                      .filter((n) => n.interface === i.name)
+                      .map(
+                        (n) =>
+                          `<option value="${n.ip}" ${n.ip === i.gateway ? "selected" : ""}>${escapeHtml(n.name.substring(0, 20))}</option>`
+                      )
+                      .join("")}
+                </select>
+            </td>
+        </tr>`
+    )
+    .join(`\n`);
+
+  if (!html) {
+    html = `<tr class="none"><td colspan="100">The ethernet forwarder has not started</td></tr>`;
+  }
+
+  e.interfaces.body.innerHTML = html;
+
+  for (const select of e.interfaces.body.querySelectorAll("select")) {
+    select.addEventListener("change", () => setInterfaceGateway(select.dataset.name, select.value));
+  }
+};
+
+const renderVlans = () => {
+  const html = s.vlans
+    .map(
+      (v) => `<tr>
+            <td>${v.id}${v.native ? " (native)" : ""}</td>
+            <td>${v.nodes.map(escapeHtml).join(" &harr; ") || "No nodes"}</td>
+            <td>
+                <select data-id="${v.id}">
+                    <option value="">None</option>
+                    ${s.nodes
+                      .filter((n) => n.vlan === v.id)
+                      .map(
+                        (n) =>
+                          `<option value="${n.ip}" ${n.ip === v.gateway ? "selected" : ""}>${escapeHtml(n.name.substring(0, 20))}</option>`
+                      )
+                      .join("")}
+                </select>
+            </td>
+            <td><button data-id="${v.id}" ${v.nodes.length || v.native ? "disabled" : ""}>&times;</button></td>
+        </tr>`
+    )
+    .join(`\n`);
+
+  e.vlans.body.innerHTML = html;
+
+  for (const select of e.vlans.body.querySelectorAll("select")) {
+    select.addEventListener("change", () => setVlanGateway(Number(select.dataset.id), select.value));
+  }
+
+  for (const button of e.vlans.body.querySelectorAll("button")) {
+    button.addEventListener("click", () => removeVlan(button.dataset.id));
+  }
 };
 
+const renderSwitching = () => {
+  // Leave the form alone while it is being edited
+  if (!e.switching.form.contains(document.activeElement)) {
+    e.switching.mode.value = s.switching.mode;
+    e.switching.aging.value = s.switching.aging;
+    e.switching.stp.value = s.switching.stp;
+  }
+
+  const port = (entry) => (entry.port === "node" ? "Node" : `Switch (${escapeHtml(entry.port_name.substring(0, 20))})`);
+
+  let html = s.switching.switches
+    .flatMap((sw) =>
+      sw.entries.map(
+        (entry) => `<tr>
+            <td>${escapeHtml(sw.name.substring(0, 20))}</td>
+            <td>${entry.mac}</td>
+            <td>${escapeHtml(entry.name || "Unknown")}</td>
+            <td>${port(entry)}</td>
+            <td>${entry.age.toFixed(1)}s</td>
+        </tr>`
+      )
+    )
+    .join(`\n`);
+
+  if (!html) {
+    html = `<tr class="none"><td colspan="100">The switches have not learnt any MAC addresses</td></tr>`;
+  }
+
+  e.switching.body.innerHTML = html;
+
+  const bridgeId = (sw) => `${sw.priority}.${sw.node}`;
+
+  html = s.switching.switches
+    .filter((sw) => sw.root !== null)
+    .map(
+      (sw) => `<tr>
+            <td>${escapeHtml(sw.name.substring(0, 20))}</td>
+            <td>${bridgeId(sw)}</td>
+            <td>${sw.root === sw.name ? "This switch" : escapeHtml(sw.root.substring(0, 20))}</td>
+            <td>${sw.root_cost}</td>
+            <td>${
+              sw.ports.map((p) => `${escapeHtml(p.name.substring(0, 20))}: ${p.role} ${p.state}`).join(`<br />`) || "None"
+            }</td>
+        </tr>`
+    )
+    .join(`\n`);
+
+  if (!html) {
+    html = `<tr class="none"><td colspan="100">${
+      s.switching.stp === "off" ? "The spanning tree is off" : "No bridges have been elected"
+    }</td></tr>`;
+  }
+
+  e.switching.bridges.innerHTML = html;
+};
+
+const renderMulticast = () => {
+  e.multicast.snooping.checked = s.multicast.snooping;
+
+  let html = s.multicast.groups
+    .map(
+      (g) => `<tr>
+            <td>${g.group}</td>
+            <td>${g.members.map((m) => `${escapeHtml(m.name.substring(0, 20))} (${m.age.toFixed(0)}s)`).join(", ")}</td>
+        </tr>`
+    )
+    .join(`\n`);
+
+  if (!html) {
+    html = `<tr class="none"><td colspan="100">No nodes have joined a multicast group</td></tr>`;
+  }
+
+  e.multicast.body.innerHTML = html;
+};
 
 const renderStatus = () => {
   e.status.container.classList.toggle("on", s.status);
@@ -154,11 +1298,15 @@ const registerNodeHandlers = () => {
         const up = row.querySelector(".up")
         const down = row.querySelector(".down")
 
+        // Nodes only swap places with their neighbours in the same group's chain
         const idx = i;
-        up.addEventListener("click", () => reorder(idx, idx - 1));
-        down.addEventListener("click", () => reorder(idx, idx + 1));
-        up.disabled = idx === 0;
-        down.disabled = idx === s.nodes.length - 1;
+        const group = s.nodes[idx].group;
+        const prev = s.nodes.slice(0, idx).map((n) => n.group).lastIndexOf(group);
+        const next = s.nodes.findIndex((n, j) => j > idx && n.group === group);
+        up.addEventListener("click", () => reorder(idx, prev));
+        down.addEventListener("click", () => reorder(idx, next));
+        up.disabled = prev === -1;
+        down.disabled = next === -1;
     }
 }
 
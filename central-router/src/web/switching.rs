@@ -0,0 +1,198 @@
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use warp::{filters::BoxedFilter, hyper::StatusCode, Filter, Reply};
+
+use crate::state::{Mode, SharedState, StpMode, SwitchPort};
+
+use super::topology::node_name;
+
+/// The longest time switches can be set to remember mac addresses for, in seconds
+const MAX_MAC_AGING: u64 = 3600;
+
+/// Bridge priorities are set in steps of 4096 up to 61440
+const BRIDGE_PRIORITY_STEP: u16 = 4096;
+const MAX_BRIDGE_PRIORITY: u16 = 61440;
+
+#[derive(Serialize)]
+struct SwitchingResponse {
+    mode: &'static str,
+    /// Seconds until mac table entries which are not seen again are forgotten
+    aging: u64,
+    stp: &'static str,
+    switches: Vec<SwitchResponse>,
+}
+
+/// The mac table and spanning tree ports of the switch simulated at a node
+#[derive(Serialize)]
+struct SwitchResponse {
+    node: String,
+    name: String,
+    priority: u16,
+    /// The name of the root bridge, unless the spanning tree is off
+    root: Option<String>,
+    root_cost: Option<u32>,
+    entries: Vec<MacEntryResponse>,
+    ports: Vec<StpPortResponse>,
+}
+
+/// A port linked to another switch
+#[derive(Serialize)]
+struct StpPortResponse {
+    /// The node of the switch at the other end
+    switch: String,
+    name: String,
+    role: &'static str,
+    state: &'static str,
+}
+
+#[derive(Serialize)]
+struct MacEntryResponse {
+    mac: String,
+    /// The node with the mac address
+    name: Option<String>,
+    port: &'static str,
+    /// The node at the other end of the port
+    port_name: String,
+    /// Seconds since the mac address was last seen
+    age: f64,
+}
+
+#[derive(Deserialize)]
+struct SwitchingRequest {
+    /// One of "routing" or "switching"
+    mode: Option<String>,
+    aging: Option<u64>,
+    /// One of "off", "stp" or "rstp"
+    stp: Option<String>,
+    /// Sets the bridge priority of the switch at the node, by its name or ip
+    node: Option<String>,
+    priority: Option<u16>,
+    /// Empties the mac tables
+    #[serde(default)]
+    clear: bool,
+}
+
+pub fn get(state: SharedState) -> BoxedFilter<(impl Reply,)> {
+    warp::get()
+        .map(move || {
+            let now = Instant::now();
+
+            let switching = state.get(|s| SwitchingResponse {
+                mode: s.mode.name(),
+                aging: s.mac_aging.as_secs(),
+                stp: s.stp.name(),
+                switches: s
+                    .nodes
+                    .iter()
+                    .map(|node| {
+                        let neighbour = |port| match port {
+                            SwitchPort::Node => node.name.clone(),
+                            SwitchPort::Switch(ip) => node_name(s, ip),
+                        };
+
+                        let entries = s.mac_tables.get(&node.ip).into_iter().flatten();
+                        let bridge = s.bridges.get(&node.ip);
+
+                        SwitchResponse {
+                            node: node.ip.to_string(),
+                            name: node.name.clone(),
+                            priority: s.bridge_priority(node.ip),
+                            root: bridge.map(|i| node_name(s, i.root)),
+                            root_cost: bridge.map(|i| i.cost),
+                            ports: s
+                                .stp_ports
+                                .iter()
+                                .filter(|((switch, _), _)| *switch == node.ip)
+                                .map(|((_, other), port)| StpPortResponse {
+                                    switch: other.to_string(),
+                                    name: node_name(s, *other),
+                                    role: port.role.name(),
+                                    state: port.state.name(),
+                                })
+                                .collect(),
+                            entries: entries
+                                .filter(|(_, entry)| {
+                                    now.duration_since(entry.learned) < s.mac_aging
+                                })
+                                .map(|(mac, entry)| MacEntryResponse {
+                                    mac: mac.to_string(),
+                                    name: s
+                                        .nodes
+                                        .iter()
+                                        .find(|i| i.mac == Some(*mac))
+                                        .map(|i| i.name.clone()),
+                                    port: entry.port.name(),
+                                    port_name: neighbour(entry.port),
+                                    age: now.duration_since(entry.learned).as_secs_f64(),
+                                })
+                                .collect(),
+                        }
+                    })
+                    .collect(),
+            });
+
+            warp::reply::json(&switching)
+        })
+        .boxed()
+}
+
+pub fn post(state: SharedState) -> BoxedFilter<(impl Reply,)> {
+    warp::post()
+        .and(warp::body::json())
+        .map(move |r: SwitchingRequest| {
+            let mode = match r.mode.as_deref().map(Mode::from_name) {
+                Some(None) => return StatusCode::BAD_REQUEST,
+                Some(mode) => mode,
+                None => None,
+            };
+
+            let stp = match r.stp.as_deref().map(StpMode::from_name) {
+                Some(None) => return StatusCode::BAD_REQUEST,
+                Some(stp) => stp,
+                None => None,
+            };
+
+            if !r.aging.map_or(true, |i| (1..=MAX_MAC_AGING).contains(&i)) {
+                return StatusCode::BAD_REQUEST;
+            }
+
+            let valid_priority = |i: u16| i % BRIDGE_PRIORITY_STEP == 0 && i <= MAX_BRIDGE_PRIORITY;
+            if !r.priority.map_or(true, valid_priority) {
+                return StatusCode::BAD_REQUEST;
+            }
+
+            state.update(|s| {
+                if let (Some(node), Some(priority)) = (&r.node, r.priority) {
+                    let ip = match s.find_node(node) {
+                        Some(node) => node.ip,
+                        None => return StatusCode::NOT_FOUND,
+                    };
+
+                    log::info!("set bridge priority of {} to {}", ip, priority);
+                    s.bridge_priorities.insert(ip, priority);
+                }
+
+                if let Some(stp) = stp {
+                    log::info!("set spanning tree to {}", stp.name());
+                    s.stp = stp;
+                }
+
+                if let Some(mode) = mode {
+                    log::info!("switched to {} mode", mode.name());
+                    s.mode = mode;
+                }
+
+                if let Some(aging) = r.aging {
+                    s.mac_aging = Duration::from_secs(aging);
+                }
+
+                if r.clear {
+                    s.mac_tables.clear();
+                }
+
+                StatusCode::OK
+            })
+        })
+        .boxed()
+}
@@ -0,0 +1,156 @@
+use std::net::Ipv4Addr;
+
+use serde::{Deserialize, Serialize};
+use warp::{filters::BoxedFilter, hyper::StatusCode, Filter, Reply};
+
+use crate::state::{Ecmp, SharedState, State};
+
+#[derive(Serialize)]
+struct LinkResponse {
+    id: u64,
+    a: String,
+    b: String,
+}
+
+/// Links two nodes by their names or ips
+#[derive(Deserialize)]
+struct NewLink {
+    a: String,
+    b: String,
+}
+
+#[derive(Serialize)]
+struct EcmpResponse {
+    mode: &'static str,
+    paths: Vec<PathResponse>,
+}
+
+/// Packets sent from one node towards a destination via one of the equal cost next hops
+#[derive(Serialize)]
+struct PathResponse {
+    from: String,
+    to: String,
+    via: String,
+    packets: u64,
+}
+
+#[derive(Deserialize)]
+struct EcmpRequest {
+    /// One of "hash" or "round-robin"
+    mode: Option<String>,
+    /// Clears the path counters
+    #[serde(default)]
+    reset: bool,
+}
+
+pub fn get_links(state: SharedState) -> BoxedFilter<(impl Reply,)> {
+    warp::get()
+        .map(move || {
+            let links = state.get(|s| {
+                s.links
+                    .iter()
+                    .map(|i| LinkResponse {
+                        id: i.id,
+                        a: node_name(s, i.a),
+                        b: node_name(s, i.b),
+                    })
+                    .collect::<Vec<_>>()
+            });
+
+            warp::reply::json(&links)
+        })
+        .boxed()
+}
+
+pub fn post_link(state: SharedState) -> BoxedFilter<(impl Reply,)> {
+    warp::post()
+        .and(warp::body::json())
+        .map(move |l: NewLink| {
+            state.update(|s| {
+                let (a, b) = match (s.find_node(&l.a), s.find_node(&l.b)) {
+                    (Some(a), Some(b)) if a != b && a.group == b.group => (a.ip, b.ip),
+                    _ => return StatusCode::BAD_REQUEST,
+                };
+
+                let exists = s
+                    .links
+                    .iter()
+                    .any(|i| (i.a == a && i.b == b) || (i.a == b && i.b == a));
+
+                if !exists {
+                    let id = s.add_link(a, b);
+                    log::info!("added link #{} between {} and {}", id, a, b);
+                }
+
+                StatusCode::OK
+            })
+        })
+        .boxed()
+}
+
+pub fn delete_link(state: SharedState) -> BoxedFilter<(impl Reply,)> {
+    warp::delete()
+        .and(warp::path::param())
+        .and(warp::path::end())
+        .map(move |id: u64| {
+            state.update(|s| {
+                s.links.retain(|i| i.id != id);
+            });
+
+            log::info!("removed link #{}", id);
+            StatusCode::OK
+        })
+        .boxed()
+}
+
+pub fn get_ecmp(state: SharedState) -> BoxedFilter<(impl Reply,)> {
+    warp::get()
+        .map(move || {
+            let ecmp = state.get(|s| EcmpResponse {
+                mode: s.ecmp.name(),
+                paths: s
+                    .path_counters
+                    .iter()
+                    .map(|(key, packets)| PathResponse {
+                        from: node_name(s, key.from),
+                        to: node_name(s, key.to),
+                        via: node_name(s, key.via),
+                        packets: *packets,
+                    })
+                    .collect(),
+            });
+
+            warp::reply::json(&ecmp)
+        })
+        .boxed()
+}
+
+pub fn post_ecmp(state: SharedState) -> BoxedFilter<(impl Reply,)> {
+    warp::post()
+        .and(warp::body::json())
+        .map(move |r: EcmpRequest| {
+            let mode = match r.mode.as_deref().map(Ecmp::from_name) {
+                Some(None) => return StatusCode::BAD_REQUEST,
+                Some(mode) => mode,
+                None => None,
+            };
+
+            state.update(|s| {
+                if let Some(mode) = mode {
+                    s.ecmp = mode;
+                }
+
+                if r.reset {
+                    s.path_counters.clear();
+                }
+            });
+
+            StatusCode::OK
+        })
+        .boxed()
+}
+
+/// Shows nodes by name where they are still registered
+pub fn node_name(state: &State, ip: Ipv4Addr) -> String {
+    state.node_name(ip).unwrap_or_else(|| ip.to_string())
+}
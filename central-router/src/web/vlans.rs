@@ -0,0 +1,152 @@
+use serde::{Deserialize, Serialize};
+use warp::{filters::BoxedFilter, hyper::StatusCode, Filter, Reply};
+
+use crate::state::{SharedState, MAX_VLAN, NATIVE_VLAN};
+
+use super::topology::node_name;
+
+#[derive(Serialize)]
+struct VlanResponse {
+    id: u16,
+    /// Frames in the native vlan are untagged
+    native: bool,
+    gateway: Option<String>,
+    gateway_name: Option<String>,
+    /// Names of the nodes in the vlan in the order of the chain
+    nodes: Vec<String>,
+}
+
+/// Creates or updates the vlan, leaving out the gateway keeps the current one
+#[derive(Deserialize)]
+struct VlanRequest {
+    id: u16,
+    /// The name or ip of a node in the vlan, or empty to remove the gateway
+    gateway: Option<String>,
+}
+
+/// Assigns the node to the vlan
+#[derive(Deserialize)]
+struct AssignRequest {
+    /// The name or ip of the node
+    node: String,
+    vlan: u16,
+}
+
+pub fn get(state: SharedState) -> BoxedFilter<(impl Reply,)> {
+    warp::get()
+        .map(move || {
+            let vlans = state.get(|s| {
+                s.vlan_ids()
+                    .into_iter()
+                    .map(|id| {
+                        let gateway = s.vlan(id).gateway;
+                        VlanResponse {
+                            id,
+                            native: id == NATIVE_VLAN,
+                            gateway: gateway.map(|i| i.to_string()),
+                            gateway_name: gateway.map(|i| node_name(s, i)),
+                            nodes: s
+                                .nodes
+                                .iter()
+                                .filter(|i| i.vlan == id)
+                                .map(|i| i.name.clone())
+                                .collect(),
+                        }
+                    })
+                    .collect::<Vec<_>>()
+            });
+
+            warp::reply::json(&vlans)
+        })
+        .boxed()
+}
+
+pub fn post(state: SharedState) -> BoxedFilter<(impl Reply,)> {
+    warp::post()
+        .and(warp::body::json())
+        .map(move |v: VlanRequest| {
+            let VlanRequest { id, gateway } = v;
+            if !is_valid_id(id) {
+                return StatusCode::BAD_REQUEST;
+            }
+
+            state.update(|s| {
+                let gateway = match gateway.as_deref() {
+                    None => s.vlan(id).gateway,
+                    Some("") => None,
+                    Some(gateway) => match s.find_node(gateway) {
+                        Some(node) if node.vlan == id => Some(node.ip),
+                        _ => return StatusCode::BAD_REQUEST,
+                    },
+                };
+
+                let vlan = s.vlans.entry(id).or_default();
+                vlan.gateway = gateway;
+
+                log::info!("updated vlan {} to {:?}", id, vlan);
+                StatusCode::OK
+            })
+        })
+        .boxed()
+}
+
+pub fn put(state: SharedState) -> BoxedFilter<(impl Reply,)> {
+    warp::put()
+        .and(warp::body::json())
+        .map(move |a: AssignRequest| {
+            if !is_valid_id(a.vlan) {
+                return StatusCode::BAD_REQUEST;
+            }
+
+            state.update(|s| {
+                let node = s
+                    .nodes
+                    .iter_mut()
+                    .find(|i| i.name == a.node || i.ip.to_string() == a.node);
+
+                let node = match node {
+                    Some(node) => node,
+                    None => return StatusCode::NOT_FOUND,
+                };
+
+                node.vlan = a.vlan;
+                let ip = node.ip;
+                s.vlans.entry(a.vlan).or_default();
+
+                // The node stops being the gateway of the vlan it has left
+                for (id, vlan) in s.vlans.iter_mut() {
+                    if vlan.gateway == Some(ip) && *id != a.vlan {
+                        vlan.gateway = None;
+                    }
+                }
+
+                log::info!("assigned node {} to vlan {}", ip, a.vlan);
+                StatusCode::OK
+            })
+        })
+        .boxed()
+}
+
+/// Removes a vlan's settings once none of the nodes are in it
+pub fn delete(state: SharedState) -> BoxedFilter<(impl Reply,)> {
+    warp::delete()
+        .and(warp::path::param())
+        .and(warp::path::end())
+        .map(move |id: u16| {
+            state.update(|s| {
+                if s.nodes.iter().any(|i| i.vlan == id) {
+                    return StatusCode::BAD_REQUEST;
+                }
+
+                s.vlans.remove(&id);
+
+                log::info!("removed vlan {}", id);
+                StatusCode::OK
+            })
+        })
+        .boxed()
+}
+
+fn is_valid_id(id: u16) -> bool {
+    (1..=MAX_VLAN).contains(&id)
+}
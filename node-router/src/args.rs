@@ -1,5 +1,9 @@
+use std::net::Ipv4Addr;
+
 use clap::Clap;
 
+use crate::ip::{filter::Filter, tamper::TamperRules};
+
 #[derive(Clap, Clone)]
 #[clap(version = "1.0", author = "Elliot Levin <elliotlevin@hotmail.com>")]
 pub struct Args {
@@ -8,6 +12,174 @@ pub struct Args {
     #[clap(long)]
     pub promisc: bool,
 
+    /// Dump received packets, repeat for more detail (ip, transport, payload, hexdump)
     #[clap(short, long, parse(from_occurrences))]
-    pub dump: u16
-}
\ No newline at end of file
+    pub dump: u16,
+
+    /// Only dump packets matching the filter expression, eg "tcp port 80 and src host 10.0.0.5"
+    #[clap(short, long)]
+    pub filter: Option<Filter>,
+
+    /// Reassemble tcp connections and print each conversation when it closes (or when enter is pressed)
+    #[clap(long)]
+    pub streams: bool,
+
+    /// Forward packets to the next node along the chain rather than returning them to the central router
+    #[clap(long)]
+    pub forward: bool,
+
+    /// Address of the central router's web server used to learn the chain and name hops, eg 10.0.0.1:8080
+    #[clap(long)]
+    pub central: Option<String>,
+
+    /// Path to a json file listing the chain in the same format as the central router's /api/nodes
+    #[clap(long, conflicts_with = "central")]
+    pub nodes: Option<String>,
+
+    /// Drop packets passing through this node whose ipv4 header checksum is wrong, as a router would
+    #[clap(long)]
+    pub drop_bad_checksums: bool,
+
+    /// Path to a rule file used to drop, delay or modify packets passing through this node
+    #[clap(long, parse(try_from_str = TamperRules::load))]
+    pub tamper: Option<TamperRules>,
+
+    /// Show an interactive terminal ui rather than printing packets
+    #[clap(long, conflicts_with = "streams")]
+    pub tui: bool,
+
+    /// Run udp and tcp echo services on this port
+    #[clap(long)]
+    pub echo_port: Option<u16>,
+
+    /// Serve a page saying hello from this node over http on this port
+    #[clap(long)]
+    pub http_port: Option<u16>,
+
+    /// Chat with other nodes on this port, type "<node name or ip> <message>" to send a message
+    #[clap(long, conflicts_with_all = &["streams", "tui"])]
+    pub chat_port: Option<u16>,
+
+    /// Name of this node used by the services, defaults to its name in the chain
+    #[clap(long)]
+    pub name: Option<String>,
+
+    /// Tag frames sent by this node with this vlan. Otherwise the vlan is learnt from the frames
+    /// of the central router, whose ip is taken from --central or the gateway of the subcommand
+    #[clap(long, parse(try_from_str = parse_vlan))]
+    pub vlan: Option<u16>,
+
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Clap, Clone)]
+pub enum Command {
+    /// Send icmp echo requests to another node
+    Ping(TrafficArgs),
+    /// Send udp datagrams to a port on another node
+    Udp(PortTrafficArgs),
+    /// Send tcp syn segments to a port on another node
+    Syn(PortTrafficArgs),
+    /// Print the hops along the chain to another node
+    Trace(TraceArgs),
+}
+
+#[derive(Clap, Clone)]
+pub struct TrafficArgs {
+    /// IP address of the node to send packets to
+    pub dest: Ipv4Addr,
+
+    /// IP address of the central router which the packets are sent through
+    #[clap(short, long)]
+    pub gateway: Ipv4Addr,
+
+    /// Number of packets to send
+    #[clap(short, long, default_value = "4")]
+    pub count: u32,
+
+    /// Packets sent per second
+    #[clap(short, long, default_value = "1")]
+    pub rate: f64,
+
+    /// Payload size in bytes of icmp and udp probes
+    #[clap(short, long, default_value = "32", parse(try_from_str = parse_size))]
+    pub size: usize,
+}
+
+#[derive(Clap, Clone)]
+pub struct PortTrafficArgs {
+    #[clap(flatten)]
+    pub traffic: TrafficArgs,
+
+    /// Destination port
+    #[clap(short, long)]
+    pub port: u16,
+}
+
+#[derive(Clap, Clone)]
+pub struct TraceArgs {
+    /// IP address of the node to trace the route to
+    pub dest: Ipv4Addr,
+
+    /// IP address of the central router which the packets are sent through
+    #[clap(short, long)]
+    pub gateway: Ipv4Addr,
+
+    /// Maximum number of hops to probe
+    #[clap(short, long, default_value = "16")]
+    pub max_hops: u8,
+
+    /// Number of probes sent per hop
+    #[clap(short, long, default_value = "3")]
+    pub queries: u8,
+}
+
+impl Args {
+    /// Returns the ip of the central router, if it is known
+    pub fn router_ip(&self) -> Option<Ipv4Addr> {
+        self.command.as_ref().map(|i| i.gateway()).or_else(|| {
+            self.central
+                .as_ref()
+                .and_then(|i| i.split(':').next()?.parse().ok())
+        })
+    }
+}
+
+/// The largest payload which fits in an ipv4 packet along with the ipv4 and icmp or udp headers
+const MAX_PROBE_SIZE: usize = 65535 - 20 - 8;
+
+fn parse_size(s: &str) -> Result<usize, String> {
+    match s.parse::<usize>() {
+        Ok(size) if size <= MAX_PROBE_SIZE => Ok(size),
+        _ => Err(format!(
+            "invalid size '{}', must be at most {} bytes",
+            s, MAX_PROBE_SIZE
+        )),
+    }
+}
+
+fn parse_vlan(s: &str) -> Result<u16, String> {
+    match s.parse::<u16>() {
+        Ok(vlan) if (1..=4094).contains(&vlan) => Ok(vlan),
+        _ => Err(format!("invalid vlan '{}', must be between 1 and 4094", s)),
+    }
+}
+
+impl Command {
+    pub fn dest(&self) -> Ipv4Addr {
+        match self {
+            Command::Ping(args) => args.dest,
+            Command::Udp(args) | Command::Syn(args) => args.traffic.dest,
+            Command::Trace(args) => args.dest,
+        }
+    }
+
+    pub fn gateway(&self) -> Ipv4Addr {
+        match self {
+            Command::Ping(args) => args.gateway,
+            Command::Udp(args) | Command::Syn(args) => args.traffic.gateway,
+            Command::Trace(args) => args.gateway,
+        }
+    }
+}
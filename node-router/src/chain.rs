@@ -0,0 +1,139 @@
+use std::{
+    fs,
+    io::{Read, Write},
+    net::{Ipv4Addr, TcpStream, ToSocketAddrs},
+    thread,
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Result};
+use pnet::util::MacAddr;
+use serde::Deserialize;
+
+use crate::{
+    args::Args,
+    state::{ChainNode, SharedState},
+};
+
+const REFRESH_INTERVAL: Duration = Duration::from_secs(2);
+const HTTP_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// A node as returned by the central router's /api/nodes endpoint
+#[derive(Deserialize)]
+struct NodeResponse {
+    name: String,
+    ip: Ipv4Addr,
+    mac: Option<String>,
+    #[serde(default)]
+    group: Option<String>,
+    #[serde(default)]
+    interface: Option<String>,
+    #[serde(default)]
+    vlan: Option<u16>,
+}
+
+/// Keeps the local copy of the chain up to date so this node can forward
+/// packets to its neighbours itself.
+pub fn start(args: Args, state: SharedState) -> Result<()> {
+    if let Some(path) = &args.nodes {
+        log::info!("loading chain from {}", path);
+        let chain = load(&args)?;
+        log_chain(&chain);
+        state.update(|s| s.chain = chain);
+        return Ok(());
+    }
+
+    let central = match &args.central {
+        Some(central) => central,
+        None => bail!("--central or --nodes is required to forward packets"),
+    };
+
+    log::info!("fetching chain from central router at {}", central);
+
+    while state.running() {
+        match fetch_nodes(central) {
+            Ok(chain) => {
+                if state.get(|s| s.chain != chain) {
+                    log_chain(&chain);
+                    state.update(|s| s.chain = chain);
+                }
+            }
+            Err(err) => log::warn!("failed to fetch nodes from central router: {}", err),
+        }
+
+        thread::sleep(REFRESH_INTERVAL);
+    }
+
+    Ok(())
+}
+
+/// Loads the chain once from the nodes file or the central router
+pub fn load(args: &Args) -> Result<Vec<ChainNode>> {
+    match (&args.nodes, &args.central) {
+        (Some(path), _) => parse_nodes(&fs::read_to_string(path)?),
+        (None, Some(central)) => fetch_nodes(central),
+        (None, None) => bail!("--central or --nodes is required to learn the chain"),
+    }
+}
+
+fn fetch_nodes(central: &str) -> Result<Vec<ChainNode>> {
+    let addr = central
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| anyhow!("could not resolve {}", central))?;
+
+    let mut stream = TcpStream::connect_timeout(&addr, HTTP_TIMEOUT)?;
+    stream.set_read_timeout(Some(HTTP_TIMEOUT))?;
+    write!(
+        stream,
+        "GET /api/nodes HTTP/1.0\r\nHost: {}\r\nAccept: application/json\r\n\r\n",
+        central
+    )?;
+
+    let mut res = String::new();
+    stream.read_to_string(&mut res)?;
+
+    let (head, body) = res
+        .split_once("\r\n\r\n")
+        .ok_or_else(|| anyhow!("invalid http response"))?;
+
+    let status = head.lines().next().unwrap_or_default();
+    if !status.contains(" 200 ") {
+        bail!("unexpected http response status: {}", status);
+    }
+
+    parse_nodes(body)
+}
+
+fn parse_nodes(json: &str) -> Result<Vec<ChainNode>> {
+    serde_json::from_str::<Vec<NodeResponse>>(json)?
+        .into_iter()
+        .map(|i| {
+            Ok(ChainNode {
+                mac: match i.mac {
+                    Some(mac) => Some(
+                        mac.parse::<MacAddr>()
+                            .map_err(|_| anyhow!("invalid mac address {}", mac))?,
+                    ),
+                    None => None,
+                },
+                name: i.name,
+                ip: i.ip,
+                group: i.group,
+                interface: i.interface,
+                vlan: i.vlan,
+            })
+        })
+        .collect()
+}
+
+fn log_chain(chain: &[ChainNode]) {
+    log::info!(
+        "chain is now: {}",
+        chain
+            .iter()
+            .map(|i| format!("{} ({})", i.name, i.ip))
+            .collect::<Vec<_>>()
+            .join(" -> ")
+    );
+}
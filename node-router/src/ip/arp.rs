@@ -0,0 +1,68 @@
+use std::net::Ipv4Addr;
+
+use pnet::packet::arp::{ArpHardwareTypes, ArpOperations, ArpPacket, MutableArpPacket};
+use pnet::{
+    datalink::NetworkInterface,
+    packet::ethernet::{EtherTypes, EthernetPacket, MutableEthernetPacket},
+    packet::Packet,
+    util::MacAddr,
+};
+
+use crate::state::SharedState;
+
+/// Builds a broadcast arp request for the mac address of `ip`
+pub fn build_request(
+    interface: &NetworkInterface,
+    source_ip: Ipv4Addr,
+    ip: Ipv4Addr,
+) -> Option<EthernetPacket<'static>> {
+    let source_mac = interface.mac?;
+
+    let mut buff = vec![0u8; 42]; // 14 (eth frame header) + 28 (arp request length)
+    let (eth_buff, arp_buff) = buff.split_at_mut(14);
+
+    let mut eth = MutableEthernetPacket::new(eth_buff).unwrap();
+    eth.set_source(source_mac);
+    eth.set_destination(MacAddr::broadcast());
+    eth.set_ethertype(EtherTypes::Arp);
+
+    let mut arp = MutableArpPacket::new(arp_buff).unwrap();
+    arp.set_hardware_type(ArpHardwareTypes::Ethernet);
+    arp.set_protocol_type(EtherTypes::Ipv4);
+    arp.set_hw_addr_len(6);
+    arp.set_proto_addr_len(4);
+    arp.set_operation(ArpOperations::Request);
+    arp.set_sender_hw_addr(source_mac);
+    arp.set_sender_proto_addr(source_ip);
+    arp.set_target_hw_addr(MacAddr::zero());
+    arp.set_target_proto_addr(ip);
+
+    EthernetPacket::owned(buff)
+}
+
+/// Learns the mac address of the sender of any arp request or reply
+pub fn process_packet(state: &SharedState, eth: &EthernetPacket) {
+    let arp = match ArpPacket::new(eth.payload()) {
+        Some(arp) => arp,
+        None => return,
+    };
+    log::trace!("packet is arp");
+
+    if arp.get_protocol_type() != EtherTypes::Ipv4 {
+        log::trace!("protocol type is not ipv4");
+        return;
+    }
+
+    let sender_mac = arp.get_sender_hw_addr();
+    let sender_ip = arp.get_sender_proto_addr();
+
+    if sender_ip.is_unspecified() {
+        return;
+    }
+
+    state.update(|s| {
+        if s.arp.insert(sender_ip, sender_mac) != Some(sender_mac) {
+            log::debug!("learnt mac {} for ip {}", sender_mac, sender_ip);
+        }
+    });
+}
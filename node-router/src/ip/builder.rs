@@ -0,0 +1,128 @@
+use std::net::Ipv4Addr;
+
+use pnet::packet::ethernet::{EtherTypes, EthernetPacket, MutableEthernetPacket};
+use pnet::packet::icmp::{
+    self, echo_request::MutableEchoRequestPacket, time_exceeded::IcmpCodes, IcmpPacket, IcmpTypes,
+    MutableIcmpPacket,
+};
+use pnet::packet::ip::{IpNextHeaderProtocol, IpNextHeaderProtocols};
+use pnet::packet::ipv4::{self, Ipv4Packet, MutableIpv4Packet};
+use pnet::packet::tcp::{self, MutableTcpPacket, TcpFlags};
+use pnet::packet::udp::{self, MutableUdpPacket};
+use pnet::packet::{MutablePacket, Packet};
+use pnet::util::MacAddr;
+
+const IPV4_HEADER_LEN: usize = 20;
+const ICMP_HEADER_LEN: usize = 8;
+const UDP_HEADER_LEN: usize = 8;
+const TCP_HEADER_LEN: usize = 20;
+const DEFAULT_TTL: u8 = 64;
+
+/// Wraps the ipv4 packet in an ethernet frame
+pub fn build_ethernet(src: MacAddr, dst: MacAddr, ip: &[u8]) -> EthernetPacket<'static> {
+    let mut eth = MutableEthernetPacket::owned(vec![0u8; 14 + ip.len()]).unwrap();
+    eth.set_source(src);
+    eth.set_destination(dst);
+    eth.set_ethertype(EtherTypes::Ipv4);
+    eth.set_payload(ip);
+    eth.consume_to_immutable()
+}
+
+/// Builds an ipv4 packet without options around the supplied payload
+pub fn build_ipv4(
+    src: Ipv4Addr,
+    dst: Ipv4Addr,
+    protocol: IpNextHeaderProtocol,
+    payload: &[u8],
+) -> Vec<u8> {
+    build_ipv4_with_ttl(src, dst, protocol, DEFAULT_TTL, payload)
+}
+
+/// Builds an ipv4 packet which expires after `ttl` hops
+pub fn build_ipv4_with_ttl(
+    src: Ipv4Addr,
+    dst: Ipv4Addr,
+    protocol: IpNextHeaderProtocol,
+    ttl: u8,
+    payload: &[u8],
+) -> Vec<u8> {
+    let mut ip = MutableIpv4Packet::owned(vec![0u8; IPV4_HEADER_LEN + payload.len()]).unwrap();
+    ip.set_version(4);
+    ip.set_header_length((IPV4_HEADER_LEN / 4) as u8);
+    ip.set_total_length((IPV4_HEADER_LEN + payload.len()) as u16);
+    ip.set_ttl(ttl);
+    ip.set_next_level_protocol(protocol);
+    ip.set_source(src);
+    ip.set_destination(dst);
+    ip.set_payload(payload);
+    ip.set_checksum(ipv4::checksum(&ip.to_immutable()));
+    ip.packet().to_vec()
+}
+
+/// Builds an icmp time exceeded packet from `src` in response to the expired packet
+pub fn build_time_exceeded(src: Ipv4Addr, expired: &Ipv4Packet) -> Vec<u8> {
+    // The body contains the original ip header and the first 8 bytes of its payload
+    let original = expired.packet();
+    let quoted_len = (expired.get_header_length() as usize * 4 + 8).min(original.len());
+
+    let mut icmp = MutableIcmpPacket::owned(vec![0u8; ICMP_HEADER_LEN + quoted_len]).unwrap();
+    icmp.set_icmp_type(IcmpTypes::TimeExceeded);
+    icmp.set_icmp_code(IcmpCodes::TimeToLiveExceededInTransit);
+    icmp.packet_mut()[ICMP_HEADER_LEN..].copy_from_slice(&original[..quoted_len]);
+    icmp.set_checksum(icmp::checksum(&IcmpPacket::new(icmp.packet()).unwrap()));
+
+    build_ipv4(
+        src,
+        expired.get_source(),
+        IpNextHeaderProtocols::Icmp,
+        icmp.packet(),
+    )
+}
+
+/// Builds an icmp echo request carrying the supplied payload
+pub fn build_echo_request(identifier: u16, sequence: u16, payload: &[u8]) -> Vec<u8> {
+    let mut echo =
+        MutableEchoRequestPacket::owned(vec![0u8; ICMP_HEADER_LEN + payload.len()]).unwrap();
+    echo.set_icmp_type(IcmpTypes::EchoRequest);
+    echo.set_identifier(identifier);
+    echo.set_sequence_number(sequence);
+    echo.set_payload(payload);
+    echo.set_checksum(icmp::checksum(&IcmpPacket::new(echo.packet()).unwrap()));
+    echo.packet().to_vec()
+}
+
+/// Builds a udp datagram, the addresses are needed for the checksum
+pub fn build_udp(
+    src: Ipv4Addr,
+    dst: Ipv4Addr,
+    src_port: u16,
+    dst_port: u16,
+    payload: &[u8],
+) -> Vec<u8> {
+    let mut udp = MutableUdpPacket::owned(vec![0u8; UDP_HEADER_LEN + payload.len()]).unwrap();
+    udp.set_source(src_port);
+    udp.set_destination(dst_port);
+    udp.set_length((UDP_HEADER_LEN + payload.len()) as u16);
+    udp.set_payload(payload);
+    udp.set_checksum(udp::ipv4_checksum(&udp.to_immutable(), &src, &dst));
+    udp.packet().to_vec()
+}
+
+/// Builds a tcp segment with only the syn flag set
+pub fn build_tcp_syn(
+    src: Ipv4Addr,
+    dst: Ipv4Addr,
+    src_port: u16,
+    dst_port: u16,
+    sequence: u32,
+) -> Vec<u8> {
+    let mut tcp = MutableTcpPacket::owned(vec![0u8; TCP_HEADER_LEN]).unwrap();
+    tcp.set_source(src_port);
+    tcp.set_destination(dst_port);
+    tcp.set_sequence(sequence);
+    tcp.set_data_offset((TCP_HEADER_LEN / 4) as u8);
+    tcp.set_flags(TcpFlags::SYN);
+    tcp.set_window(64240);
+    tcp.set_checksum(tcp::ipv4_checksum(&tcp.to_immutable(), &src, &dst));
+    tcp.packet().to_vec()
+}
@@ -1,69 +1,276 @@
 use std::ascii::escape_default;
 
-use pnet::packet::icmp::{IcmpPacket, IcmpTypes};
+use pnet::packet::ethernet::EthernetPacket;
+use pnet::packet::icmp::{self, IcmpPacket, IcmpTypes};
 use pnet::packet::ip::IpNextHeaderProtocols;
-use pnet::packet::ipv4::Ipv4Packet;
-use pnet::packet::tcp::TcpPacket;
-use pnet::packet::udp::UdpPacket;
+use pnet::packet::ipv4::{self, Ipv4Flags, Ipv4Packet};
+use pnet::packet::tcp::{self, TcpPacket};
+use pnet::packet::udp::{self, UdpPacket};
 use pnet::packet::Packet;
 
-use crate::args::Args;
+const ETHERNET_HEADER_LEN: usize = 14;
+const UDP_HEADER_LEN: usize = 8;
+const ICMP_HEADER_LEN: usize = 8;
+/// Shown for the transport checksum of a fragment, which covers the rest of the packet
+const FRAGMENT_CHECKSUM: &str = "n/a (fragment)";
 
-pub fn dump_packet(args: &Args, packet: &Ipv4Packet) -> Result<(), String> {
-    if args.dump == 0 {
-        return Ok(());
+pub fn dump_packet(dump: u16, eth: &EthernetPacket, vlan: Option<u16>) {
+    if dump == 0 {
+        return;
     }
 
     println!("\n ------ packet received ------ ");
-    println!("IP   | src ip: {} | dst ip: {} |", packet.get_source(), packet.get_destination());
+    print_lines(describe_vlan(vlan));
+    print_lines(describe_frame(dump, eth));
+}
 
-    if args.dump == 1 {
-        return Ok(());
+/// Dumps a packet which was reassembled from the fragments dumped before it
+pub fn dump_reassembled(dump: u16, eth: &EthernetPacket, vlan: Option<u16>, fragments: usize) {
+    if dump == 0 {
+        return;
     }
 
-    let payload = match packet.get_next_level_protocol() {
-        IpNextHeaderProtocols::Tcp => {
-            dump_tcp_header(TcpPacket::new(packet.payload()).ok_or("invalid tcp packet")?)
-        }
-        IpNextHeaderProtocols::Udp => {
-            dump_udp_header(UdpPacket::new(packet.payload()).ok_or("invalid udp packet")?)
-        }
-        IpNextHeaderProtocols::Icmp => {
-            dump_icmp_header(IcmpPacket::new(packet.payload()).ok_or("invalid icmp packet")?)
+    println!(
+        "\n ------ packet reassembled from {} fragments ------ ",
+        fragments
+    );
+    print_lines(describe_vlan(vlan));
+    print_lines(describe_frame(dump, eth));
+}
+
+/// Dumps a packet before and after it was modified by the tamper rules
+pub fn dump_tampered(
+    dump: u16,
+    before: &EthernetPacket,
+    vlan: Option<u16>,
+    after: Option<&EthernetPacket>,
+    actions: &[String],
+) {
+    if dump == 0 {
+        return;
+    }
+
+    println!("\n ------ packet tampered: {} ------ ", actions.join(", "));
+    print_lines(describe_vlan(vlan));
+    print_lines(describe_tampered(dump, before, after));
+}
+
+/// Returns the 802.1Q tag the frame arrived with, untagged frames have no line
+pub fn describe_vlan(vlan: Option<u16>) -> Vec<String> {
+    vlan.map(|i| format!("VLAN | id: {} |", i))
+        .into_iter()
+        .collect()
+}
+
+/// Returns the decoded headers of the frame, in more detail for higher dump levels
+pub fn describe_frame(dump: u16, eth: &EthernetPacket) -> Vec<String> {
+    let mut out = vec![];
+
+    if let Err(err) = describe_frame_into(dump, eth, &mut out) {
+        out.push(err);
+    }
+
+    out
+}
+
+pub fn describe_tampered(
+    dump: u16,
+    before: &EthernetPacket,
+    after: Option<&EthernetPacket>,
+) -> Vec<String> {
+    let mut out = vec![" >> before".to_string()];
+    out.extend(describe_frame(dump, before));
+
+    match after {
+        Some(after) => {
+            out.push(" >> after".to_string());
+            out.extend(describe_frame(dump, after));
         }
+        None => out.push(" >> after: dropped".to_string()),
+    }
+
+    out
+}
+
+/// Returns a one line summary of the frame, eg "10.0.0.1:5000 -> 10.0.0.2:80 TCP 60 bytes"
+pub fn summarize_frame(eth: &EthernetPacket) -> String {
+    let packet = match Ipv4Packet::new(eth.payload()) {
+        Some(packet) => packet,
+        None => return "invalid ipv4 packet".to_string(),
+    };
+
+    let fragment = match fragment_offset(&packet) {
+        Some(offset) => format!(" fragment offset {}", offset),
+        None => String::new(),
+    };
+
+    let ports = |ports| match packet.get_fragment_offset() {
+        0 => ports,
+        _ => None,
+    };
+
+    let (protocol, ports) = match packet.get_next_level_protocol() {
+        IpNextHeaderProtocols::Tcp => (
+            "TCP",
+            ports(TcpPacket::new(packet.payload()).map(|i| (i.get_source(), i.get_destination()))),
+        ),
+        IpNextHeaderProtocols::Udp => (
+            "UDP",
+            ports(UdpPacket::new(packet.payload()).map(|i| (i.get_source(), i.get_destination()))),
+        ),
+        IpNextHeaderProtocols::Icmp => ("ICMP", None),
+        _ => ("IP", None),
+    };
+
+    match ports {
+        Some((src, dst)) => format!(
+            "{}:{} -> {}:{} {} {} bytes{}",
+            packet.get_source(),
+            src,
+            packet.get_destination(),
+            dst,
+            protocol,
+            packet.get_total_length(),
+            fragment
+        ),
+        None => format!(
+            "{} -> {} {} {} bytes{}",
+            packet.get_source(),
+            packet.get_destination(),
+            protocol,
+            packet.get_total_length(),
+            fragment
+        ),
+    }
+}
+
+fn print_lines(lines: Vec<String>) {
+    for line in lines {
+        println!("{}", line);
+    }
+}
+
+fn describe_frame_into(
+    dump: u16,
+    eth: &EthernetPacket,
+    out: &mut Vec<String>,
+) -> Result<(), String> {
+    let packet = Ipv4Packet::new(eth.payload()).ok_or("invalid ipv4 packet")?;
+
+    out.push(format!(
+        "IP   | src ip: {} | dst ip: {} | checksum: {} |",
+        packet.get_source(),
+        packet.get_destination(),
+        format_checksum(packet.get_checksum(), ipv4::checksum(&packet))
+    ));
+
+    if let Some(offset) = fragment_offset(&packet) {
+        let more_fragments = packet.get_flags() & Ipv4Flags::MoreFragments != 0;
+        out.push(format!(
+            "FRAG | id: {} | offset: {} | more fragments: {} |",
+            packet.get_identification(),
+            offset,
+            if more_fragments { "yes" } else { "no" }
+        ));
+    }
+
+    // Only the first fragment carries the transport header
+    if dump >= 2 && packet.get_fragment_offset() == 0 {
+        dump_transport(dump, &packet, out)?;
+    }
+
+    if dump >= 4 {
+        dump_hex(eth, &packet, out);
+    }
+
+    Ok(())
+}
+
+fn dump_transport(dump: u16, packet: &Ipv4Packet, out: &mut Vec<String>) -> Result<(), String> {
+    // The transport checksum covers the whole packet so can only be checked once reassembled
+    let fragment = fragment_offset(packet).is_some();
+
+    let payload = match packet.get_next_level_protocol() {
+        IpNextHeaderProtocols::Tcp => dump_tcp_header(
+            packet,
+            TcpPacket::new(packet.payload()).ok_or("invalid tcp packet")?,
+            fragment,
+            out,
+        ),
+        IpNextHeaderProtocols::Udp => dump_udp_header(
+            packet,
+            UdpPacket::new(packet.payload()).ok_or("invalid udp packet")?,
+            fragment,
+            out,
+        ),
+        IpNextHeaderProtocols::Icmp => dump_icmp_header(
+            IcmpPacket::new(packet.payload()).ok_or("invalid icmp packet")?,
+            fragment,
+            out,
+        ),
         _ => {
-            println!("Unknown transport protocol");
+            out.push("Unknown transport protocol".to_string());
             return Ok(());
         }
     };
 
-    if args.dump == 2 {
-        return Ok(());
+    if dump >= 3 {
+        out.push(escape_payload(payload.as_slice()));
     }
 
-    dump_app_payload(payload.as_slice());
     Ok(())
 }
 
-fn dump_tcp_header<'a>(packet: TcpPacket<'a>) -> Vec<u8> {
-    println!(
-        "TCP  | src port: {} | dest port: {} |",
+fn dump_tcp_header(
+    ip: &Ipv4Packet,
+    packet: TcpPacket,
+    fragment: bool,
+    out: &mut Vec<String>,
+) -> Vec<u8> {
+    let checksum = if fragment {
+        FRAGMENT_CHECKSUM.to_string()
+    } else {
+        format_checksum(
+            packet.get_checksum(),
+            tcp::ipv4_checksum(&packet, &ip.get_source(), &ip.get_destination()),
+        )
+    };
+
+    out.push(format!(
+        "TCP  | src port: {} | dest port: {} | checksum: {} |",
         packet.get_source(),
-        packet.get_destination()
-    );
+        packet.get_destination(),
+        checksum
+    ));
     packet.payload().to_vec()
 }
 
-fn dump_udp_header<'a>(packet: UdpPacket<'a>) -> Vec<u8> {
-    println!(
-        "UDP  | src port: {} | dest port: {} |",
+fn dump_udp_header(
+    ip: &Ipv4Packet,
+    packet: UdpPacket,
+    fragment: bool,
+    out: &mut Vec<String>,
+) -> Vec<u8> {
+    // A zero checksum indicates the sender did not calculate one
+    let checksum = match packet.get_checksum() {
+        0 => "none".to_string(),
+        _ if fragment => FRAGMENT_CHECKSUM.to_string(),
+        actual => format_checksum(
+            actual,
+            udp::ipv4_checksum(&packet, &ip.get_source(), &ip.get_destination()),
+        ),
+    };
+
+    out.push(format!(
+        "UDP  | src port: {} | dest port: {} | checksum: {} |",
         packet.get_source(),
-        packet.get_destination()
-    );
+        packet.get_destination(),
+        checksum
+    ));
     packet.payload().to_vec()
 }
 
-fn dump_icmp_header<'a>(packet: IcmpPacket<'a>) -> Vec<u8> {
+fn dump_icmp_header(packet: IcmpPacket, fragment: bool, out: &mut Vec<String>) -> Vec<u8> {
     let icmp_type = match packet.get_icmp_type() {
         IcmpTypes::EchoRequest => "Echo Request",
         IcmpTypes::EchoReply => "Echo Reply",
@@ -72,18 +279,117 @@ fn dump_icmp_header<'a>(packet: IcmpPacket<'a>) -> Vec<u8> {
         IcmpTypes::Traceroute => "Traceroute",
         _ => "Other",
     };
-    println!("ICMP | type: {} |", icmp_type);
+    let checksum = if fragment {
+        FRAGMENT_CHECKSUM.to_string()
+    } else {
+        format_checksum(packet.get_checksum(), icmp::checksum(&packet))
+    };
+
+    out.push(format!(
+        "ICMP | type: {} | checksum: {} |",
+        icmp_type, checksum
+    ));
     packet.payload().to_vec()
 }
 
-fn dump_app_payload(payload: &[u8]) {
-    let escaped = String::from_utf8(
-        payload
-            .iter()
-            .map(|i| escape_default(*i))
-            .flatten()
-            .collect(),
-    ).unwrap();
+pub fn escape_payload(payload: &[u8]) -> String {
+    String::from_utf8(payload.iter().flat_map(|i| escape_default(*i)).collect()).unwrap()
+}
+
+/// Returns the offset in bytes of the fragment, none if the packet is not a fragment
+fn fragment_offset(packet: &Ipv4Packet) -> Option<usize> {
+    let more_fragments = packet.get_flags() & Ipv4Flags::MoreFragments != 0;
+
+    match packet.get_fragment_offset() {
+        0 if !more_fragments => None,
+        offset => Some(offset as usize * 8),
+    }
+}
+
+fn format_checksum(actual: u16, expected: u16) -> String {
+    if actual == expected {
+        format!("0x{:04x} (ok)", actual)
+    } else {
+        format!("0x{:04x} (BAD, expected 0x{:04x})", actual, expected)
+    }
+}
+
+/// Prints an offset/hex/ascii dump of the entire frame, split at each header boundary
+fn dump_hex(eth: &EthernetPacket, ip: &Ipv4Packet, out: &mut Vec<String>) {
+    let frame = eth.packet();
+
+    let ip_start = ETHERNET_HEADER_LEN;
+    let ip_header_end = ip_start + ip.get_header_length() as usize * 4;
+    let ip_end = ip_start + ip.get_total_length() as usize;
+
+    let transport = match ip.get_next_level_protocol() {
+        _ if ip.get_fragment_offset() > 0 => None,
+        IpNextHeaderProtocols::Tcp => {
+            TcpPacket::new(ip.payload()).map(|i| ("tcp header", i.get_data_offset() as usize * 4))
+        }
+        IpNextHeaderProtocols::Udp => Some(("udp header", UDP_HEADER_LEN)),
+        IpNextHeaderProtocols::Icmp => Some(("icmp header", ICMP_HEADER_LEN)),
+        _ => None,
+    };
+
+    let mut sections = vec![
+        ("ethernet header", 0, ip_start),
+        ("ipv4 header", ip_start, ip_header_end),
+    ];
+
+    match transport {
+        Some((name, len)) => {
+            sections.push((name, ip_header_end, ip_header_end + len));
+            sections.push(("payload", ip_header_end + len, ip_end));
+        }
+        None => sections.push(("ipv4 payload", ip_header_end, ip_end)),
+    }
+
+    sections.push(("trailer", ip_end, frame.len()));
+
+    out.push(format!("HEX  | frame length: {} |", frame.len()));
+
+    for (name, start, end) in sections {
+        // Clamp each section to the frame in case of truncated or malformed packets
+        let end = end.min(frame.len());
+        let start = start.min(end);
+
+        if start == end {
+            continue;
+        }
+
+        out.push(format!("---- {} ({} bytes)", name, end - start));
+
+        for offset in (start..end).step_by(16) {
+            out.push(format_hex_line(
+                offset,
+                &frame[offset..end.min(offset + 16)],
+            ));
+        }
+    }
+}
+
+fn format_hex_line(offset: usize, bytes: &[u8]) -> String {
+    let hex = (0..16)
+        .map(|i| match bytes.get(i) {
+            Some(b) => format!("{:02x}", b),
+            None => "  ".to_string(),
+        })
+        .collect::<Vec<_>>();
+
+    let ascii = bytes
+        .iter()
+        .map(|i| match *i {
+            0x20..=0x7e => *i as char,
+            _ => '.',
+        })
+        .collect::<String>();
 
-    println!("{}", escaped);
+    format!(
+        "{:04x}  {}  {}  |{}|",
+        offset,
+        hex[..8].join(" "),
+        hex[8..].join(" "),
+        ascii
+    )
 }
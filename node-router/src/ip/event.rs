@@ -4,5 +4,8 @@ use pnet::packet::ethernet::EthernetPacket;
 pub enum Event {
     PacketReceived(EthernetPacket<'static>),
     SendPacket(EthernetPacket<'static>),
-    Terminate(Result<()>)
-}
\ No newline at end of file
+    DelayedPacket(EthernetPacket<'static>),
+    PrintStreams,
+    SendProbe(u32),
+    Terminate(Result<()>),
+}
@@ -0,0 +1,375 @@
+use std::{net::Ipv4Addr, str::FromStr};
+
+use pnet::ipnetwork::Ipv4Network;
+use pnet::packet::ip::{IpNextHeaderProtocol, IpNextHeaderProtocols};
+use pnet::packet::ipv4::Ipv4Packet;
+use pnet::packet::tcp::TcpPacket;
+use pnet::packet::udp::UdpPacket;
+use pnet::packet::Packet;
+
+/// A tcpdump-like packet filter expression, eg `tcp port 80 and not src host 10.0.0.5`.
+///
+/// Supported primitives are `ip`, `icmp`, `tcp`, `udp`, `[src|dst] host <ip>`,
+/// `[src|dst] net <cidr>` and `[src|dst] port <port>`, which can be combined
+/// using `and`/`&&`, `or`/`||`, `not`/`!` and parentheses.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Filter {
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+    Not(Box<Filter>),
+    Ip,
+    Protocol(IpNextHeaderProtocol),
+    Host(Direction, Ipv4Addr),
+    Net(Direction, Ipv4Network),
+    Port(Direction, u16),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Src,
+    Dst,
+    Any,
+}
+
+impl Filter {
+    pub fn matches(&self, packet: &Ipv4Packet) -> bool {
+        match self {
+            Filter::And(l, r) => l.matches(packet) && r.matches(packet),
+            Filter::Or(l, r) => l.matches(packet) || r.matches(packet),
+            Filter::Not(f) => !f.matches(packet),
+            Filter::Ip => true,
+            Filter::Protocol(p) => packet.get_next_level_protocol() == *p,
+            Filter::Host(dir, ip) => {
+                dir.matches(packet.get_source(), packet.get_destination(), |i| i == *ip)
+            }
+            Filter::Net(dir, net) => {
+                dir.matches(packet.get_source(), packet.get_destination(), |i| {
+                    net.contains(i)
+                })
+            }
+            Filter::Port(dir, port) => match get_ports(packet) {
+                Some((src, dst)) => dir.matches(src, dst, |i| i == *port),
+                None => false,
+            },
+        }
+    }
+}
+
+impl Direction {
+    fn matches<T, F>(self, src: T, dst: T, f: F) -> bool
+    where
+        F: Fn(T) -> bool,
+    {
+        match self {
+            Direction::Src => f(src),
+            Direction::Dst => f(dst),
+            Direction::Any => f(src) || f(dst),
+        }
+    }
+}
+
+fn get_ports(packet: &Ipv4Packet) -> Option<(u16, u16)> {
+    match packet.get_next_level_protocol() {
+        // Only the first fragment carries the transport header
+        _ if packet.get_fragment_offset() > 0 => None,
+        IpNextHeaderProtocols::Tcp => {
+            TcpPacket::new(packet.payload()).map(|i| (i.get_source(), i.get_destination()))
+        }
+        IpNextHeaderProtocols::Udp => {
+            UdpPacket::new(packet.payload()).map(|i| (i.get_source(), i.get_destination()))
+        }
+        _ => None,
+    }
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(s);
+        let mut parser = Parser { tokens, pos: 0 };
+
+        let filter = parser.parse_or()?;
+
+        if let Some(token) = parser.peek() {
+            return Err(format!("unexpected token '{}' in filter", token));
+        }
+
+        Ok(filter)
+    }
+}
+
+fn tokenize(s: &str) -> Vec<String> {
+    let mut tokens = vec![];
+    let mut cur = String::new();
+    let mut chars = s.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        let op = match c {
+            '(' | ')' | '!' => Some(c.to_string()),
+            '&' | '|' if chars.peek() == Some(&c) => {
+                chars.next();
+                Some(format!("{}{}", c, c))
+            }
+            _ => None,
+        };
+
+        if op.is_some() || c.is_whitespace() {
+            if !cur.is_empty() {
+                tokens.push(cur.to_lowercase());
+                cur = String::new();
+            }
+        } else {
+            cur.push(c);
+        }
+
+        if let Some(op) = op {
+            tokens.push(op);
+        }
+    }
+
+    if !cur.is_empty() {
+        tokens.push(cur.to_lowercase());
+    }
+
+    tokens
+}
+
+struct Parser {
+    tokens: Vec<String>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(|i| i.as_str())
+    }
+
+    fn next(&mut self) -> Result<String, String> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| "unexpected end of filter".to_string())?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn parse_or(&mut self) -> Result<Filter, String> {
+        let mut filter = self.parse_and()?;
+
+        while let Some("or") | Some("||") = self.peek() {
+            self.pos += 1;
+            filter = Filter::Or(Box::new(filter), Box::new(self.parse_and()?));
+        }
+
+        Ok(filter)
+    }
+
+    fn parse_and(&mut self) -> Result<Filter, String> {
+        let mut filter = self.parse_not()?;
+
+        while let Some("and") | Some("&&") = self.peek() {
+            self.pos += 1;
+            filter = Filter::And(Box::new(filter), Box::new(self.parse_not()?));
+        }
+
+        Ok(filter)
+    }
+
+    fn parse_not(&mut self) -> Result<Filter, String> {
+        if let Some("not") | Some("!") = self.peek() {
+            self.pos += 1;
+            return Ok(Filter::Not(Box::new(self.parse_not()?)));
+        }
+
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Filter, String> {
+        let token = self.next()?;
+
+        let protocol = match token.as_str() {
+            "(" => {
+                let filter = self.parse_or()?;
+                return match self.next()?.as_str() {
+                    ")" => Ok(filter),
+                    t => Err(format!("expected ')' but found '{}'", t)),
+                };
+            }
+            "ip" => Filter::Ip,
+            "icmp" => Filter::Protocol(IpNextHeaderProtocols::Icmp),
+            "tcp" => Filter::Protocol(IpNextHeaderProtocols::Tcp),
+            "udp" => Filter::Protocol(IpNextHeaderProtocols::Udp),
+            _ => {
+                self.pos -= 1;
+                return self.parse_qualified();
+            }
+        };
+
+        // Like tcpdump, a protocol can be directly followed by a qualifier,
+        // eg "tcp port 80" is shorthand for "tcp and port 80"
+        match self.peek() {
+            Some("src") | Some("dst") | Some("host") | Some("net") | Some("port") => Ok(
+                Filter::And(Box::new(protocol), Box::new(self.parse_qualified()?)),
+            ),
+            _ => Ok(protocol),
+        }
+    }
+
+    fn parse_qualified(&mut self) -> Result<Filter, String> {
+        let dir = match self.peek() {
+            Some("src") => Direction::Src,
+            Some("dst") => Direction::Dst,
+            _ => Direction::Any,
+        };
+
+        if dir != Direction::Any {
+            self.pos += 1;
+        }
+
+        let token = self.next()?;
+
+        match token.as_str() {
+            "host" => Ok(Filter::Host(dir, parse_value(&self.next()?, "ip address")?)),
+            "net" => Ok(Filter::Net(dir, parse_value(&self.next()?, "network")?)),
+            "port" => Ok(Filter::Port(dir, parse_value(&self.next()?, "port")?)),
+            // Allow the host keyword to be omitted, eg "src 10.0.0.5"
+            _ if token.parse::<Ipv4Addr>().is_ok() => Ok(Filter::Host(dir, token.parse().unwrap())),
+            _ => Err(format!("unknown filter primitive '{}'", token)),
+        }
+    }
+}
+
+fn parse_value<T: FromStr>(token: &str, kind: &str) -> Result<T, String> {
+    token
+        .parse::<T>()
+        .map_err(|_| format!("invalid {} '{}' in filter", kind, token))
+}
+
+#[cfg(test)]
+mod tests {
+    use pnet::packet::ipv4::MutableIpv4Packet;
+    use pnet::packet::tcp::MutableTcpPacket;
+
+    use super::*;
+
+    fn parse(s: &str) -> Filter {
+        s.parse().unwrap()
+    }
+
+    fn parse_err(s: &str) -> String {
+        s.parse::<Filter>().unwrap_err()
+    }
+
+    fn tcp_packet(src: Ipv4Addr, dst: Ipv4Addr, src_port: u16, dst_port: u16) -> Vec<u8> {
+        let mut buf = vec![0u8; 40];
+        let mut ip = MutableIpv4Packet::new(&mut buf).unwrap();
+        ip.set_version(4);
+        ip.set_header_length(5);
+        ip.set_total_length(40);
+        ip.set_next_level_protocol(IpNextHeaderProtocols::Tcp);
+        ip.set_source(src);
+        ip.set_destination(dst);
+
+        let mut tcp = MutableTcpPacket::new(&mut buf[20..]).unwrap();
+        tcp.set_source(src_port);
+        tcp.set_destination(dst_port);
+        tcp.set_data_offset(5);
+
+        buf
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(
+            parse("icmp or udp and not tcp"),
+            Filter::Or(
+                Box::new(Filter::Protocol(IpNextHeaderProtocols::Icmp)),
+                Box::new(Filter::And(
+                    Box::new(Filter::Protocol(IpNextHeaderProtocols::Udp)),
+                    Box::new(Filter::Not(Box::new(Filter::Protocol(
+                        IpNextHeaderProtocols::Tcp
+                    )))),
+                )),
+            )
+        );
+        assert_eq!(
+            parse("icmp || udp && ! tcp"),
+            parse("icmp or udp and not tcp")
+        );
+        assert_eq!(
+            parse("(icmp or udp) and tcp"),
+            Filter::And(
+                Box::new(Filter::Or(
+                    Box::new(Filter::Protocol(IpNextHeaderProtocols::Icmp)),
+                    Box::new(Filter::Protocol(IpNextHeaderProtocols::Udp)),
+                )),
+                Box::new(Filter::Protocol(IpNextHeaderProtocols::Tcp)),
+            )
+        );
+    }
+
+    #[test]
+    fn protocol_can_be_followed_by_qualifier() {
+        assert_eq!(
+            parse("tcp port 80"),
+            Filter::And(
+                Box::new(Filter::Protocol(IpNextHeaderProtocols::Tcp)),
+                Box::new(Filter::Port(Direction::Any, 80)),
+            )
+        );
+        assert_eq!(
+            parse("udp dst net 10.0.0.0/8"),
+            Filter::And(
+                Box::new(Filter::Protocol(IpNextHeaderProtocols::Udp)),
+                Box::new(Filter::Net(Direction::Dst, "10.0.0.0/8".parse().unwrap())),
+            )
+        );
+    }
+
+    #[test]
+    fn host_keyword_can_be_omitted() {
+        let ip = Ipv4Addr::new(10, 0, 0, 5);
+
+        assert_eq!(parse("src 10.0.0.5"), Filter::Host(Direction::Src, ip));
+        assert_eq!(parse("10.0.0.5"), Filter::Host(Direction::Any, ip));
+        assert_eq!(parse("src 10.0.0.5"), parse("SRC HOST 10.0.0.5"));
+    }
+
+    #[test]
+    fn reports_invalid_filters() {
+        assert_eq!(parse_err(""), "unexpected end of filter");
+        assert_eq!(parse_err("tcp and"), "unexpected end of filter");
+        assert_eq!(parse_err("tcp udp"), "unexpected token 'udp' in filter");
+        assert_eq!(parse_err("(tcp udp"), "expected ')' but found 'udp'");
+        assert_eq!(parse_err("arp"), "unknown filter primitive 'arp'");
+        assert_eq!(parse_err("port 70000"), "invalid port '70000' in filter");
+        assert_eq!(
+            parse_err("host 10.0.0"),
+            "invalid ip address '10.0.0' in filter"
+        );
+        assert_eq!(
+            parse_err("net 10.0.0.0/33"),
+            "invalid network '10.0.0.0/33' in filter"
+        );
+    }
+
+    #[test]
+    fn matches_packets() {
+        let buf = tcp_packet(
+            Ipv4Addr::new(10, 0, 0, 5),
+            Ipv4Addr::new(10, 0, 1, 7),
+            40000,
+            80,
+        );
+        let packet = Ipv4Packet::new(&buf).unwrap();
+
+        assert!(parse("tcp port 80").matches(&packet));
+        assert!(parse("src host 10.0.0.5 and dst port 80").matches(&packet));
+        assert!(parse("dst net 10.0.1.0/24").matches(&packet));
+        assert!(!parse("src port 80").matches(&packet));
+        assert!(!parse("udp or dst 10.0.0.5").matches(&packet));
+        assert!(!parse("tcp port 80 and not src host 10.0.0.5").matches(&packet));
+    }
+}
@@ -0,0 +1,476 @@
+use std::{
+    collections::BTreeMap,
+    net::Ipv4Addr,
+    sync::{atomic::Ordering, mpsc::Sender},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use pnet::{
+    datalink::NetworkInterface,
+    packet::ethernet::EthernetPacket,
+    packet::icmp::{echo_reply::EchoReplyPacket, IcmpPacket, IcmpTypes},
+    packet::ip::{IpNextHeaderProtocol, IpNextHeaderProtocols},
+    packet::ipv4::Ipv4Packet,
+    packet::tcp::{TcpFlags, TcpPacket},
+    packet::udp::UdpPacket,
+    packet::Packet,
+};
+
+use crate::{
+    args::{Args, Command},
+    chain,
+    state::SharedState,
+};
+
+use super::arp;
+use super::builder::{
+    build_echo_request, build_ethernet, build_ipv4, build_ipv4_with_ttl, build_tcp_syn, build_udp,
+};
+use super::event::Event;
+
+const ARP_ATTEMPTS: u32 = 5;
+const ARP_INTERVAL: Duration = Duration::from_secs(1);
+/// How long to wait for replies after the last probe was sent
+const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+/// Udp and tcp probes are told apart by their source port
+const SRC_PORT_BASE: u16 = 49152;
+const SRC_PORT_RANGE: u32 = 16384;
+const ICMP_HEADER_LEN: usize = 8;
+/// Trace probes are sent quickly so the whole path is probed in a few seconds
+const TRACE_RATE: f64 = 20.0;
+const TRACE_PAYLOAD_LEN: usize = 32;
+
+/// Sends probe packets to another node and matches up the replies to report rtt and loss
+pub struct Generator {
+    command: Command,
+    local_ip: Ipv4Addr,
+    identifier: u16,
+    /// Probes awaiting a reply keyed by the icmp sequence number or source port
+    pending: BTreeMap<u16, (u32, Instant)>,
+    sent: u32,
+    /// Probes the interface failed to send, which are not counted as lost
+    failed: u32,
+    rtts: Vec<Duration>,
+    /// The hop which answered each trace probe keyed by probe number
+    hops: BTreeMap<u32, (Ipv4Addr, Duration)>,
+}
+
+impl Generator {
+    pub fn new(command: &Command, local_ip: Ipv4Addr) -> Self {
+        Self {
+            command: command.clone(),
+            local_ip,
+            identifier: std::process::id() as u16,
+            pending: BTreeMap::new(),
+            sent: 0,
+            failed: 0,
+            rtts: vec![],
+            hops: BTreeMap::new(),
+        }
+    }
+
+    /// Builds the frame for probe `seq`, none if the gateway's mac is not known
+    pub fn build_probe(
+        &mut self,
+        seq: u32,
+        interface: &NetworkInterface,
+        state: &SharedState,
+    ) -> Option<EthernetPacket<'static>> {
+        let dest = self.command.dest();
+        let gateway_mac = state.get(|s| s.arp.get(&self.command.gateway()).cloned())?;
+        let src_mac = interface.mac?;
+
+        let key = self.probe_key(seq);
+        let payload = |size: usize| (0..size).map(|i| i as u8).collect::<Vec<_>>();
+
+        let ip = match &self.command {
+            Command::Ping(args) => build_ipv4(
+                self.local_ip,
+                dest,
+                IpNextHeaderProtocols::Icmp,
+                &build_echo_request(self.identifier, key, &payload(args.size)),
+            ),
+            Command::Udp(args) => build_ipv4(
+                self.local_ip,
+                dest,
+                IpNextHeaderProtocols::Udp,
+                &build_udp(
+                    self.local_ip,
+                    dest,
+                    key,
+                    args.port,
+                    &payload(args.traffic.size),
+                ),
+            ),
+            Command::Syn(args) => build_ipv4(
+                self.local_ip,
+                dest,
+                IpNextHeaderProtocols::Tcp,
+                &build_tcp_syn(self.local_ip, dest, key, args.port, rand::random()),
+            ),
+            Command::Trace(args) => build_ipv4_with_ttl(
+                self.local_ip,
+                dest,
+                IpNextHeaderProtocols::Icmp,
+                (seq / args.queries as u32 + 1) as u8,
+                &build_echo_request(self.identifier, key, &payload(TRACE_PAYLOAD_LEN)),
+            ),
+        };
+
+        self.pending.insert(key, (seq, Instant::now()));
+        self.sent += 1;
+
+        Some(build_ethernet(src_mac, gateway_mac, &ip))
+    }
+
+    /// Forgets probe `seq` after the interface failed to send it
+    pub fn send_failed(&mut self, seq: u32) {
+        if self.pending.remove(&self.probe_key(seq)).is_some() {
+            self.sent -= 1;
+            self.failed += 1;
+        }
+    }
+
+    /// Reports the packet if it is a reply to one of the probes
+    pub fn process_packet(&mut self, ip: &Ipv4Packet) {
+        if ip.get_destination() != self.local_ip {
+            return;
+        }
+
+        let (key, description) = match self.match_reply(ip) {
+            Some(reply) => reply,
+            None => return,
+        };
+
+        let (seq, sent) = match self.pending.remove(&key) {
+            Some(probe) => probe,
+            None => {
+                log::debug!("ignoring duplicate or late reply from {}", ip.get_source());
+                return;
+            }
+        };
+
+        let rtt = sent.elapsed();
+        self.rtts.push(rtt);
+
+        if let Command::Trace(_) = self.command {
+            self.hops.insert(seq, (ip.get_source(), rtt));
+            return;
+        }
+
+        println!(
+            "{} from {}: seq={} ttl={} time={:.3} ms",
+            description,
+            ip.get_source(),
+            seq,
+            ip.get_ttl(),
+            as_millis(rtt)
+        );
+    }
+
+    pub fn print_summary(&self, state: &SharedState) {
+        if let Command::Trace(args) = &self.command {
+            self.print_hops(args.queries as u32, state);
+            return;
+        }
+
+        let received = self.rtts.len() as u32;
+        let loss = if self.sent == 0 {
+            0.0
+        } else {
+            (self.sent - received) as f64 / self.sent as f64 * 100.0
+        };
+
+        println!("--- {} {} statistics ---", self.command.dest(), self.name());
+        println!(
+            "{} probes sent, {} answered, {:.1}% loss",
+            self.sent, received, loss
+        );
+
+        if self.failed > 0 {
+            println!(
+                "{} probes could not be sent, they may be larger than the interface mtu",
+                self.failed
+            );
+        }
+
+        if received > 0 {
+            let min = self.rtts.iter().min().cloned().unwrap_or_default();
+            let max = self.rtts.iter().max().cloned().unwrap_or_default();
+            let avg = self.rtts.iter().sum::<Duration>() / received;
+            println!(
+                "rtt min/avg/max = {:.3}/{:.3}/{:.3} ms",
+                as_millis(min),
+                as_millis(avg),
+                as_millis(max)
+            );
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self.command {
+            Command::Ping(_) => "ping",
+            Command::Udp(_) => "udp",
+            Command::Syn(_) => "syn",
+            Command::Trace(_) => "trace",
+        }
+    }
+
+    /// Prints the node which answered each probe, one line per hop up to the destination
+    fn print_hops(&self, queries: u32, state: &SharedState) {
+        let dest = self.command.dest();
+        let names = state.get(|s| {
+            s.chain
+                .iter()
+                .map(|i| (i.ip, i.name.clone()))
+                .collect::<BTreeMap<_, _>>()
+        });
+        let describe = |ip: &Ipv4Addr| match names.get(ip) {
+            Some(name) => format!("{} ({})", ip, name),
+            None => ip.to_string(),
+        };
+
+        println!("trace to {}", describe(&dest));
+
+        for (hop, probes) in (0..self.sent)
+            .collect::<Vec<_>>()
+            .chunks(queries as usize)
+            .enumerate()
+        {
+            let mut line = format!("{:>2} ", hop + 1);
+            let mut last = None;
+
+            for seq in probes {
+                match self.hops.get(seq) {
+                    Some((ip, rtt)) => {
+                        if last != Some(ip) {
+                            line += &format!(" {}", describe(ip));
+                            last = Some(ip);
+                        }
+                        line += &format!("  {:.3} ms", as_millis(*rtt));
+                    }
+                    None => line += "  *",
+                }
+            }
+
+            println!("{}", line);
+
+            if last == Some(&dest) {
+                break;
+            }
+        }
+    }
+
+    fn probe_key(&self, seq: u32) -> u16 {
+        match self.command {
+            Command::Ping(_) | Command::Trace(_) => seq as u16,
+            _ => SRC_PORT_BASE + (seq % SRC_PORT_RANGE) as u16,
+        }
+    }
+
+    /// Returns the probe key and a description of the reply
+    fn match_reply(&self, ip: &Ipv4Packet) -> Option<(u16, String)> {
+        let protocol = ip.get_next_level_protocol();
+
+        if protocol == IpNextHeaderProtocols::Icmp {
+            return self.match_icmp_reply(ip);
+        }
+
+        if ip.get_source() != self.command.dest() {
+            return None;
+        }
+
+        match &self.command {
+            Command::Udp(args) if protocol == IpNextHeaderProtocols::Udp => {
+                let udp = UdpPacket::new(ip.payload())?;
+
+                if udp.get_source() != args.port {
+                    return None;
+                }
+
+                Some((
+                    udp.get_destination(),
+                    format!("{} bytes", udp.payload().len()),
+                ))
+            }
+            Command::Syn(args) if protocol == IpNextHeaderProtocols::Tcp => {
+                let tcp = TcpPacket::new(ip.payload())?;
+
+                if tcp.get_source() != args.port {
+                    return None;
+                }
+
+                let flags = tcp.get_flags();
+                let description = if flags & TcpFlags::RST != 0 {
+                    "RST"
+                } else if flags & (TcpFlags::SYN | TcpFlags::ACK) == TcpFlags::SYN | TcpFlags::ACK {
+                    "SYN-ACK"
+                } else {
+                    return None;
+                };
+
+                Some((tcp.get_destination(), description.to_string()))
+            }
+            _ => None,
+        }
+    }
+
+    fn match_icmp_reply(&self, ip: &Ipv4Packet) -> Option<(u16, String)> {
+        let icmp = IcmpPacket::new(ip.payload())?;
+        let icmp_type = icmp.get_icmp_type();
+
+        if icmp_type == IcmpTypes::EchoReply {
+            let echo = EchoReplyPacket::new(ip.payload())?;
+
+            if !matches!(self.command, Command::Ping(_) | Command::Trace(_))
+                || ip.get_source() != self.command.dest()
+                || echo.get_identifier() != self.identifier
+            {
+                return None;
+            }
+
+            return Some((
+                echo.get_sequence_number(),
+                format!("{} bytes", echo.payload().len()),
+            ));
+        }
+
+        let description = match icmp_type {
+            IcmpTypes::DestinationUnreachable => "destination unreachable",
+            IcmpTypes::TimeExceeded => "time exceeded",
+            _ => return None,
+        };
+
+        // Errors quote the ip header and the first 8 bytes of the probe
+        let quoted = icmp.packet().get(ICMP_HEADER_LEN..)?;
+        let header_len = (*quoted.first()? & 0x0f) as usize * 4;
+        let quoted_ip = Ipv4Packet::new(quoted)?;
+        let transport = quoted.get(header_len..)?;
+
+        if quoted_ip.get_source() != self.local_ip
+            || quoted_ip.get_destination() != self.command.dest()
+        {
+            return None;
+        }
+
+        let key = self.quoted_key(quoted_ip.get_next_level_protocol(), transport)?;
+
+        Some((key, description.to_string()))
+    }
+
+    /// Extracts the probe key from the start of a probe quoted in an icmp error
+    fn quoted_key(&self, protocol: IpNextHeaderProtocol, transport: &[u8]) -> Option<u16> {
+        let field = |offset: usize| {
+            transport
+                .get(offset..offset + 2)
+                .map(|i| u16::from_be_bytes([i[0], i[1]]))
+        };
+
+        match self.command {
+            Command::Ping(_) | Command::Trace(_) if protocol == IpNextHeaderProtocols::Icmp => {
+                if field(4)? != self.identifier {
+                    return None;
+                }
+                field(6)
+            }
+            Command::Udp(_) if protocol == IpNextHeaderProtocols::Udp => field(0),
+            Command::Syn(_) if protocol == IpNextHeaderProtocols::Tcp => field(0),
+            _ => None,
+        }
+    }
+}
+
+/// Resolves the gateway's mac address then asks the event loop to send each
+/// probe at the configured rate, stopping the node router once done.
+pub fn send_probes(
+    args: Args,
+    state: SharedState,
+    interface: NetworkInterface,
+    local_ip: Ipv4Addr,
+    tx: Sender<Event>,
+) {
+    let command = args.command.as_ref().unwrap();
+    let (dest, gateway) = (command.dest(), command.gateway());
+
+    let (count, rate) = match command {
+        Command::Ping(args) => (args.count, args.rate),
+        Command::Udp(args) | Command::Syn(args) => (args.traffic.count, args.traffic.rate),
+        Command::Trace(args) => (args.max_hops as u32 * args.queries as u32, TRACE_RATE),
+    };
+
+    if let Command::Trace(args) = command {
+        if args.queries == 0 {
+            tx.send(Event::Terminate(Err(anyhow!(
+                "queries must be greater than 0"
+            ))))
+            .unwrap();
+            return;
+        }
+    }
+
+    // A rate so small that the interval between probes overflows is rejected too
+    if !rate.is_finite() || rate <= 0.0 || !(1.0 / rate).is_finite() {
+        tx.send(Event::Terminate(Err(anyhow!(
+            "rate must be a positive number"
+        ))))
+        .unwrap();
+        return;
+    }
+
+    // Hops are named after the nodes in the chain
+    if let Command::Trace(_) = command {
+        if state.get(|s| s.chain.is_empty()) {
+            match chain::load(&args) {
+                Ok(chain) => state.update(|s| s.chain = chain),
+                Err(err) => log::warn!("failed to load node names: {}", err),
+            }
+        }
+    }
+
+    for _ in 0..ARP_ATTEMPTS {
+        if !state.running() || state.get(|s| s.arp.contains_key(&gateway)) {
+            break;
+        }
+
+        log::info!("sending arp request for gateway {}", gateway);
+        if let Some(request) = arp::build_request(&interface, local_ip, gateway) {
+            tx.send(Event::SendPacket(request)).unwrap();
+        }
+
+        thread::sleep(ARP_INTERVAL);
+    }
+
+    if !state.get(|s| s.arp.contains_key(&gateway)) {
+        tx.send(Event::Terminate(Err(anyhow!(
+            "could not resolve the mac address of gateway {}",
+            gateway
+        ))))
+        .unwrap();
+        return;
+    }
+
+    println!(
+        "sending {} probes to {} via {} at {} per second",
+        count, dest, gateway, rate
+    );
+
+    let interval = Duration::from_secs_f64(1.0 / rate);
+
+    for seq in 0..count {
+        if !state.running() {
+            return;
+        }
+
+        tx.send(Event::SendProbe(seq)).unwrap();
+        thread::sleep(interval);
+    }
+
+    thread::sleep(REPLY_TIMEOUT);
+    state.term_arc().store(true, Ordering::Relaxed);
+}
+
+fn as_millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
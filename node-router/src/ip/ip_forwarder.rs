@@ -1,24 +1,39 @@
 use std::{
+    cmp::Ordering,
     net::{IpAddr, Ipv4Addr},
     sync::mpsc::Sender,
+    thread,
+    time::Instant,
 };
 
 use pnet::{
     datalink::NetworkInterface,
     packet::ethernet::{EthernetPacket, MutableEthernetPacket},
-    packet::Packet,
+    packet::{MutablePacket, Packet},
+};
+use pnet::{
+    ipnetwork::IpNetwork,
+    packet::ip::IpNextHeaderProtocols,
+    packet::ipv4::{self, Ipv4Packet, MutableIpv4Packet},
 };
-use pnet::{ipnetwork::IpNetwork, packet::ipv4::Ipv4Packet};
 
-use crate::args::Args;
+use crate::{
+    args::Args,
+    state::{CapturedPacket, ChainNode, PeerCounters, SharedState},
+};
 
-use super::dumper::dump_packet;
+use super::builder::{build_ethernet, build_time_exceeded};
+use super::dumper::{dump_packet, dump_reassembled, dump_tampered, summarize_frame};
 use super::event::Event;
+use super::streams::StreamTracker;
 
 pub fn process_packet(
     args: &Args,
     tx: &mut Sender<Event>,
+    state: &SharedState,
+    streams: &mut StreamTracker,
     eth: EthernetPacket,
+    vlan: Option<u16>,
     interface: &NetworkInterface,
 ) {
     let ip = Ipv4Packet::new(eth.payload()).unwrap();
@@ -27,6 +42,13 @@ pub fn process_packet(
     let src_mac = eth.get_source();
 
     if interface.mac == Some(src_mac) {
+        // Packets we return to the sender are also seen here, only our own
+        // outgoing packets should be included in the tcp conversations
+        if is_local_ip(ip.get_source(), interface) {
+            count_packet(state, ip.get_destination(), &ip, |i| &mut i.sent);
+            track_stream(args, streams, &ip);
+        }
+
         log::trace!("packet is from target interface, ignoring");
         return;
     }
@@ -46,23 +68,291 @@ pub fn process_packet(
         return;
     }
 
-    let host_is_dest = interface.ips.iter().any(|i| i.ip() == IpAddr::V4(dest_ip));
-    if host_is_dest {
+    if is_local_ip(dest_ip, interface) {
         log::trace!("received packet from {} to localhost", src_ip);
-        let _ = dump_packet(args, &ip);
+        count_packet(state, src_ip, &ip, |i| &mut i.received);
+        dump_if_matches(args, state, &eth, &ip, vlan);
+        track_stream(args, streams, &ip);
         return;
     }
 
     log::trace!("received packet from {} to {}", src_ip, dest_ip);
 
-    if args.promisc {
-        let _ = dump_packet(args, &ip);
+    count_packet(state, src_ip, &ip, |i| &mut i.passed_on);
+
+    if state.get(|s| s.promisc) {
+        dump_if_matches(args, state, &eth, &ip, vlan);
+        track_stream(args, streams, &ip);
+    }
+
+    // Like a router, packets with a damaged header can be dropped rather than passed on
+    if args.drop_bad_checksums && ip.get_checksum() != ipv4::checksum(&ip) {
+        log::debug!(
+            "dropping packet from {} to {} with a bad header checksum",
+            src_ip,
+            dest_ip
+        );
+        return;
+    }
+
+    let eth = match tamper_packet(args, tx, state, &eth, &ip, vlan) {
+        Some(tampered) => tampered,
+        None => return,
+    };
+
+    pass_on(args, tx, state, interface, eth);
+}
+
+/// Passes on a packet which is not for us to the next hop.
+/// Each node counts as a hop so the ttl is decremented the same way a router would.
+pub fn pass_on(
+    args: &Args,
+    tx: &mut Sender<Event>,
+    state: &SharedState,
+    interface: &NetworkInterface,
+    eth: EthernetPacket,
+) {
+    let eth = match decrement_ttl(args, tx, state, interface, &eth) {
+        Some(eth) => eth,
+        None => return,
+    };
+
+    if args.forward {
+        forward_to_next_hop(tx, state, interface, eth);
+        return;
     }
 
     // This packet is not for us, return to the sender
     return_to_sender(tx, interface, eth);
 }
 
+/// Sets the ttl, adjusting the header checksum for the change (RFC 1624) rather than
+/// recalculating it so a header which arrived with a bad checksum is not repaired
+fn set_ttl(ip: &mut MutableIpv4Packet, ttl: u8) {
+    let protocol = ip.get_next_level_protocol().0;
+    let old = u16::from_be_bytes([ip.get_ttl(), protocol]);
+    let new = u16::from_be_bytes([ttl, protocol]);
+
+    let mut sum = (!ip.get_checksum()) as u32 + (!old) as u32 + new as u32;
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+
+    ip.set_ttl(ttl);
+    ip.set_checksum(!(sum as u16));
+}
+
+/// Returns the packet with its ttl decremented, or replies with an icmp
+/// time exceeded message if the ttl has expired
+fn decrement_ttl(
+    args: &Args,
+    tx: &mut Sender<Event>,
+    state: &SharedState,
+    interface: &NetworkInterface,
+    eth: &EthernetPacket,
+) -> Option<EthernetPacket<'static>> {
+    let ip = Ipv4Packet::new(eth.payload()).unwrap();
+
+    if ip.get_ttl() > 1 {
+        let mut new_eth = MutableEthernetPacket::owned(eth.packet().to_vec()).unwrap();
+        let mut new_ip = MutableIpv4Packet::new(new_eth.payload_mut()).unwrap();
+        set_ttl(&mut new_ip, ip.get_ttl() - 1);
+        return Some(new_eth.consume_to_immutable());
+    }
+
+    log::debug!(
+        "ttl expired for packet from {} to {}",
+        ip.get_source(),
+        ip.get_destination()
+    );
+
+    let local_ip = match get_local_ipv4(interface) {
+        Some(ip) => ip,
+        None => {
+            log::warn!("interface {} does not have an ipv4 address", interface.name);
+            return None;
+        }
+    };
+
+    let reply = build_time_exceeded(local_ip, &ip);
+
+    if args.forward && send_towards(tx, state, interface, local_ip, ip.get_source(), &reply) {
+        return None;
+    }
+
+    // Let the sender route the reply back to the source
+    if let Some(mac) = interface.mac {
+        let reply = build_ethernet(mac, eth.get_source(), &reply);
+        if let Err(err) = tx.send(Event::SendPacket(reply)) {
+            log::warn!("error while sending packet: {}", err);
+        }
+    }
+
+    None
+}
+
+/// Applies the tamper rules to the packet, returning the packet to pass on
+/// or none if it was dropped or will be passed on after a delay
+fn tamper_packet(
+    args: &Args,
+    tx: &mut Sender<Event>,
+    state: &SharedState,
+    eth: &EthernetPacket,
+    ip: &Ipv4Packet,
+    vlan: Option<u16>,
+) -> Option<EthernetPacket<'static>> {
+    let rules = match &args.tamper {
+        Some(rules) => rules,
+        None => return Some(EthernetPacket::owned(eth.packet().to_vec()).unwrap()),
+    };
+
+    let tampered = rules.apply(ip);
+
+    let new_eth = match &tampered.packet {
+        Some(packet) => build_ethernet(eth.get_source(), eth.get_destination(), packet),
+        None => EthernetPacket::owned(eth.packet().to_vec()).unwrap(),
+    };
+
+    if !tampered.actions.is_empty() {
+        log::debug!(
+            "tampered with packet from {} to {}: {}",
+            ip.get_source(),
+            ip.get_destination(),
+            tampered.actions.join(", ")
+        );
+
+        let after = if tampered.drop { None } else { Some(&new_eth) };
+
+        if args.tui {
+            state.update(|s| {
+                s.capture(CapturedPacket {
+                    id: 0,
+                    time: Instant::now(),
+                    summary: summarize_frame(eth),
+                    frame: eth.packet().to_vec(),
+                    vlan,
+                    tampered: Some((tampered.actions.clone(), after.map(|i| i.packet().to_vec()))),
+                })
+            });
+        } else {
+            dump_tampered(state.get(|s| s.dump), eth, vlan, after, &tampered.actions);
+        }
+    }
+
+    if tampered.drop {
+        return None;
+    }
+
+    if let Some(delay) = tampered.delay {
+        let tx = tx.clone();
+        thread::spawn(move || {
+            thread::sleep(delay);
+            let _ = tx.send(Event::DelayedPacket(new_eth));
+        });
+        return None;
+    }
+
+    Some(new_eth)
+}
+
+fn dump_if_matches(
+    args: &Args,
+    state: &SharedState,
+    eth: &EthernetPacket,
+    ip: &Ipv4Packet,
+    vlan: Option<u16>,
+) {
+    if matches_filter(args, ip) {
+        dump(args, state, eth, vlan, None);
+    } else {
+        log::trace!("packet does not match filter, not dumping");
+    }
+
+    if !args.tui && state.get(|s| s.dump) == 0 {
+        return;
+    }
+
+    // Later fragments do not have ports so the whole packet is filtered once reassembled
+    let mut reassembled = None;
+    state.update(|s| reassembled = s.reassembler.add(ip));
+
+    if let Some((packet, fragments)) = reassembled {
+        let eth = build_ethernet(eth.get_source(), eth.get_destination(), &packet);
+        let ip = Ipv4Packet::new(eth.payload()).unwrap();
+
+        if matches_filter(args, &ip) {
+            dump(args, state, &eth, vlan, Some(fragments));
+        }
+    }
+}
+
+/// Dumps the frame or captures it for the terminal ui, along with its
+/// vlan tag and the number of fragments if it was reassembled
+fn dump(
+    args: &Args,
+    state: &SharedState,
+    eth: &EthernetPacket,
+    vlan: Option<u16>,
+    fragments: Option<usize>,
+) {
+    if args.tui {
+        let summary = match fragments {
+            Some(fragments) => format!(
+                "{} (reassembled from {} fragments)",
+                summarize_frame(eth),
+                fragments
+            ),
+            None => summarize_frame(eth),
+        };
+
+        state.update(|s| {
+            s.capture(CapturedPacket {
+                id: 0,
+                time: Instant::now(),
+                summary,
+                frame: eth.packet().to_vec(),
+                vlan,
+                tampered: None,
+            })
+        });
+        return;
+    }
+
+    match fragments {
+        Some(fragments) => dump_reassembled(state.get(|s| s.dump), eth, vlan, fragments),
+        None => dump_packet(state.get(|s| s.dump), eth, vlan),
+    }
+}
+
+fn count_packet<F>(state: &SharedState, peer: Ipv4Addr, ip: &Ipv4Packet, f: F)
+where
+    F: FnOnce(&mut PeerCounters) -> &mut u64,
+{
+    state.update(|s| {
+        let counters = s.peers.entry(peer).or_default();
+        *f(counters) += 1;
+        counters.bytes += ip.get_total_length() as u64;
+    });
+}
+
+fn track_stream(args: &Args, streams: &mut StreamTracker, ip: &Ipv4Packet) {
+    if !args.streams || ip.get_next_level_protocol() != IpNextHeaderProtocols::Tcp {
+        return;
+    }
+
+    if matches_filter(args, ip) {
+        streams.process_packet(ip);
+    }
+}
+
+fn matches_filter(args: &Args, ip: &Ipv4Packet) -> bool {
+    args.filter.as_ref().map_or(true, |i| i.matches(ip))
+}
+
+fn is_local_ip(ip: Ipv4Addr, interface: &NetworkInterface) -> bool {
+    interface.ips.iter().any(|i| i.ip() == IpAddr::V4(ip))
+}
+
 fn is_in_local_net(dest_ip: Ipv4Addr, interface: &NetworkInterface) -> bool {
     interface
         .ips
@@ -77,7 +367,7 @@ fn is_in_local_net(dest_ip: Ipv4Addr, interface: &NetworkInterface) -> bool {
             if i.prefix() == 32 {
                 IpNetwork::new(i.ip(), 24).unwrap()
             } else {
-                i.clone()
+                *i
             }
         })
         .any(|i| i.contains(IpAddr::V4(dest_ip)))
@@ -102,3 +392,147 @@ fn return_to_sender(tx: &mut Sender<Event>, interface: &NetworkInterface, eth: E
         log::warn!("error while sending packet: {}", err);
     }
 }
+
+/// Forwards the packet directly to the next node along the chain towards its destination,
+/// returning it to the sender instead if the next hop is not known.
+fn forward_to_next_hop(
+    tx: &mut Sender<Event>,
+    state: &SharedState,
+    interface: &NetworkInterface,
+    eth: EthernetPacket,
+) {
+    let local_ip = match get_local_ipv4(interface) {
+        Some(ip) => ip,
+        None => {
+            log::warn!("interface {} does not have an ipv4 address", interface.name);
+            return;
+        }
+    };
+
+    let ip = Ipv4Packet::new(eth.payload()).unwrap();
+
+    if !send_towards(
+        tx,
+        state,
+        interface,
+        local_ip,
+        ip.get_destination(),
+        ip.packet(),
+    ) {
+        return_to_sender(tx, interface, eth);
+    }
+}
+
+/// Sends the ipv4 packet to the next node along the chain from this node towards `dest_ip`
+fn send_towards(
+    tx: &mut Sender<Event>,
+    state: &SharedState,
+    interface: &NetworkInterface,
+    local_ip: Ipv4Addr,
+    dest_ip: Ipv4Addr,
+    ip: &[u8],
+) -> bool {
+    let next_hop = state.get(|s| find_next_hop(&s.chain, local_ip, dest_ip).cloned());
+
+    let next_hop = match next_hop {
+        Some(node) => node,
+        None => {
+            log::debug!("could not find next hop from {} to {}", local_ip, dest_ip);
+            return false;
+        }
+    };
+
+    let (src_mac, dest_mac) = match (interface.mac, next_hop.mac) {
+        (Some(src), Some(dest)) => (src, dest),
+        _ => {
+            log::warn!(
+                "could not forward packet to next hop {} as mac is not known",
+                next_hop.name
+            );
+            return false;
+        }
+    };
+
+    log::trace!(
+        "forwarding packet to {} via next hop {}",
+        dest_ip,
+        next_hop.name
+    );
+    if let Err(err) = tx.send(Event::SendPacket(build_ethernet(src_mac, dest_mac, ip))) {
+        log::warn!("error while forwarding packet: {}", err);
+    }
+
+    true
+}
+
+/// Returns the next node along the chain from `from` which is one stop closer to `to`.
+/// The chain is defined by the order in which the nodes in the same group and subnet
+/// are listed by the central router, packets for other nodes are left to the central router.
+fn find_next_hop(chain: &[ChainNode], from: Ipv4Addr, to: Ipv4Addr) -> Option<&ChainNode> {
+    let local = chain.iter().find(|i| i.ip == from)?;
+    let chain = chain
+        .iter()
+        .filter(|i| {
+            i.group == local.group && i.interface == local.interface && i.vlan == local.vlan
+        })
+        .collect::<Vec<_>>();
+
+    let from_index = chain.iter().position(|i| i.ip == from)?;
+    let to_index = chain.iter().position(|i| i.ip == to)?;
+
+    let next_hop_index = match from_index.cmp(&to_index) {
+        Ordering::Less => from_index + 1,
+        Ordering::Greater => from_index - 1,
+        Ordering::Equal => return None,
+    };
+
+    Some(chain[next_hop_index])
+}
+
+pub fn get_local_ipv4(interface: &NetworkInterface) -> Option<Ipv4Addr> {
+    interface.ips.iter().find_map(|i| match i.ip() {
+        IpAddr::V4(ip) => Some(ip),
+        _ => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(ttl: u8) -> Vec<u8> {
+        let mut buf = vec![0u8; 20];
+        let mut ip = MutableIpv4Packet::new(&mut buf).unwrap();
+        ip.set_version(4);
+        ip.set_header_length(5);
+        ip.set_total_length(20);
+        ip.set_ttl(ttl);
+        ip.set_next_level_protocol(IpNextHeaderProtocols::Udp);
+        ip.set_source(Ipv4Addr::new(10, 0, 0, 5));
+        ip.set_destination(Ipv4Addr::new(10, 0, 1, 7));
+        ip.set_checksum(ipv4::checksum(&ip.to_immutable()));
+        buf
+    }
+
+    #[test]
+    fn set_ttl_keeps_good_checksum_valid() {
+        for ttl in [1, 2, 64, 128, 255] {
+            let mut buf = header(ttl);
+            let mut ip = MutableIpv4Packet::new(&mut buf).unwrap();
+            set_ttl(&mut ip, ttl - 1);
+
+            assert_eq!(ip.get_checksum(), ipv4::checksum(&ip.to_immutable()));
+        }
+    }
+
+    #[test]
+    fn set_ttl_keeps_bad_checksum_invalid() {
+        let mut buf = header(64);
+        let mut ip = MutableIpv4Packet::new(&mut buf).unwrap();
+        let bad = ip.get_checksum() ^ 0x0f0f;
+        ip.set_checksum(bad);
+        set_ttl(&mut ip, 63);
+
+        assert_ne!(ip.get_checksum(), ipv4::checksum(&ip.to_immutable()));
+    }
+}
@@ -1,8 +1,17 @@
-mod dumper;
+mod arp;
+mod builder;
+pub mod dumper;
 mod event;
+pub mod filter;
+mod generator;
 mod ip_forwarder;
+pub mod reassembly;
+mod streams;
+pub mod tamper;
+mod vlan;
 
 use std::{
+    io::{self, BufRead},
     sync::mpsc::{self, Sender},
     thread,
     time::Duration,
@@ -12,9 +21,12 @@ use anyhow::anyhow;
 use anyhow::{bail, Result};
 use datalink::NetworkInterface;
 use event::Event;
+use generator::Generator;
 use pnet::datalink::{self, Channel, DataLinkReceiver};
 use pnet::packet::ethernet::{EtherTypes, EthernetPacket};
+use pnet::packet::ipv4::Ipv4Packet;
 use pnet::{datalink::DataLinkSender, packet::Packet};
+use streams::StreamTracker;
 
 use crate::{args::Args, state::SharedState};
 
@@ -37,8 +49,7 @@ pub fn start(args: Args, mut state: SharedState) -> Result<()> {
             );
             i
         })
-        .filter(|i| i.name == args.interface)
-        .next()
+        .find(|i| i.name == args.interface)
         .ok_or(anyhow!("could not find interface named {}", args.interface))?;
 
     let (mut dtx, drx) = match datalink::channel(&interface, Default::default()) {
@@ -56,13 +67,76 @@ pub fn start(args: Args, mut state: SharedState) -> Result<()> {
         terminate_if_stopped(state, tx)
     });
 
+    if args.streams {
+        spawn(&tx, &state, &interface, |tx, _, _| {
+            print_streams_on_enter(tx)
+        });
+    }
+
+    let mut generator = None;
+
+    if let Some(command) = &args.command {
+        if args.tui {
+            bail!("the terminal ui cannot be used while sending traffic");
+        }
+
+        let local_ip = ip_forwarder::get_local_ipv4(&interface).ok_or(anyhow!(
+            "interface {} does not have an ipv4 address",
+            interface.name
+        ))?;
+        generator = Some(Generator::new(command, local_ip));
+
+        let args = args.clone();
+        spawn(&tx, &state, &interface, move |tx, state, interface| {
+            generator::send_probes(args, state, interface, local_ip, tx)
+        });
+    }
+
+    let mut streams = StreamTracker::new();
+
     loop {
         match rx.recv()? {
-            Event::PacketReceived(packet) => {
-                process_packet(&args, &mut tx, &mut state, packet, &interface)
+            Event::PacketReceived(packet) => process_packet(
+                &args,
+                &mut tx,
+                &mut state,
+                &mut streams,
+                &mut generator,
+                packet,
+                &interface,
+            ),
+            Event::SendPacket(packet) => {
+                send_packet(&mut dtx, &state, packet);
+            }
+            Event::DelayedPacket(packet) => {
+                ip_forwarder::pass_on(&args, &mut tx, &state, &interface, packet)
+            }
+            Event::PrintStreams => streams.print_open(),
+            Event::SendProbe(seq) => {
+                let probe = generator
+                    .as_mut()
+                    .and_then(|g| g.build_probe(seq, &interface, &state));
+
+                match probe {
+                    Some(probe) => {
+                        if !send_packet(&mut dtx, &state, probe) {
+                            if let Some(generator) = generator.as_mut() {
+                                generator.send_failed(seq);
+                            }
+                        }
+                    }
+                    None => log::warn!("could not send probe {}", seq),
+                }
+            }
+            Event::Terminate(res) => {
+                if args.streams {
+                    streams.print_open();
+                }
+                if let Some(generator) = &generator {
+                    generator.print_summary(&state);
+                }
+                break res?;
             }
-            Event::SendPacket(packet) => send_packet(&mut dtx, packet),
-            Event::Terminate(res) => break res?,
         }
     }
 
@@ -73,7 +147,7 @@ pub fn start(args: Args, mut state: SharedState) -> Result<()> {
 
 fn spawn<F>(tx: &mpsc::Sender<Event>, state: &SharedState, interface: &NetworkInterface, f: F)
 where
-    F: FnOnce(mpsc::Sender<Event>, SharedState, NetworkInterface) -> () + Send + 'static,
+    F: FnOnce(mpsc::Sender<Event>, SharedState, NetworkInterface) + Send + 'static,
 {
     let tx = tx.clone();
     let state = state.clone();
@@ -82,8 +156,33 @@ where
     thread::spawn(move || f(tx, state, interface));
 }
 
-fn send_packet(dtx: &mut Box<dyn DataLinkSender>, packet: EthernetPacket) {
-    dtx.send_to(packet.packet(), None);
+/// Sends the frame, returning false if the interface failed to send it, such as when it is
+/// larger than the interface's mtu
+fn send_packet(
+    dtx: &mut Box<dyn DataLinkSender>,
+    state: &SharedState,
+    packet: EthernetPacket,
+) -> bool {
+    let res = match state.get(|s| s.vlan) {
+        Some(vlan) => dtx.send_to(vlan::tag(&packet, vlan).packet(), None),
+        None => dtx.send_to(packet.packet(), None),
+    };
+
+    match res {
+        Some(Ok(())) => true,
+        Some(Err(err)) => {
+            log::warn!(
+                "failed to send {} byte packet: {}",
+                packet.packet().len(),
+                err
+            );
+            false
+        }
+        None => {
+            log::warn!("failed to send packet: no buffer available");
+            false
+        }
+    }
 }
 
 fn receive_packets(mut drx: Box<dyn DataLinkReceiver>, tx: mpsc::Sender<Event>) {
@@ -116,16 +215,53 @@ fn receive_packets(mut drx: Box<dyn DataLinkReceiver>, tx: mpsc::Sender<Event>)
 fn process_packet(
     args: &Args,
     tx: &mut Sender<Event>,
-    _state: &mut SharedState,
-    packet: EthernetPacket,
+    state: &mut SharedState,
+    streams: &mut StreamTracker,
+    generator: &mut Option<Generator>,
+    packet: EthernetPacket<'static>,
     interface: &NetworkInterface,
 ) {
+    let (packet, vlan) = match vlan::untag(packet) {
+        Some(untagged) => untagged,
+        None => {
+            log::warn!("failed to parse vlan tag");
+            return;
+        }
+    };
+
+    // Replies are tagged the same way as the frames from the central router, frames
+    // from other hosts on a trunk can be tagged differently
+    if args.vlan.is_none() && is_from_router(args, state, &packet) && state.get(|s| s.vlan != vlan)
+    {
+        log::debug!("frames are now tagged with vlan {:?}", vlan);
+        state.update(|s| s.vlan = vlan);
+    }
+
     match packet.get_ethertype() {
-        EtherTypes::Ipv4 => ip_forwarder::process_packet(args, tx, packet, interface),
+        EtherTypes::Ipv4 => {
+            if let Some(generator) = generator {
+                if interface.mac != Some(packet.get_source()) {
+                    if let Some(ip) = Ipv4Packet::new(packet.payload()) {
+                        generator.process_packet(&ip);
+                    }
+                }
+            }
+
+            ip_forwarder::process_packet(args, tx, state, streams, packet, vlan, interface)
+        }
+        EtherTypes::Arp => arp::process_packet(state, &packet),
         _ => {}
     }
 }
 
+/// Returns true if the frame was sent by the central router, whose mac is learnt from its arp packets
+fn is_from_router(args: &Args, state: &SharedState, eth: &EthernetPacket) -> bool {
+    match args.router_ip() {
+        Some(ip) => state.get(|s| s.arp.get(&ip) == Some(&eth.get_source())),
+        None => false,
+    }
+}
+
 fn terminate_if_stopped(state: SharedState, tx: mpsc::Sender<Event>) {
     while state.running() {
         thread::sleep(Duration::from_millis(500));
@@ -133,3 +269,11 @@ fn terminate_if_stopped(state: SharedState, tx: mpsc::Sender<Event>) {
 
     tx.send(Event::Terminate(Ok(()))).unwrap();
 }
+
+fn print_streams_on_enter(tx: mpsc::Sender<Event>) {
+    for line in io::stdin().lock().lines() {
+        if line.is_err() || tx.send(Event::PrintStreams).is_err() {
+            return;
+        }
+    }
+}
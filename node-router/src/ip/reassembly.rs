@@ -0,0 +1,134 @@
+use std::{
+    collections::BTreeMap,
+    net::Ipv4Addr,
+    time::{Duration, Instant},
+};
+
+use pnet::packet::{
+    ipv4::{self, Ipv4Flags, Ipv4Packet, MutableIpv4Packet},
+    Packet,
+};
+
+/// Fragments which have not been completed in this time are discarded, as in rfc 791
+const REASSEMBLY_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_PENDING_PACKETS: usize = 64;
+
+/// Identifies the packet a fragment belongs to
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct FragmentKey {
+    src: Ipv4Addr,
+    dst: Ipv4Addr,
+    identification: u16,
+    protocol: u8,
+}
+
+#[derive(Clone, Debug)]
+struct PendingPacket {
+    started: Instant,
+    /// The header of the first fragment, which is reused for the reassembled packet
+    header: Option<Vec<u8>>,
+    /// The payload of each fragment by its offset in bytes
+    parts: BTreeMap<usize, Vec<u8>>,
+    /// Known once the last fragment has arrived
+    total_len: Option<usize>,
+}
+
+/// Collects ipv4 fragments until the whole packet has arrived
+#[derive(Clone, Debug, Default)]
+pub struct Reassembler {
+    pending: BTreeMap<FragmentKey, PendingPacket>,
+}
+
+impl Reassembler {
+    /// Adds the fragment, returning the reassembled packet and the number of
+    /// fragments it was made from once every fragment has arrived
+    pub fn add(&mut self, ip: &Ipv4Packet) -> Option<(Vec<u8>, usize)> {
+        let offset = ip.get_fragment_offset() as usize * 8;
+        let more_fragments = ip.get_flags() & Ipv4Flags::MoreFragments != 0;
+
+        if offset == 0 && !more_fragments {
+            return None;
+        }
+
+        let now = Instant::now();
+        self.pending
+            .retain(|_, i| now.duration_since(i.started) < REASSEMBLY_TIMEOUT);
+
+        let key = FragmentKey {
+            src: ip.get_source(),
+            dst: ip.get_destination(),
+            identification: ip.get_identification(),
+            protocol: ip.get_next_level_protocol().0,
+        };
+
+        if !self.pending.contains_key(&key) && self.pending.len() >= MAX_PENDING_PACKETS {
+            log::debug!("too many packets are being reassembled, ignoring fragment");
+            return None;
+        }
+
+        let pending = self.pending.entry(key).or_insert_with(|| PendingPacket {
+            started: now,
+            header: None,
+            parts: BTreeMap::new(),
+            total_len: None,
+        });
+
+        if offset == 0 {
+            let header_len = ip.get_header_length() as usize * 4;
+            pending.header = Some(ip.packet()[..header_len].to_vec());
+        }
+
+        if !more_fragments {
+            pending.total_len = Some(offset + ip.payload().len());
+        }
+
+        pending.parts.insert(offset, ip.payload().to_vec());
+
+        let packet = pending.reassemble()?;
+        let fragments = pending.parts.len();
+        self.pending.remove(&key);
+
+        Some((packet, fragments))
+    }
+}
+
+impl PendingPacket {
+    /// Returns the whole packet if there are no gaps between the fragments
+    fn reassemble(&self) -> Option<Vec<u8>> {
+        let header = self.header.as_ref()?;
+        let total_len = self.total_len?;
+
+        // Fragments may overlap when they were retransmitted or fragmented again
+        let mut end = 0;
+        for (offset, part) in self.parts.iter() {
+            if *offset > end {
+                return None;
+            }
+            end = end.max(offset + part.len());
+        }
+
+        if end < total_len {
+            return None;
+        }
+
+        let mut buff = header.clone();
+        buff.resize(header.len() + total_len, 0);
+        for (offset, part) in self.parts.iter() {
+            let start = header.len() + offset;
+            if start >= buff.len() {
+                continue;
+            }
+
+            let len = part.len().min(buff.len() - start);
+            buff[start..start + len].copy_from_slice(&part[..len]);
+        }
+
+        let mut packet = MutableIpv4Packet::new(&mut buff)?;
+        packet.set_total_length((header.len() + total_len) as u16);
+        packet.set_flags(packet.get_flags() & !Ipv4Flags::MoreFragments);
+        packet.set_fragment_offset(0);
+        packet.set_checksum(ipv4::checksum(&packet.to_immutable()));
+
+        Some(buff)
+    }
+}
@@ -0,0 +1,418 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    net::SocketAddrV4,
+    time::{Duration, Instant},
+};
+
+use pnet::packet::ipv4::Ipv4Packet;
+use pnet::packet::tcp::{TcpFlags, TcpPacket};
+use pnet::packet::Packet;
+
+use super::dumper::escape_payload;
+
+/// Stop buffering payload for a single conversation after this many bytes,
+/// including out of order segments waiting for the gap before them
+const MAX_STREAM_DATA: usize = 1024 * 1024;
+
+/// Conversations which see no segments for this long are printed and forgotten
+const STREAM_IDLE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Tracks tcp connections and reassembles the payload sent in each direction
+/// so that whole conversations can be printed rather than individual segments.
+pub struct StreamTracker {
+    streams: HashMap<StreamKey, Stream>,
+}
+
+/// A tcp connection identified by its 5-tuple, the client being the side which sent the first segment
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct StreamKey {
+    client: SocketAddrV4,
+    server: SocketAddrV4,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Side {
+    Client,
+    Server,
+}
+
+struct Stream {
+    key: StreamKey,
+    started: Instant,
+    last_seen: Instant,
+    timeline: Vec<(Duration, Side, &'static str)>,
+    messages: Vec<(Side, Vec<u8>)>,
+    client: HalfStream,
+    server: HalfStream,
+    truncated: bool,
+    reset: bool,
+}
+
+/// The state of one direction of a tcp connection
+#[derive(Default)]
+struct HalfStream {
+    next_seq: Option<u32>,
+    pending: BTreeMap<u32, Vec<u8>>,
+    pending_bytes: usize,
+    bytes: usize,
+    retransmits: usize,
+    out_of_order: usize,
+    fin: bool,
+}
+
+impl StreamTracker {
+    pub fn new() -> Self {
+        Self {
+            streams: HashMap::new(),
+        }
+    }
+
+    /// Processes a tcp segment, printing the conversation if this segment closed the connection
+    pub fn process_packet(&mut self, ip: &Ipv4Packet) {
+        let tcp = match TcpPacket::new(ip.payload()) {
+            Some(tcp) => tcp,
+            None => return,
+        };
+
+        self.expire_idle();
+
+        let src = SocketAddrV4::new(ip.get_source(), tcp.get_source());
+        let dst = SocketAddrV4::new(ip.get_destination(), tcp.get_destination());
+        let flags = tcp.get_flags();
+
+        let (key, side) = if self.streams.contains_key(&StreamKey {
+            client: src,
+            server: dst,
+        }) {
+            (
+                StreamKey {
+                    client: src,
+                    server: dst,
+                },
+                Side::Client,
+            )
+        } else if self.streams.contains_key(&StreamKey {
+            client: dst,
+            server: src,
+        }) {
+            (
+                StreamKey {
+                    client: dst,
+                    server: src,
+                },
+                Side::Server,
+            )
+        } else if flags & TcpFlags::SYN != 0 || !tcp.payload().is_empty() {
+            let key = if flags & (TcpFlags::SYN | TcpFlags::ACK) == TcpFlags::SYN | TcpFlags::ACK {
+                // We missed the initial syn, the sender of the syn-ack is the server
+                StreamKey {
+                    client: dst,
+                    server: src,
+                }
+            } else {
+                StreamKey {
+                    client: src,
+                    server: dst,
+                }
+            };
+            log::trace!("tracking new tcp stream {} -> {}", key.client, key.server);
+            self.streams.insert(key, Stream::new(key));
+            (
+                key,
+                if key.client == src {
+                    Side::Client
+                } else {
+                    Side::Server
+                },
+            )
+        } else {
+            return;
+        };
+
+        let stream = self.streams.get_mut(&key).unwrap();
+        stream.process_segment(side, &tcp);
+
+        if stream.is_closed() {
+            let stream = self.streams.remove(&key).unwrap();
+            stream.print();
+        }
+    }
+
+    /// Stops tracking connections which never closed, printing what was seen of them
+    fn expire_idle(&mut self) {
+        let idle = self
+            .streams
+            .iter()
+            .filter(|(_, i)| i.last_seen.elapsed() >= STREAM_IDLE_TIMEOUT)
+            .map(|(key, _)| *key)
+            .collect::<Vec<_>>();
+
+        for key in idle {
+            log::debug!("tcp stream {} -> {} is idle", key.client, key.server);
+            self.streams.remove(&key).unwrap().print();
+        }
+    }
+
+    /// Prints all the conversations which are still open
+    pub fn print_open(&self) {
+        if self.streams.is_empty() {
+            println!("\n ------ no open tcp conversations ------ ");
+            return;
+        }
+
+        let mut streams = self.streams.values().collect::<Vec<_>>();
+        streams.sort_by_key(|i| i.started);
+
+        for stream in streams {
+            stream.print();
+        }
+    }
+}
+
+impl Default for StreamTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Stream {
+    fn new(key: StreamKey) -> Self {
+        Self {
+            key,
+            started: Instant::now(),
+            last_seen: Instant::now(),
+            timeline: vec![],
+            messages: vec![],
+            client: HalfStream::default(),
+            server: HalfStream::default(),
+            truncated: false,
+            reset: false,
+        }
+    }
+
+    fn process_segment(&mut self, side: Side, tcp: &TcpPacket) {
+        let flags = tcp.get_flags();
+        let elapsed = self.started.elapsed();
+        self.last_seen = Instant::now();
+
+        let event = if flags & TcpFlags::RST != 0 {
+            self.reset = true;
+            Some("RST")
+        } else if flags & TcpFlags::SYN != 0 {
+            Some(if flags & TcpFlags::ACK != 0 {
+                "SYN-ACK"
+            } else {
+                "SYN"
+            })
+        } else if flags & TcpFlags::FIN != 0 {
+            Some("FIN")
+        } else if side == Side::Client
+            && self.timeline.last().map(|i| i.2) == Some("SYN-ACK")
+            && tcp.payload().is_empty()
+        {
+            Some("ACK (established)")
+        } else {
+            None
+        };
+
+        if let Some(event) = event {
+            self.timeline.push((elapsed, side, event));
+        }
+
+        let room = MAX_STREAM_DATA.saturating_sub(
+            self.total_bytes() + self.client.pending_bytes + self.server.pending_bytes,
+        );
+
+        let half = match side {
+            Side::Client => &mut self.client,
+            Side::Server => &mut self.server,
+        };
+
+        let (data, dropped) = half.process_segment(tcp, room);
+
+        if dropped {
+            self.truncated = true;
+        }
+
+        if !data.is_empty() {
+            if self.total_bytes() + data.len() > MAX_STREAM_DATA {
+                self.truncated = true;
+                return;
+            }
+
+            match self.messages.last_mut() {
+                Some((last_side, last)) if *last_side == side => last.extend(data),
+                _ => self.messages.push((side, data)),
+            }
+        }
+    }
+
+    fn total_bytes(&self) -> usize {
+        self.messages.iter().map(|i| i.1.len()).sum()
+    }
+
+    fn is_closed(&self) -> bool {
+        self.reset || (self.client.fin && self.server.fin)
+    }
+
+    fn print(&self) {
+        println!(
+            "\n ====== tcp conversation {} <-> {} ({}) ====== ",
+            self.key.client,
+            self.key.server,
+            if self.is_closed() { "closed" } else { "open" }
+        );
+
+        println!("timeline:");
+        for (elapsed, side, event) in self.timeline.iter() {
+            println!(
+                "  +{:.3}s  {}  {}",
+                elapsed.as_secs_f64(),
+                self.direction(*side),
+                event
+            );
+        }
+
+        for side in [Side::Client, Side::Server].iter() {
+            let half = match side {
+                Side::Client => &self.client,
+                Side::Server => &self.server,
+            };
+
+            println!(
+                "{}: {} bytes | {} retransmitted | {} out of order |",
+                self.direction(*side),
+                half.bytes,
+                half.retransmits,
+                half.out_of_order
+            );
+        }
+
+        println!("conversation:");
+        for (side, data) in self.messages.iter() {
+            let name = match side {
+                Side::Client => "client",
+                Side::Server => "server",
+            };
+
+            let data = data.strip_suffix(b"\n").unwrap_or(data);
+
+            for line in data.split(|i| *i == b'\n') {
+                println!("  {} > {}", name, escape_payload(line));
+            }
+        }
+
+        if self.truncated {
+            println!(
+                "  ... conversation truncated after {} bytes",
+                MAX_STREAM_DATA
+            );
+        }
+    }
+
+    fn direction(&self, side: Side) -> String {
+        match side {
+            Side::Client => format!("{} -> {}", self.key.client, self.key.server),
+            Side::Server => format!("{} -> {}", self.key.server, self.key.client),
+        }
+    }
+}
+
+impl HalfStream {
+    /// Processes a segment and returns any payload which is now in order, along with whether
+    /// an out of order segment was dropped as there was no room left to buffer it
+    fn process_segment(&mut self, tcp: &TcpPacket, room: usize) -> (Vec<u8>, bool) {
+        let flags = tcp.get_flags();
+        let seq = tcp.get_sequence();
+
+        if flags & TcpFlags::SYN != 0 {
+            self.next_seq = Some(seq.wrapping_add(1));
+            return (vec![], false);
+        }
+
+        // If we did not see the handshake start from the first segment we see
+        let next_seq = *self.next_seq.get_or_insert(seq);
+        let payload = tcp.payload();
+
+        let mut data = vec![];
+        let mut dropped = false;
+
+        if !payload.is_empty() {
+            let offset = seq.wrapping_sub(next_seq) as i32;
+
+            if offset > 0 {
+                log::trace!(
+                    "out of order tcp segment, expected seq {} got {}",
+                    next_seq,
+                    seq
+                );
+                self.out_of_order += 1;
+                let existing = self.pending.entry(seq).or_insert_with(Vec::new);
+                if existing.len() < payload.len() {
+                    if payload.len() - existing.len() > room {
+                        log::trace!("no room to buffer out of order tcp segment {}", seq);
+                        dropped = true;
+                    } else {
+                        self.pending_bytes += payload.len() - existing.len();
+                        *existing = payload.to_vec();
+                    }
+                }
+
+                if existing.is_empty() {
+                    self.pending.remove(&seq);
+                }
+            } else {
+                self.append(seq, payload, &mut data);
+                self.drain_pending(&mut data);
+            }
+        }
+
+        if flags & TcpFlags::FIN != 0 {
+            self.fin = true;
+        }
+
+        (data, dropped)
+    }
+
+    /// Appends the part of the segment which has not already been received
+    fn append(&mut self, seq: u32, payload: &[u8], data: &mut Vec<u8>) {
+        let next_seq = self.next_seq.unwrap();
+        let overlap = next_seq.wrapping_sub(seq) as usize;
+
+        if overlap >= payload.len() {
+            log::trace!("retransmitted tcp segment with seq {}", seq);
+            self.retransmits += 1;
+            return;
+        }
+
+        if overlap > 0 {
+            self.retransmits += 1;
+        }
+
+        let new = &payload[overlap..];
+        data.extend_from_slice(new);
+        self.bytes += new.len();
+        self.next_seq = Some(next_seq.wrapping_add(new.len() as u32));
+    }
+
+    /// Appends buffered out of order segments which are now contiguous with the stream
+    fn drain_pending(&mut self, data: &mut Vec<u8>) {
+        loop {
+            let next_seq = self.next_seq.unwrap();
+            let ready = self
+                .pending
+                .keys()
+                .cloned()
+                .find(|i| i.wrapping_sub(next_seq) as i32 <= 0);
+
+            let seq = match ready {
+                Some(seq) => seq,
+                None => return,
+            };
+
+            let payload = self.pending.remove(&seq).unwrap();
+            self.pending_bytes -= payload.len();
+            self.append(seq, &payload, data);
+        }
+    }
+}
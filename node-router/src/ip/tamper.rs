@@ -0,0 +1,638 @@
+use std::{convert::TryFrom, fmt, fs, time::Duration};
+
+use pnet::packet::icmp::{self, MutableIcmpPacket};
+use pnet::packet::ip::IpNextHeaderProtocols;
+use pnet::packet::ipv4::{self, Ipv4Flags, Ipv4Packet, MutableIpv4Packet};
+use pnet::packet::tcp::{self, MutableTcpPacket, TcpPacket};
+use pnet::packet::udp::{self, MutableUdpPacket};
+use pnet::packet::Packet;
+use rand::Rng;
+use regex::bytes::Regex;
+
+use super::filter::{Direction, Filter};
+
+/// The length of an ipv4 header without options
+const MIN_IPV4_HEADER_LEN: usize = 20;
+
+/// Rules which turn this node into a man-in-the-middle for packets passing through it.
+///
+/// Rules are loaded from a file with one rule per line in the form `<action> [if <filter>]`:
+///
+/// ```text
+/// # drop half of all pings
+/// drop 50% if icmp
+/// delay 500ms if udp
+/// replace "Hello" "Goodbye" if tcp port 80
+/// rewrite-port dst 8080 if tcp dst port 80
+/// corrupt-checksum if udp port 53
+/// ```
+#[derive(Clone, Debug)]
+pub struct TamperRules {
+    rules: Vec<Rule>,
+}
+
+#[derive(Clone, Debug)]
+struct Rule {
+    action: Action,
+    filter: Option<Filter>,
+}
+
+#[derive(Clone, Debug)]
+enum Action {
+    Drop(f64),
+    Delay(Duration),
+    Replace(Regex, Vec<u8>),
+    RewritePort(Direction, u16),
+    CorruptChecksum,
+}
+
+/// The result of applying the rules to a packet
+#[derive(Default)]
+pub struct Tampered {
+    /// Description of each rule which was applied
+    pub actions: Vec<String>,
+    pub drop: bool,
+    pub delay: Option<Duration>,
+    /// The modified ipv4 packet, if any rule changed its contents
+    pub packet: Option<Vec<u8>>,
+}
+
+impl TamperRules {
+    pub fn load(path: &str) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| format!("could not read rule file {}: {}", path, err))?;
+
+        let rules = contents
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i, line.trim()))
+            .filter(|(_, line)| !line.is_empty() && !line.starts_with('#'))
+            .map(|(i, line)| parse_rule(line).map_err(|err| format!("line {}: {}", i + 1, err)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self { rules })
+    }
+
+    pub fn apply(&self, ip: &Ipv4Packet) -> Tampered {
+        let mut tampered = Tampered::default();
+        let mut packet = ip.packet().to_vec();
+        let mut corrupt = false;
+
+        for rule in self.rules.iter() {
+            let current = Ipv4Packet::new(&packet).unwrap();
+
+            if !rule.filter.as_ref().map_or(true, |i| i.matches(&current)) {
+                continue;
+            }
+
+            let applied = match &rule.action {
+                Action::Drop(probability) => {
+                    if rand::thread_rng().gen_bool(*probability) {
+                        tampered.drop = true;
+                    }
+                    tampered.drop
+                }
+                Action::Delay(delay) => {
+                    tampered.delay = Some(tampered.delay.unwrap_or_default() + *delay);
+                    true
+                }
+                Action::Replace(regex, replacement) => {
+                    replace_payload(&mut packet, regex, replacement)
+                }
+                Action::RewritePort(dir, port) => rewrite_port(&mut packet, *dir, *port),
+                Action::CorruptChecksum => {
+                    corrupt = true;
+                    true
+                }
+            };
+
+            if applied {
+                tampered.actions.push(rule.action.to_string());
+            }
+
+            if tampered.drop {
+                return tampered;
+            }
+        }
+
+        if packet != ip.packet() || corrupt {
+            fix_checksums(&mut packet);
+
+            if corrupt {
+                corrupt_checksum(&mut packet);
+            }
+
+            tampered.packet = Some(packet);
+        }
+
+        tampered
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Drop(p) => write!(f, "drop {}%", p * 100.0),
+            Action::Delay(d) => write!(f, "delay {}ms", d.as_millis()),
+            Action::Replace(r, s) => {
+                write!(
+                    f,
+                    "replace \"{}\" \"{}\"",
+                    r.as_str(),
+                    String::from_utf8_lossy(s)
+                )
+            }
+            Action::RewritePort(Direction::Src, p) => write!(f, "rewrite-port src {}", p),
+            Action::RewritePort(_, p) => write!(f, "rewrite-port dst {}", p),
+            Action::CorruptChecksum => write!(f, "corrupt-checksum"),
+        }
+    }
+}
+
+fn parse_rule(line: &str) -> Result<Rule, String> {
+    let (args, filter) = split_args(line)?;
+    let filter = filter.map(|i| i.parse::<Filter>()).transpose()?;
+
+    let args = args.iter().map(|i| i.as_str()).collect::<Vec<_>>();
+
+    let action = match args.as_slice() {
+        ["drop"] => Action::Drop(1.0),
+        ["drop", p] => Action::Drop(parse_percentage(p)?),
+        ["delay", d] => Action::Delay(parse_duration(d)?),
+        ["replace", pattern, replacement] => Action::Replace(
+            Regex::new(pattern).map_err(|err| format!("invalid regex: {}", err))?,
+            replacement.as_bytes().to_vec(),
+        ),
+        ["rewrite-port", "src", port] => Action::RewritePort(Direction::Src, parse_port(port)?),
+        ["rewrite-port", "dst", port] => Action::RewritePort(Direction::Dst, parse_port(port)?),
+        ["corrupt-checksum"] => Action::CorruptChecksum,
+        _ => return Err(format!("invalid rule '{}'", line)),
+    };
+
+    Ok(Rule { action, filter })
+}
+
+/// Splits the action's arguments on whitespace, allowing them to be double quoted, up to an
+/// unquoted `if` which starts the filter. Returns the arguments along with the filter, if any.
+fn split_args(s: &str) -> Result<(Vec<String>, Option<&str>), String> {
+    let mut args = vec![];
+    let mut chars = s.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+
+        let mut arg = String::new();
+
+        if c == '"' {
+            loop {
+                match chars.next() {
+                    Some((_, '"')) => break,
+                    Some((_, '\\')) if chars.peek().map(|i| i.1) == Some('"') => {
+                        arg.push(chars.next().unwrap().1)
+                    }
+                    Some((_, c)) => arg.push(c),
+                    None => return Err("unterminated quote".to_string()),
+                }
+            }
+        } else {
+            arg.push(c);
+            while let Some((_, c)) = chars.peek().copied().filter(|i| !i.1.is_whitespace()) {
+                arg.push(c);
+                chars.next();
+            }
+
+            if arg == "if" {
+                return Ok((args, Some(&s[start + arg.len()..])));
+            }
+        }
+
+        args.push(arg);
+    }
+
+    Ok((args, None))
+}
+
+fn parse_percentage(s: &str) -> Result<f64, String> {
+    match s.trim_end_matches('%').parse::<f64>() {
+        Ok(p) if (0.0..=100.0).contains(&p) => Ok(p / 100.0),
+        _ => Err(format!("invalid percentage '{}'", s)),
+    }
+}
+
+fn parse_duration(s: &str) -> Result<Duration, String> {
+    let (value, scale) = if let Some(ms) = s.strip_suffix("ms") {
+        (ms, 0.001)
+    } else if let Some(secs) = s.strip_suffix('s') {
+        (secs, 1.0)
+    } else {
+        (s, 0.001)
+    };
+
+    match value.parse::<f64>() {
+        Ok(v) if v >= 0.0 => Ok(Duration::from_secs_f64(v * scale)),
+        _ => Err(format!("invalid duration '{}'", s)),
+    }
+}
+
+fn parse_port(s: &str) -> Result<u16, String> {
+    s.parse().map_err(|_| format!("invalid port '{}'", s))
+}
+
+/// Returns true if the packet is a fragment, whose transport header and checksum cannot be
+/// changed without the rest of the packet
+fn is_fragment(ip: &Ipv4Packet) -> bool {
+    ip.get_fragment_offset() > 0 || ip.get_flags() & Ipv4Flags::MoreFragments != 0
+}
+
+/// Returns the length of the transport header of the tcp or udp packet
+fn transport_header_len(packet: &[u8]) -> Option<usize> {
+    let ip = Ipv4Packet::new(packet)?;
+
+    match ip.get_next_level_protocol() {
+        _ if is_fragment(&ip) => None,
+        IpNextHeaderProtocols::Tcp => {
+            TcpPacket::new(ip.payload()).map(|i| i.get_data_offset() as usize * 4)
+        }
+        IpNextHeaderProtocols::Udp => Some(8),
+        _ => None,
+    }
+}
+
+fn replace_payload(packet: &mut Vec<u8>, regex: &Regex, replacement: &[u8]) -> bool {
+    let ip_header_len = Ipv4Packet::new(packet).unwrap().get_header_length() as usize * 4;
+    let ip_len = Ipv4Packet::new(packet).unwrap().get_total_length() as usize;
+
+    let header_len = match transport_header_len(packet) {
+        Some(len) => ip_header_len + len,
+        None => return false,
+    };
+
+    if header_len > ip_len || ip_len > packet.len() {
+        return false;
+    }
+
+    let payload = &packet[header_len..ip_len];
+
+    if !regex.is_match(payload) {
+        return false;
+    }
+
+    // Note that changing the length of a tcp payload will desync the sequence numbers
+    // between the two hosts, which is realistic for a naive man-in-the-middle
+    let new_payload = regex.replace_all(payload, replacement).into_owned();
+
+    // The packet is left alone if the replacement no longer fits in the length fields
+    let (ip_len, udp_len) = match (
+        u16::try_from(header_len + new_payload.len()),
+        u16::try_from(header_len - ip_header_len + new_payload.len()),
+    ) {
+        (Ok(ip_len), Ok(udp_len)) => (ip_len, udp_len),
+        _ => {
+            log::debug!("replacement would make the packet too long, leaving it unchanged");
+            return false;
+        }
+    };
+
+    packet.truncate(header_len);
+    packet.extend_from_slice(&new_payload);
+
+    let mut ip = MutableIpv4Packet::new(packet).unwrap();
+    ip.set_total_length(ip_len);
+
+    if ip.get_next_level_protocol() == IpNextHeaderProtocols::Udp {
+        MutableUdpPacket::new(&mut packet[ip_header_len..])
+            .unwrap()
+            .set_length(udp_len);
+    }
+
+    true
+}
+
+fn rewrite_port(packet: &mut [u8], dir: Direction, port: u16) -> bool {
+    let ip = Ipv4Packet::new(packet).unwrap();
+    let offset = ip.get_header_length() as usize * 4;
+    let protocol = ip.get_next_level_protocol();
+
+    if is_fragment(&ip) {
+        return false;
+    }
+
+    // The header length of a malformed packet can point past its end
+    let transport = match packet.get_mut(offset..) {
+        Some(transport) if offset >= MIN_IPV4_HEADER_LEN => transport,
+        _ => return false,
+    };
+
+    match (protocol, dir) {
+        (IpNextHeaderProtocols::Tcp, Direction::Src) => {
+            MutableTcpPacket::new(transport).map(|mut i| i.set_source(port))
+        }
+        (IpNextHeaderProtocols::Tcp, _) => {
+            MutableTcpPacket::new(transport).map(|mut i| i.set_destination(port))
+        }
+        (IpNextHeaderProtocols::Udp, Direction::Src) => {
+            MutableUdpPacket::new(transport).map(|mut i| i.set_source(port))
+        }
+        (IpNextHeaderProtocols::Udp, _) => {
+            MutableUdpPacket::new(transport).map(|mut i| i.set_destination(port))
+        }
+        _ => None,
+    }
+    .is_some()
+}
+
+/// Recalculates the ipv4 and transport checksums after the packet has been modified
+fn fix_checksums(packet: &mut [u8]) {
+    let ip = Ipv4Packet::new(packet).unwrap();
+    let offset = ip.get_header_length() as usize * 4;
+    let end = (ip.get_total_length() as usize).min(packet.len());
+    let protocol = ip.get_next_level_protocol();
+    let (src, dst) = (ip.get_source(), ip.get_destination());
+    // The transport checksum covers the whole packet so cannot be recalculated from a fragment
+    let fragment = is_fragment(&ip);
+
+    if offset < end && !fragment {
+        let transport = &mut packet[offset..end];
+
+        match protocol {
+            IpNextHeaderProtocols::Tcp => {
+                if let Some(mut tcp) = MutableTcpPacket::new(transport) {
+                    tcp.set_checksum(tcp::ipv4_checksum(&tcp.to_immutable(), &src, &dst));
+                }
+            }
+            IpNextHeaderProtocols::Udp => {
+                if let Some(mut udp) = MutableUdpPacket::new(transport) {
+                    udp.set_checksum(udp::ipv4_checksum(&udp.to_immutable(), &src, &dst));
+                }
+            }
+            IpNextHeaderProtocols::Icmp => {
+                if let Some(mut icmp) = MutableIcmpPacket::new(transport) {
+                    icmp.set_checksum(icmp::checksum(&icmp.to_immutable()));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut ip = MutableIpv4Packet::new(packet).unwrap();
+    ip.set_checksum(ipv4::checksum(&ip.to_immutable()));
+}
+
+/// Flips the bits of the transport checksum, or the ipv4 checksum if there is no known transport
+fn corrupt_checksum(packet: &mut [u8]) {
+    let ip = Ipv4Packet::new(packet).unwrap();
+    let offset = ip.get_header_length() as usize * 4;
+    let protocol = ip.get_next_level_protocol();
+    // Only the first fragment carries the transport header
+    let first = ip.get_fragment_offset() == 0;
+
+    let transport = match packet.get_mut(offset..) {
+        Some(transport) if offset >= MIN_IPV4_HEADER_LEN && first => transport,
+        _ => &mut [],
+    };
+
+    let corrupted = match protocol {
+        IpNextHeaderProtocols::Tcp => MutableTcpPacket::new(transport).map(|mut i| {
+            let checksum = i.get_checksum();
+            i.set_checksum(!checksum)
+        }),
+        IpNextHeaderProtocols::Udp => MutableUdpPacket::new(transport).map(|mut i| {
+            let checksum = i.get_checksum();
+            i.set_checksum(!checksum)
+        }),
+        IpNextHeaderProtocols::Icmp => MutableIcmpPacket::new(transport).map(|mut i| {
+            let checksum = i.get_checksum();
+            i.set_checksum(!checksum)
+        }),
+        _ => None,
+    };
+
+    if corrupted.is_none() {
+        let mut ip = MutableIpv4Packet::new(packet).unwrap();
+        let checksum = ip.get_checksum();
+        ip.set_checksum(!checksum);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use pnet::packet::ip::IpNextHeaderProtocol;
+    use pnet::packet::udp::UdpPacket;
+
+    use super::*;
+
+    fn parse_err(line: &str) -> String {
+        parse_rule(line).unwrap_err()
+    }
+
+    fn rules(lines: &[&str]) -> TamperRules {
+        TamperRules {
+            rules: lines.iter().map(|i| parse_rule(i).unwrap()).collect(),
+        }
+    }
+
+    /// Builds an ipv4 packet with valid checksums around a tcp or udp header and payload
+    fn packet(protocol: IpNextHeaderProtocol, payload: &[u8]) -> Vec<u8> {
+        let header_len = if protocol == IpNextHeaderProtocols::Tcp {
+            20
+        } else {
+            8
+        };
+        let mut buf = vec![0u8; 20 + header_len + payload.len()];
+        buf[20 + header_len..].copy_from_slice(payload);
+
+        let mut ip = MutableIpv4Packet::new(&mut buf).unwrap();
+        ip.set_version(4);
+        ip.set_header_length(5);
+        ip.set_total_length((20 + header_len + payload.len()) as u16);
+        ip.set_ttl(64);
+        ip.set_next_level_protocol(protocol);
+        ip.set_source(Ipv4Addr::new(10, 0, 0, 5));
+        ip.set_destination(Ipv4Addr::new(10, 0, 1, 7));
+
+        if protocol == IpNextHeaderProtocols::Tcp {
+            let mut tcp = MutableTcpPacket::new(&mut buf[20..]).unwrap();
+            tcp.set_source(40000);
+            tcp.set_destination(80);
+            tcp.set_data_offset(5);
+        } else {
+            let mut udp = MutableUdpPacket::new(&mut buf[20..]).unwrap();
+            udp.set_source(40000);
+            udp.set_destination(53);
+            udp.set_length((8 + payload.len()) as u16);
+        }
+
+        fix_checksums(&mut buf);
+        buf
+    }
+
+    /// Returns true if the ipv4 and transport checksums of the packet are valid
+    fn checksums_valid(packet: &[u8]) -> bool {
+        let ip = Ipv4Packet::new(packet).unwrap();
+        let (src, dst) = (ip.get_source(), ip.get_destination());
+
+        let transport = match ip.get_next_level_protocol() {
+            IpNextHeaderProtocols::Tcp => {
+                let tcp = TcpPacket::new(ip.payload()).unwrap();
+                tcp.get_checksum() == tcp::ipv4_checksum(&tcp, &src, &dst)
+            }
+            _ => {
+                let udp = UdpPacket::new(ip.payload()).unwrap();
+                udp.get_checksum() == udp::ipv4_checksum(&udp, &src, &dst)
+            }
+        };
+
+        transport && ip.get_checksum() == ipv4::checksum(&ip)
+    }
+
+    #[test]
+    fn splits_quoted_args() {
+        let (args, filter) = split_args(r#"replace "a b" c if tcp"#).unwrap();
+        assert_eq!(args, vec!["replace", "a b", "c"]);
+        assert_eq!(filter, Some(" tcp"));
+
+        let (args, filter) = split_args(r#"replace "say \"hi\"" "" "#).unwrap();
+        assert_eq!(args, vec!["replace", r#"say "hi""#, ""]);
+        assert_eq!(filter, None);
+
+        assert_eq!(
+            split_args(r#"replace "open ended"#).unwrap_err(),
+            "unterminated quote"
+        );
+    }
+
+    #[test]
+    fn only_unquoted_if_starts_filter() {
+        let rule = parse_rule(r#"replace "a if b" "c if d""#).unwrap();
+        assert_eq!(rule.action.to_string(), r#"replace "a if b" "c if d""#);
+        assert!(rule.filter.is_none());
+
+        let rule = parse_rule(r#"replace "if" "iff" if udp port 53"#).unwrap();
+        assert_eq!(rule.action.to_string(), r#"replace "if" "iff""#);
+        assert_eq!(rule.filter, Some("udp port 53".parse().unwrap()));
+    }
+
+    #[test]
+    fn parses_actions() {
+        let rule = parse_rule("drop 50% if icmp").unwrap();
+        assert!(matches!(rule.action, Action::Drop(p) if (p - 0.5).abs() < f64::EPSILON));
+        assert_eq!(
+            rule.filter,
+            Some(Filter::Protocol(IpNextHeaderProtocols::Icmp))
+        );
+
+        assert!(matches!(parse_rule("drop").unwrap().action, Action::Drop(p) if p == 1.0));
+        assert!(matches!(
+            parse_rule("delay 1.5s").unwrap().action,
+            Action::Delay(d) if d == Duration::from_millis(1500)
+        ));
+        assert!(matches!(
+            parse_rule("delay 20").unwrap().action,
+            Action::Delay(d) if d == Duration::from_millis(20)
+        ));
+        assert!(matches!(
+            parse_rule("rewrite-port dst 8080 if tcp dst port 80")
+                .unwrap()
+                .action,
+            Action::RewritePort(Direction::Dst, 8080)
+        ));
+        assert!(matches!(
+            parse_rule("corrupt-checksum").unwrap().action,
+            Action::CorruptChecksum
+        ));
+    }
+
+    #[test]
+    fn reports_malformed_rules() {
+        assert_eq!(parse_err("explode"), "invalid rule 'explode'");
+        assert_eq!(parse_err("drop 50% 10%"), "invalid rule 'drop 50% 10%'");
+        assert_eq!(
+            parse_err("rewrite-port any 80"),
+            "invalid rule 'rewrite-port any 80'"
+        );
+        assert_eq!(parse_err("drop 150%"), "invalid percentage '150%'");
+        assert_eq!(parse_err("delay soon"), "invalid duration 'soon'");
+        assert_eq!(parse_err("rewrite-port src 65536"), "invalid port '65536'");
+        assert_eq!(parse_err("drop if"), "unexpected end of filter");
+        assert_eq!(parse_err("drop if arp"), "unknown filter primitive 'arp'");
+        assert!(parse_err(r#"replace "(" "x""#).starts_with("invalid regex: "));
+    }
+
+    #[test]
+    fn handles_header_length_past_end_of_packet() {
+        let mut packet = vec![0u8; 20];
+        let mut ip = MutableIpv4Packet::new(&mut packet).unwrap();
+        ip.set_version(4);
+        ip.set_header_length(15);
+        ip.set_next_level_protocol(IpNextHeaderProtocols::Tcp);
+        ip.set_checksum(0x1234);
+
+        assert!(!rewrite_port(&mut packet, Direction::Dst, 80));
+
+        corrupt_checksum(&mut packet);
+        assert_eq!(Ipv4Packet::new(&packet).unwrap().get_checksum(), !0x1234);
+    }
+
+    #[test]
+    fn rewrites_port() {
+        let original = packet(IpNextHeaderProtocols::Tcp, b"GET / HTTP/1.1");
+        let tampered = rules(&["rewrite-port dst 8080 if tcp dst port 80"])
+            .apply(&Ipv4Packet::new(&original).unwrap());
+
+        let packet = tampered.packet.unwrap();
+        let ip = Ipv4Packet::new(&packet).unwrap();
+        let tcp = TcpPacket::new(ip.payload()).unwrap();
+
+        assert_eq!(tampered.actions, vec!["rewrite-port dst 8080"]);
+        assert_eq!((tcp.get_source(), tcp.get_destination()), (40000, 8080));
+        assert_eq!(tcp.payload(), b"GET / HTTP/1.1");
+        assert!(checksums_valid(&packet));
+    }
+
+    #[test]
+    fn replaces_payload_with_different_length() {
+        let original = packet(IpNextHeaderProtocols::Udp, b"Hello world");
+        let tampered = rules(&[r#"replace "Hello" "Goodbye" if udp"#])
+            .apply(&Ipv4Packet::new(&original).unwrap());
+
+        let packet = tampered.packet.unwrap();
+        let ip = Ipv4Packet::new(&packet).unwrap();
+        let udp = UdpPacket::new(ip.payload()).unwrap();
+
+        assert_eq!(tampered.actions, vec![r#"replace "Hello" "Goodbye""#]);
+        assert_eq!(packet.len(), original.len() + 2);
+        assert_eq!(ip.get_total_length() as usize, packet.len());
+        assert_eq!(udp.get_length() as usize, packet.len() - 20);
+        assert_eq!(udp.payload(), b"Goodbye world");
+        assert!(checksums_valid(&packet));
+    }
+
+    #[test]
+    fn leaves_fragments_untouched() {
+        let rules = rules(&[
+            r#"replace "Hello" "Goodbye""#,
+            "rewrite-port dst 8080",
+            "rewrite-port src 1234",
+        ]);
+
+        for (flags, offset) in [
+            (Ipv4Flags::MoreFragments, 0),
+            (0, 2),
+            (Ipv4Flags::MoreFragments, 2),
+        ] {
+            let mut original = packet(IpNextHeaderProtocols::Udp, b"Hello world");
+            let mut ip = MutableIpv4Packet::new(&mut original).unwrap();
+            ip.set_flags(flags);
+            ip.set_fragment_offset(offset);
+
+            let tampered = rules.apply(&Ipv4Packet::new(&original).unwrap());
+
+            assert!(tampered.actions.is_empty());
+            assert!(tampered.packet.is_none());
+        }
+    }
+}
@@ -1,8 +1,11 @@
 mod args;
+mod chain;
 mod ip;
+mod services;
 pub mod state;
+mod ui;
 
-use std::{process, thread};
+use std::{process, sync::atomic::Ordering, thread};
 
 use anyhow::{anyhow, Result};
 use args::Args;
@@ -11,13 +14,20 @@ use state::SharedState;
 use thread::JoinHandle;
 
 fn main() {
+    let args = Args::parse();
+
+    // Log output would draw over the terminal ui
+    let default_filter = if args.tui { "off" } else { "info" };
     env_logger::init_from_env(
-        env_logger::Env::default().filter_or(env_logger::DEFAULT_FILTER_ENV, "info"),
+        env_logger::Env::default().filter_or(env_logger::DEFAULT_FILTER_ENV, default_filter),
     );
 
-    let args = Args::parse();
-
     let state = SharedState::new();
+    state.update(|s| {
+        s.promisc = args.promisc;
+        s.dump = args.dump;
+        s.vlan = args.vlan;
+    });
 
     log::info!("starting up");
 
@@ -25,7 +35,19 @@ fn main() {
         signal_hook::flag::register(*sig, state.term_arc()).expect("failed to set signal handler");
     }
 
-    let threads = vec![spawn(&args, &state, ip::start)];
+    let mut threads = vec![spawn(&args, &state, ip::start)];
+
+    if args.forward {
+        threads.push(spawn(&args, &state, chain::start));
+    }
+
+    if args.tui {
+        threads.push(spawn(&args, &state, ui::start));
+    }
+
+    if args.echo_port.is_some() || args.http_port.is_some() || args.chat_port.is_some() {
+        threads.push(spawn(&args, &state, services::start));
+    }
 
     let error = threads
         .into_iter()
@@ -59,5 +81,14 @@ where
 {
     let args = args.clone();
     let state = state.clone();
-    thread::spawn(move || f(args, state))
+    thread::spawn(move || {
+        let res = f(args, state.clone());
+
+        // Stop the other threads so the error can be reported
+        if res.is_err() {
+            state.term_arc().store(true, Ordering::Relaxed);
+        }
+
+        res
+    })
 }
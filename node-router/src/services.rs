@@ -0,0 +1,291 @@
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{IpAddr, Ipv4Addr, SocketAddr, TcpListener, TcpStream, UdpSocket},
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use anyhow::{anyhow, Result};
+use pnet::datalink;
+
+use crate::{args::Args, chain, state::SharedState};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+const CLIENT_TIMEOUT: Duration = Duration::from_secs(10);
+const MAX_DATAGRAM_LEN: usize = 65535;
+const MAX_HTTP_REQUEST_LEN: usize = 8192;
+
+/// Runs the demo services enabled by the arguments until the node router shuts down
+pub fn start(args: Args, state: SharedState) -> Result<()> {
+    // Services identify this node by its name in the chain and chat looks up the names
+    // of other nodes in it. It is loaded here even when forwarding, as the forwarder
+    // fetches it in the background and may not have it yet
+    let has_chain = args.central.is_some() || args.nodes.is_some();
+    if has_chain && state.get(|s| s.chain.is_empty()) {
+        match chain::load(&args) {
+            Ok(chain) => state.update(|s| s.chain = chain),
+            Err(err) => log::warn!("failed to load node names: {}", err),
+        }
+    }
+
+    let name = node_name(&args, &state);
+    log::info!("starting services as {}", name);
+
+    let mut services = vec![];
+
+    if let Some(port) = args.echo_port {
+        services.push(spawn(&state, move |state| udp_echo(port, &state)));
+        services.push(spawn(&state, move |state| {
+            serve_tcp(port, &state, tcp_echo)
+        }));
+    }
+
+    if let Some(port) = args.http_port {
+        let name = name.clone();
+        services.push(spawn(&state, move |state| {
+            serve_tcp(port, &state, move |s| http_hello(s, &name))
+        }));
+    }
+
+    if let Some(port) = args.chat_port {
+        services.push(spawn(&state, move |state| {
+            serve_tcp(port, &state, print_chat)
+        }));
+
+        // Reading stdin blocks so this thread is not waited on when shutting down
+        thread::spawn(move || {
+            if let Err(err) = send_chat(port, &state, &name) {
+                log::warn!("chat input closed: {}", err);
+            }
+        });
+    }
+
+    services
+        .into_iter()
+        .map(|i| {
+            i.join()
+                .unwrap_or(Err(anyhow!("failed to join service thread")))
+        })
+        .find(|i| i.is_err())
+        .unwrap_or(Ok(()))
+}
+
+fn spawn<F>(state: &SharedState, f: F) -> JoinHandle<Result<()>>
+where
+    F: FnOnce(SharedState) -> Result<()> + Send + 'static,
+{
+    let state = state.clone();
+    thread::spawn(move || f(state))
+}
+
+/// The name given on the command line, else the name of this node in the chain
+fn node_name(args: &Args, state: &SharedState) -> String {
+    if let Some(name) = &args.name {
+        return name.clone();
+    }
+
+    let local_ip = datalink::interfaces()
+        .into_iter()
+        .find(|i| i.name == args.interface)
+        .and_then(|i| {
+            i.ips.iter().find_map(|i| match i.ip() {
+                IpAddr::V4(ip) => Some(ip),
+                _ => None,
+            })
+        });
+
+    let local_ip = match local_ip {
+        Some(ip) => ip,
+        None => return args.interface.clone(),
+    };
+
+    state
+        .get(|s| {
+            s.chain
+                .iter()
+                .find(|i| i.ip == local_ip)
+                .map(|i| i.name.clone())
+        })
+        .unwrap_or_else(|| local_ip.to_string())
+}
+
+fn udp_echo(port: u16, state: &SharedState) -> Result<()> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, port))?;
+    socket.set_read_timeout(Some(POLL_INTERVAL))?;
+    log::info!("udp echo listening on port {}", port);
+
+    let mut buff = vec![0u8; MAX_DATAGRAM_LEN];
+
+    while state.running() {
+        let (len, peer) = match socket.recv_from(&mut buff) {
+            Ok(res) => res,
+            Err(err) if is_timeout(&err) => continue,
+            Err(err) => return Err(err.into()),
+        };
+
+        log::debug!("echoing {} bytes to {} over udp", len, peer);
+        socket.send_to(&buff[..len], peer)?;
+    }
+
+    Ok(())
+}
+
+/// Accepts connections on the port, handling each on its own thread
+fn serve_tcp<F>(port: u16, state: &SharedState, handler: F) -> Result<()>
+where
+    F: Fn(TcpStream) -> io::Result<()> + Clone + Send + 'static,
+{
+    let listener = TcpListener::bind((Ipv4Addr::UNSPECIFIED, port))?;
+    listener.set_nonblocking(true)?;
+    log::info!("tcp service listening on port {}", port);
+
+    while state.running() {
+        let (stream, peer) = match listener.accept() {
+            Ok(res) => res,
+            Err(err) if is_timeout(&err) => {
+                thread::sleep(POLL_INTERVAL);
+                continue;
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        log::debug!("accepted connection from {} on port {}", peer, port);
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+
+        let handler = handler.clone();
+        thread::spawn(move || {
+            if let Err(err) = handler(stream) {
+                log::debug!("connection from {} closed: {}", peer, err);
+            }
+        });
+    }
+
+    Ok(())
+}
+
+fn tcp_echo(mut stream: TcpStream) -> io::Result<()> {
+    let mut reader = stream.try_clone()?;
+    io::copy(&mut reader, &mut stream)?;
+    Ok(())
+}
+
+fn http_hello(mut stream: TcpStream, name: &str) -> io::Result<()> {
+    // The request is ignored apart from waiting for the end of its headers
+    let mut request = vec![];
+    let mut buff = [0u8; 1024];
+
+    while !request.windows(4).any(|i| i == b"\r\n\r\n") && request.len() < MAX_HTTP_REQUEST_LEN {
+        let len = stream.read(&mut buff)?;
+        if len == 0 {
+            break;
+        }
+        request.extend_from_slice(&buff[..len]);
+    }
+
+    let body = format!("Hello from {}\n", name);
+    write!(
+        stream,
+        "HTTP/1.0 200 OK\r\nContent-Type: text/plain\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        body.len(),
+        body
+    )
+}
+
+/// Prints each line received from another node, a connection may start
+/// with "HELLO <name>" to be shown by name rather than address
+fn print_chat(stream: TcpStream) -> io::Result<()> {
+    stream.set_read_timeout(None)?;
+    let mut sender = stream.peer_addr()?.ip().to_string();
+
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+
+        match line.strip_prefix("HELLO ") {
+            Some(name) => sender = name.trim().to_string(),
+            None => println!("[{}] {}", sender, line),
+        }
+    }
+
+    Ok(())
+}
+
+/// Sends each line typed as "<node name or ip> <message>" to that node's chat service
+fn send_chat(port: u16, state: &SharedState, name: &str) -> Result<()> {
+    println!("chat ready, type \"<node name or ip> <message>\" to send a message");
+
+    let mut connections = HashMap::<Ipv4Addr, TcpStream>::new();
+
+    for line in io::stdin().lock().lines() {
+        if !state.running() {
+            break;
+        }
+
+        let line = line?;
+        let (dest, message) = match line.trim().split_once(' ') {
+            Some((dest, message)) => (dest, message.trim()),
+            None => {
+                println!("usage: <node name or ip> <message>");
+                continue;
+            }
+        };
+
+        let ip = match resolve_node(state, dest) {
+            Some(ip) => ip,
+            None => {
+                println!("unknown node {}", dest);
+                continue;
+            }
+        };
+
+        // Reconnect once if the cached connection was closed by the other node
+        let mut sent = false;
+        for _ in 0..2 {
+            let stream = match connections.entry(ip) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => match connect_chat(ip, port, name) {
+                    Ok(stream) => entry.insert(stream),
+                    Err(err) => {
+                        println!("could not connect to {}: {}", dest, err);
+                        break;
+                    }
+                },
+            };
+
+            if writeln!(stream, "{}", message).is_ok() {
+                sent = true;
+                break;
+            }
+
+            connections.remove(&ip);
+        }
+
+        if !sent {
+            log::warn!("failed to send chat message to {}", dest);
+        }
+    }
+
+    Ok(())
+}
+
+fn connect_chat(ip: Ipv4Addr, port: u16, name: &str) -> io::Result<TcpStream> {
+    let mut stream = TcpStream::connect_timeout(&SocketAddr::from((ip, port)), CLIENT_TIMEOUT)?;
+    writeln!(stream, "HELLO {}", name)?;
+    Ok(stream)
+}
+
+fn resolve_node(state: &SharedState, dest: &str) -> Option<Ipv4Addr> {
+    if let Ok(ip) = dest.parse() {
+        return Some(ip);
+    }
+
+    state.get(|s| s.chain.iter().find(|i| i.name == dest).map(|i| i.ip))
+}
+
+fn is_timeout(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+    )
+}
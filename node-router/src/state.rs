@@ -1,20 +1,106 @@
-use std::sync::{
-    atomic::{AtomicBool, Ordering},
-    Arc,
+use std::{
+    collections::{BTreeMap, VecDeque},
+    net::Ipv4Addr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::Instant,
 };
 
+use pnet::util::MacAddr;
+
+use crate::ip::reassembly::Reassembler;
+
 #[derive(Clone)]
 pub struct SharedState {
     term: Arc<AtomicBool>,
+    state: Arc<Mutex<State>>,
+}
+
+/// A node participating in the chain, as known by the central router
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChainNode {
+    pub name: String,
+    pub ip: Ipv4Addr,
+    pub mac: Option<MacAddr>,
+    /// The group and central router interface of the node, nodes only forward
+    /// directly to others in the same group and subnet
+    pub group: Option<String>,
+    pub interface: Option<String>,
+    /// Nodes only forward directly to others in the same vlan
+    pub vlan: Option<u16>,
+}
+
+/// The maximum number of packets kept for display in the terminal ui
+const MAX_CAPTURED_PACKETS: usize = 1000;
+
+/// A packet captured for display in the terminal ui
+#[derive(Clone, Debug)]
+pub struct CapturedPacket {
+    pub id: u64,
+    pub time: Instant,
+    pub summary: String,
+    pub frame: Vec<u8>,
+    /// The 802.1Q tag the frame arrived with, the frame itself is untagged
+    pub vlan: Option<u16>,
+    /// The tamper rules which were applied and the resulting frame (none if dropped)
+    pub tampered: Option<(Vec<String>, Option<Vec<u8>>)>,
+}
+
+/// Packet counters for another host on the network
+#[derive(Clone, Debug, Default)]
+pub struct PeerCounters {
+    pub sent: u64,
+    pub received: u64,
+    pub passed_on: u64,
+    pub bytes: u64,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct State {
+    /// The nodes in the chain, in the order packets hop along them
+    pub chain: Vec<ChainNode>,
+    pub promisc: bool,
+    /// The vlan the frames received from other hosts are tagged with,
+    /// frames sent by this node are tagged the same way
+    pub vlan: Option<u16>,
+    pub dump: u16,
+    pub captured: VecDeque<CapturedPacket>,
+    pub peers: BTreeMap<Ipv4Addr, PeerCounters>,
+    /// Mac addresses learnt from arp packets seen on the interface
+    pub arp: BTreeMap<Ipv4Addr, MacAddr>,
+    /// Fragments of dumped packets waiting for the rest of their packet
+    pub reassembler: Reassembler,
+    next_capture_id: u64,
 }
 
 impl SharedState {
     pub fn new() -> Self {
         Self {
             term: Arc::new(AtomicBool::new(false)),
+            state: Arc::new(Mutex::new(State::default())),
         }
     }
 
+    pub fn update<F>(&self, f: F)
+    where
+        F: FnOnce(&mut State),
+    {
+        let mut state = self.state.lock().unwrap();
+
+        f(&mut state);
+    }
+
+    pub fn get<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&State) -> R,
+    {
+        let state = self.state.lock().unwrap();
+
+        f(&state)
+    }
+
     pub fn term_arc(&self) -> Arc<AtomicBool> {
         Arc::clone(&self.term)
     }
@@ -23,3 +109,22 @@ impl SharedState {
         !self.term.load(Ordering::Relaxed)
     }
 }
+
+impl Default for SharedState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl State {
+    pub fn capture(&mut self, mut packet: CapturedPacket) {
+        packet.id = self.next_capture_id;
+        self.next_capture_id += 1;
+
+        if self.captured.len() >= MAX_CAPTURED_PACKETS {
+            self.captured.pop_front();
+        }
+
+        self.captured.push_back(packet);
+    }
+}
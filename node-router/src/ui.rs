@@ -0,0 +1,310 @@
+use std::{
+    io::{self, Stdout, Write},
+    sync::atomic::Ordering,
+    time::{Duration, Instant},
+};
+
+use anyhow::Result;
+use crossterm::{
+    event::{self, Event, KeyCode, KeyEvent, KeyModifiers},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use pnet::packet::ethernet::EthernetPacket;
+use tui::{
+    backend::{Backend, CrosstermBackend},
+    layout::{Constraint, Direction, Layout},
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Block, Borders, Cell, List, ListItem, ListState, Paragraph, Row, Table},
+    Frame, Terminal,
+};
+
+use crate::{
+    args::Args,
+    ip::dumper::{describe_frame, describe_tampered, describe_vlan},
+    state::{CapturedPacket, SharedState, State},
+};
+
+const REDRAW_INTERVAL: Duration = Duration::from_millis(200);
+const MAX_DUMP_LEVEL: u16 = 4;
+
+/// The interactive state of the ui which is not shared with the packet forwarder
+struct View {
+    started: Instant,
+    /// The id of the selected packet, the latest packet is followed if none is selected
+    selected: Option<u64>,
+    detail_scroll: u16,
+}
+
+/// Shows a live view of the packets received by this node until the user quits
+pub fn start(args: Args, state: SharedState) -> Result<()> {
+    enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen)?;
+
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout))?;
+    let res = run(&args, &state, &mut terminal);
+
+    disable_raw_mode()?;
+    execute!(io::stdout(), LeaveAlternateScreen)?;
+    terminal.show_cursor()?;
+
+    // Closing the ui shuts down the node router
+    state.term_arc().store(true, Ordering::Relaxed);
+
+    res
+}
+
+fn run(
+    args: &Args,
+    state: &SharedState,
+    terminal: &mut Terminal<CrosstermBackend<Stdout>>,
+) -> Result<()> {
+    let mut view = View {
+        started: Instant::now(),
+        selected: None,
+        detail_scroll: 0,
+    };
+
+    // Always show at least the ip header of the selected packet
+    state.update(|s| s.dump = s.dump.max(1));
+
+    while state.running() {
+        state.get(|s| terminal.draw(|f| draw(f, args, s, &view)))?;
+
+        if !event::poll(REDRAW_INTERVAL)? {
+            continue;
+        }
+
+        if let Event::Key(key) = event::read()? {
+            if !handle_key(key, state, &mut view) {
+                break;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Handles a key press, returning false if the ui should be closed
+fn handle_key(key: KeyEvent, state: &SharedState, view: &mut View) -> bool {
+    match key.code {
+        KeyCode::Char('q') => return false,
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return false,
+        KeyCode::Char('p') => state.update(|s| s.promisc = !s.promisc),
+        KeyCode::Char('d') => state.update(|s| s.dump = s.dump % MAX_DUMP_LEVEL + 1),
+        KeyCode::Char('c') => state.update(|s| {
+            s.captured.clear();
+            s.peers.clear();
+        }),
+        KeyCode::Up | KeyCode::Down => {
+            view.selected =
+                state.get(|s| select_adjacent(s, view.selected, key.code == KeyCode::Up));
+            view.detail_scroll = 0;
+        }
+        KeyCode::End | KeyCode::Esc => {
+            view.selected = None;
+            view.detail_scroll = 0;
+        }
+        KeyCode::PageDown => view.detail_scroll = view.detail_scroll.saturating_add(10),
+        KeyCode::PageUp => view.detail_scroll = view.detail_scroll.saturating_sub(10),
+        _ => {}
+    }
+
+    true
+}
+
+fn select_adjacent(state: &State, selected: Option<u64>, up: bool) -> Option<u64> {
+    let len = state.captured.len();
+
+    if len == 0 {
+        return None;
+    }
+
+    let current = selected
+        .and_then(|id| state.captured.iter().position(|i| i.id == id))
+        .unwrap_or(len - 1);
+
+    let next = if up {
+        current.saturating_sub(1)
+    } else {
+        (current + 1).min(len - 1)
+    };
+
+    // Moving past the latest packet resumes following new packets
+    if !up && current == len - 1 {
+        return None;
+    }
+
+    Some(state.captured[next].id)
+}
+
+fn draw<B: Backend>(f: &mut Frame<B>, args: &Args, state: &State, view: &View) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(3),
+            Constraint::Percentage(50),
+            Constraint::Min(0),
+        ])
+        .split(f.size());
+
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(70), Constraint::Percentage(30)])
+        .split(rows[1]);
+
+    let selected = match view.selected {
+        Some(id) => state.captured.iter().position(|i| i.id == id),
+        None => state.captured.len().checked_sub(1),
+    };
+
+    draw_status(f, args, state, view, rows[0]);
+    draw_packets(f, state, view, selected, columns[0]);
+    draw_peers(f, state, columns[1]);
+    draw_detail(
+        f,
+        state,
+        view,
+        selected.map(|i| &state.captured[i]),
+        rows[2],
+    );
+}
+
+fn draw_status<B: Backend>(
+    f: &mut Frame<B>,
+    args: &Args,
+    state: &State,
+    view: &View,
+    area: tui::layout::Rect,
+) {
+    let on_off = |on: bool| if on { "on" } else { "off" };
+
+    let status = Spans::from(vec![
+        Span::styled(args.interface.clone(), Style::default().add_modifier(Modifier::BOLD)),
+        Span::raw(format!(
+            " | promisc: {} | dump level: {} | packets: {} | {} |",
+            on_off(state.promisc),
+            state.dump,
+            state.captured.len(),
+            if view.selected.is_some() { "paused" } else { "following" }
+        )),
+        Span::styled(
+            " q quit, p promisc, d dump level, up/down select, esc follow, pgup/pgdn scroll, c clear",
+            Style::default().fg(Color::DarkGray),
+        ),
+    ]);
+
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title("ChainNet node router");
+    f.render_widget(Paragraph::new(status).block(block), area);
+}
+
+fn draw_packets<B: Backend>(
+    f: &mut Frame<B>,
+    state: &State,
+    view: &View,
+    selected: Option<usize>,
+    area: tui::layout::Rect,
+) {
+    let items = state
+        .captured
+        .iter()
+        .map(|i| {
+            let elapsed = i.time.saturating_duration_since(view.started).as_secs_f64();
+
+            match &i.tampered {
+                Some((actions, _)) => ListItem::new(format!(
+                    "{:>9.3}  {} [tampered: {}]",
+                    elapsed,
+                    i.summary,
+                    actions.join(", ")
+                ))
+                .style(Style::default().fg(Color::Red)),
+                None => ListItem::new(format!("{:>9.3}  {}", elapsed, i.summary)),
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title("Packets"))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+
+    let mut list_state = ListState::default();
+    list_state.select(selected);
+
+    f.render_stateful_widget(list, area, &mut list_state);
+}
+
+fn draw_peers<B: Backend>(f: &mut Frame<B>, state: &State, area: tui::layout::Rect) {
+    let header = Row::new(vec!["Peer", "Sent", "Recv", "Passed", "Bytes"])
+        .style(Style::default().add_modifier(Modifier::BOLD));
+
+    let rows = state.peers.iter().map(|(ip, c)| {
+        Row::new(vec![
+            Cell::from(ip.to_string()),
+            Cell::from(c.sent.to_string()),
+            Cell::from(c.received.to_string()),
+            Cell::from(c.passed_on.to_string()),
+            Cell::from(c.bytes.to_string()),
+        ])
+    });
+
+    let widths = [
+        Constraint::Length(15),
+        Constraint::Length(6),
+        Constraint::Length(6),
+        Constraint::Length(6),
+        Constraint::Min(6),
+    ];
+
+    let table = Table::new(rows)
+        .header(header)
+        .block(Block::default().borders(Borders::ALL).title("Peers"))
+        .widths(&widths);
+
+    f.render_widget(table, area);
+}
+
+fn draw_detail<B: Backend>(
+    f: &mut Frame<B>,
+    state: &State,
+    view: &View,
+    packet: Option<&CapturedPacket>,
+    area: tui::layout::Rect,
+) {
+    let lines = match packet {
+        Some(packet) => describe_captured(state.dump, packet),
+        None => vec!["No packets captured".to_string()],
+    };
+
+    let text = lines.into_iter().map(Spans::from).collect::<Vec<_>>();
+
+    let detail = Paragraph::new(text)
+        .block(Block::default().borders(Borders::ALL).title("Detail"))
+        .scroll((view.detail_scroll, 0));
+
+    f.render_widget(detail, area);
+}
+
+fn describe_captured(dump: u16, packet: &CapturedPacket) -> Vec<String> {
+    let frame = match EthernetPacket::new(&packet.frame) {
+        Some(frame) => frame,
+        None => return vec!["invalid ethernet frame".to_string()],
+    };
+
+    let mut lines = describe_vlan(packet.vlan);
+
+    match &packet.tampered {
+        Some((actions, after)) => {
+            let after = after.as_ref().and_then(|i| EthernetPacket::new(i));
+            lines.push(format!("tampered: {}", actions.join(", ")));
+            lines.extend(describe_tampered(dump, &frame, after.as_ref()));
+        }
+        None => lines.extend(describe_frame(dump, &frame)),
+    }
+
+    lines
+}